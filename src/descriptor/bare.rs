@@ -158,6 +158,28 @@ impl<Pk: MiniscriptKey> DescriptorTrait<Pk> for Bare<Pk> {
         Ok((witness, script_sig))
     }
 
+    fn get_satisfaction_mall<S>(&self, satisfier: S) -> Result<(Vec<Vec<u8>>, Script), Error>
+    where
+        Pk: ToPublicKey,
+        S: Satisfier<Pk>,
+    {
+        let ms = self.ms.satisfy_malleable(satisfier)?;
+        let script_sig = witness_to_scriptsig(&ms);
+        let witness = vec![];
+        Ok((witness, script_sig))
+    }
+
+    fn get_dissatisfaction<S>(&self, satisfier: S) -> Result<(Vec<Vec<u8>>, Script), Error>
+    where
+        Pk: ToPublicKey,
+        S: Satisfier<Pk>,
+    {
+        let ms = self.ms.dissatisfy(satisfier)?;
+        let script_sig = witness_to_scriptsig(&ms);
+        let witness = vec![];
+        Ok((witness, script_sig))
+    }
+
     fn max_satisfaction_weight(&self) -> Result<usize, Error> {
         let scriptsig_len = self.ms.max_satisfaction_size()?;
         Ok(4 * (varint_len(scriptsig_len) + scriptsig_len))
@@ -324,7 +346,10 @@ impl<Pk: MiniscriptKey> DescriptorTrait<Pk> for Pkh<Pk> {
         Pk: ToPublicKey,
         S: Satisfier<Pk>,
     {
-        if let Some(sig) = satisfier.lookup_sig(&self.pk) {
+        let sig = satisfier
+            .try_lookup_sig(&self.pk)
+            .map_err(Error::Satisfier)?;
+        if let Some(sig) = sig {
             let mut sig_vec = sig.0.serialize_der().to_vec();
             sig_vec.push(sig.1.as_u32() as u8);
             let script_sig = script::Builder::new()
@@ -338,6 +363,28 @@ impl<Pk: MiniscriptKey> DescriptorTrait<Pk> for Pkh<Pk> {
         }
     }
 
+    fn get_satisfaction_mall<S>(&self, satisfier: S) -> Result<(Vec<Vec<u8>>, Script), Error>
+    where
+        Pk: ToPublicKey,
+        S: Satisfier<Pk>,
+    {
+        self.get_satisfaction(satisfier)
+    }
+
+    fn get_dissatisfaction<S>(&self, _: S) -> Result<(Vec<Vec<u8>>, Script), Error>
+    where
+        Pk: ToPublicKey,
+        S: Satisfier<Pk>,
+    {
+        // An empty signature fails `CHECKSIG` without aborting the script
+        let script_sig = script::Builder::new()
+            .push_slice(&[])
+            .push_key(&self.pk.to_public_key())
+            .into_script();
+        let witness = vec![];
+        Ok((witness, script_sig))
+    }
+
     fn max_satisfaction_weight(&self) -> Result<usize, Error> {
         Ok(4 * (1 + 73 + self.pk.serialized_len()))
     }
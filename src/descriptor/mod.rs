@@ -25,7 +25,7 @@
 
 use std::{collections::HashMap, sync::Arc};
 use std::{
-    fmt,
+    fmt, iter,
     str::{self, FromStr},
 };
 
@@ -35,10 +35,12 @@ use dogecoin::{self, Script};
 use self::checksum::verify_checksum;
 use expression;
 use miniscript;
+use miniscript::limits::SEQUENCE_LOCKTIME_TYPE_FLAG;
+use miniscript::satisfy::OlderUnit;
 use miniscript::{Legacy, Miniscript, Segwitv0};
 use {
-    BareCtx, Error, ForEach, ForEachKey, MiniscriptKey, Satisfier, ToPublicKey, TranslatePk,
-    TranslatePk2,
+    BareCtx, Error, ForEach, ForEachKey, MiniscriptKey, Satisfier, ScriptContext, ToPublicKey,
+    TranslatePk, TranslatePk2,
 };
 
 mod bare;
@@ -122,6 +124,31 @@ pub trait DescriptorTrait<Pk: MiniscriptKey> {
         Pk: ToPublicKey,
         S: Satisfier<Pk>;
 
+    /// Returns a possibly malleable satisfying witness and scriptSig to
+    /// spend an output controlled by the given descriptor. This may be
+    /// smaller than the one returned by `get_satisfaction`, but a third
+    /// party may be able to replace it with another valid satisfaction.
+    /// Defaults to the non-malleable satisfaction.
+    fn get_satisfaction_mall<S>(&self, satisfier: S) -> Result<(Vec<Vec<u8>>, Script), Error>
+    where
+        Pk: ToPublicKey,
+        S: Satisfier<Pk>,
+    {
+        self.get_satisfaction(satisfier)
+    }
+
+    /// Returns a witness and scriptSig which make the script of the given
+    /// descriptor evaluate to false, for testing that invalid spends are
+    /// rejected. Fails if the script cannot be dissatisfied, which is what
+    /// the default implementation reports.
+    fn get_dissatisfaction<S>(&self, _satisfier: S) -> Result<(Vec<Vec<u8>>, Script), Error>
+    where
+        Pk: ToPublicKey,
+        S: Satisfier<Pk>,
+    {
+        Err(Error::CouldNotDissatisfy)
+    }
+
     /// Attempts to produce a satisfying witness and scriptSig to spend an
     /// output controlled by the given descriptor; add the data to a given
     /// `TxIn` output.
@@ -298,6 +325,79 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
             },
         }
     }
+
+    /// Satisfy the descriptor into `txin`, setting its scriptSig and witness
+    /// as `DescriptorTrait::satisfy` does, and its `nSequence` to meet the
+    /// `older` fragments the chosen satisfaction relies on, i.e. the
+    /// smallest `older` value under which the satisfier produces the same
+    /// satisfaction. If the satisfier accepts any `after` value, a final
+    /// sequence is lowered to `0xfffffffe` so that the transaction's
+    /// `nLockTime` is enforced. Otherwise the sequence is left as given,
+    /// leaving the choice of e.g. RBF signalling to the caller.
+    pub fn satisfy_txin<S>(&self, satisfier: S, txin: &mut dogecoin::TxIn) -> Result<(), Error>
+    where
+        Pk: ToPublicKey,
+        S: Satisfier<Pk>,
+    {
+        fn timelocks<Pk: MiniscriptKey, Ctx: ScriptContext>(
+            ms: &Miniscript<Pk, Ctx>,
+            olders: &mut Vec<u32>,
+            afters: &mut Vec<u32>,
+        ) {
+            for node in ms.iter() {
+                match node.node {
+                    miniscript::decode::Terminal::Older(n) => olders.push(n),
+                    miniscript::decode::Terminal::After(n) => afters.push(n),
+                    _ => {}
+                }
+            }
+        }
+
+        let (mut olders, mut afters) = (vec![], vec![]);
+        match *self {
+            Descriptor::Bare(ref bare) => timelocks(bare.as_inner(), &mut olders, &mut afters),
+            Descriptor::Pkh(..) | Descriptor::Wpkh(..) => {}
+            Descriptor::Sh(ref sh) => match *sh.as_inner() {
+                ShInner::Wsh(ref wsh) => {
+                    if let WshInner::Ms(ref ms) = *wsh.as_inner() {
+                        timelocks(ms, &mut olders, &mut afters);
+                    }
+                }
+                ShInner::Ms(ref ms) => timelocks(ms, &mut olders, &mut afters),
+                ShInner::Wpkh(..) | ShInner::SortedMulti(..) => {}
+            },
+            Descriptor::Wsh(ref wsh) => {
+                if let WshInner::Ms(ref ms) = *wsh.as_inner() {
+                    timelocks(ms, &mut olders, &mut afters);
+                }
+            }
+        }
+        let satisfaction = self.get_satisfaction(&satisfier)?;
+
+        // Find the smallest sequence under which the same satisfaction is
+        // chosen, trying block-based values before time-based ones. A
+        // sequence of 0 meets no `older` at all.
+        olders.retain(|&n| satisfier.check_older(n));
+        olders.sort_by_key(|&n| (n & SEQUENCE_LOCKTIME_TYPE_FLAG, n & 0xffff));
+        olders.dedup();
+        let sequence = iter::once(0)
+            .chain(olders)
+            .find(|&candidate| {
+                let limited = OlderUnit::up_to(&satisfier, candidate);
+                self.get_satisfaction(limited).ok().as_ref() == Some(&satisfaction)
+            })
+            .ok_or(Error::CouldNotSatisfy)?;
+
+        let (witness, script_sig) = satisfaction;
+        txin.witness = witness;
+        txin.script_sig = script_sig;
+        if sequence != 0 {
+            txin.sequence = sequence;
+        } else if txin.sequence == 0xffffffff && afters.iter().any(|&n| satisfier.check_after(n)) {
+            txin.sequence = 0xfffffffe;
+        }
+        Ok(())
+    }
 }
 
 impl<P: MiniscriptKey, Q: MiniscriptKey> TranslatePk<P, Q> for Descriptor<P> {
@@ -438,6 +538,38 @@ impl<Pk: MiniscriptKey> DescriptorTrait<Pk> for Descriptor<Pk> {
         }
     }
 
+    /// Returns a possibly malleable satisfying witness and scriptSig to
+    /// spend an output controlled by the given descriptor
+    fn get_satisfaction_mall<S>(&self, satisfier: S) -> Result<(Vec<Vec<u8>>, Script), Error>
+    where
+        Pk: ToPublicKey,
+        S: Satisfier<Pk>,
+    {
+        match *self {
+            Descriptor::Bare(ref bare) => bare.get_satisfaction_mall(satisfier),
+            Descriptor::Pkh(ref pkh) => pkh.get_satisfaction_mall(satisfier),
+            Descriptor::Wpkh(ref wpkh) => wpkh.get_satisfaction_mall(satisfier),
+            Descriptor::Wsh(ref wsh) => wsh.get_satisfaction_mall(satisfier),
+            Descriptor::Sh(ref sh) => sh.get_satisfaction_mall(satisfier),
+        }
+    }
+
+    /// Returns a witness and scriptSig which make the script of the given
+    /// descriptor evaluate to false
+    fn get_dissatisfaction<S>(&self, satisfier: S) -> Result<(Vec<Vec<u8>>, Script), Error>
+    where
+        Pk: ToPublicKey,
+        S: Satisfier<Pk>,
+    {
+        match *self {
+            Descriptor::Bare(ref bare) => bare.get_dissatisfaction(satisfier),
+            Descriptor::Pkh(ref pkh) => pkh.get_dissatisfaction(satisfier),
+            Descriptor::Wpkh(ref wpkh) => wpkh.get_dissatisfaction(satisfier),
+            Descriptor::Wsh(ref wsh) => wsh.get_dissatisfaction(satisfier),
+            Descriptor::Sh(ref sh) => sh.get_dissatisfaction(satisfier),
+        }
+    }
+
     /// Computes an upper bound on the weight of a satisfying witness to the
     /// transaction. Assumes all signatures are 73 bytes, including push opcode
     /// and sighash suffix. Includes the weight of the VarInts encoding the
@@ -635,11 +767,13 @@ mod tests {
         DescriptorPublicKey, DescriptorSecretKey, DescriptorSinglePub, DescriptorXKey,
     };
     use hex_script;
+    use interpreter;
     use miniscript::satisfy::BitcoinSig;
     use std::cmp;
     use std::collections::HashMap;
     use std::str::FromStr;
-    use {Descriptor, DummyKey, Error, Miniscript, Satisfier, TranslatePk2};
+    use test_key;
+    use {Descriptor, DummyKey, Error, Miniscript, Satisfier, Segwitv0, TranslatePk2};
 
     #[cfg(feature = "compiler")]
     use policy;
@@ -1064,6 +1198,227 @@ mod tests {
         );
     }
 
+    #[test]
+    fn dissatisfy() {
+        let pk = PublicKey::from_str(
+            "020000000000000000000000000000000000000000000000000000000000000002",
+        )
+        .unwrap();
+
+        let pkh = Descriptor::new_pkh(pk);
+        assert_eq!(
+            pkh.get_dissatisfaction(()).unwrap(),
+            (
+                vec![],
+                script::Builder::new()
+                    .push_slice(&[])
+                    .push_key(&pk)
+                    .into_script()
+            )
+        );
+
+        let wpkh = Descriptor::new_wpkh(pk).unwrap();
+        assert_eq!(
+            wpkh.get_dissatisfaction(()).unwrap(),
+            (vec![vec![], pk.to_bytes()], dogecoin::Script::new())
+        );
+
+        let ms: Miniscript<PublicKey, Segwitv0> =
+            ms_str!("or_d(c:pk_k({}),c:pk_k({}))", pk, pk);
+        let shwsh = Descriptor::new_sh_wsh(ms.clone()).unwrap();
+        assert_eq!(
+            shwsh.get_dissatisfaction(()).unwrap(),
+            (
+                vec![vec![], vec![], ms.encode().into_bytes()],
+                shwsh.unsigned_script_sig()
+            )
+        );
+
+        let sh = StdDescriptor::from_str(&format!("sh(or_d(pk({}),pk({})))", pk, pk)).unwrap();
+        assert_eq!(
+            sh.get_dissatisfaction(()).unwrap(),
+            (
+                vec![],
+                script::Builder::new()
+                    .push_slice(&[])
+                    .push_slice(&[])
+                    .push_slice(&sh.explicit_script()[..])
+                    .into_script()
+            )
+        );
+
+        // A timelock cannot be dissatisfied
+        let wsh = StdDescriptor::from_str(&format!("wsh(and_v(v:pk({}),older(10)))", pk)).unwrap();
+        assert_eq!(
+            wsh.get_dissatisfaction(()).unwrap_err().to_string(),
+            "could not dissatisfy"
+        );
+    }
+
+    #[test]
+    fn satisfy_txin() {
+        use miniscript::satisfy::{After, Older};
+
+        let mut txin = dogecoin::TxIn {
+            previous_output: dogecoin::OutPoint::default(),
+            script_sig: dogecoin::Script::new(),
+            sequence: 0xffffffff,
+            witness: vec![],
+        };
+
+        let pk = "020000000000000000000000000000000000000000000000000000000000000002";
+        let desc =
+            StdDescriptor::from_str(&format!("wsh(or_d(pk({}),and_v(v:older(10),older(5))))", pk))
+                .unwrap();
+        desc.satisfy_txin(Older(15), &mut txin).unwrap();
+        assert_eq!(txin.sequence, 10);
+        assert_eq!(txin.witness, vec![vec![], desc.explicit_script().into_bytes()]);
+        assert!(txin.script_sig.is_empty());
+
+        // Only the relative locktimes of the chosen branch are set, so
+        // mixing time-based and block-based ones in the script is fine,
+        // and unused branches leave the sequence alone
+        struct AnyOlder(PublicKey, BitcoinSig);
+        impl Satisfier<PublicKey> for AnyOlder {
+            fn lookup_sig(&self, pk: &PublicKey) -> Option<BitcoinSig> {
+                if *pk == self.0 {
+                    Some(self.1)
+                } else {
+                    None
+                }
+            }
+
+            fn check_older(&self, _: u32) -> bool {
+                true
+            }
+        }
+        let other = "020000000000000000000000000000000000000000000000000000000000000003";
+        let sig = secp256k1::Signature::from_compact(&[1; 64]).unwrap();
+        let sigser = {
+            let mut ret = sig.serialize_der().to_vec();
+            ret.push(0x01);
+            ret
+        };
+        let satisfier = AnyOlder(pk.parse().unwrap(), (sig, dogecoin::SigHashType::All));
+
+        let desc = StdDescriptor::from_str(&format!(
+            "wsh(andor(pk({}),older(4194305),older(10)))",
+            pk
+        ))
+        .unwrap();
+        txin.sequence = 0xffffffff;
+        desc.satisfy_txin(&satisfier, &mut txin).unwrap();
+        assert_eq!(txin.sequence, 10);
+        assert_eq!(txin.witness, vec![vec![], desc.explicit_script().into_bytes()]);
+
+        let desc = StdDescriptor::from_str(&format!(
+            "wsh(or_d(pk({}),and_v(v:pk({}),older(10))))",
+            pk, other
+        ))
+        .unwrap();
+        txin.sequence = 0xffffffff;
+        desc.satisfy_txin(&satisfier, &mut txin).unwrap();
+        assert_eq!(txin.sequence, 0xffffffff);
+        assert_eq!(txin.witness, vec![sigser, desc.explicit_script().into_bytes()]);
+
+        // Absolute locktimes need a non-final sequence
+        let desc = StdDescriptor::from_str("sh(after(100))").unwrap();
+        txin.sequence = 0xffffffff;
+        desc.satisfy_txin(After(100), &mut txin).unwrap();
+        assert_eq!(txin.sequence, 0xfffffffe);
+        assert!(txin.witness.is_empty());
+
+        // ...but a caller's RBF-signalling sequence is kept
+        txin.sequence = 0xfffffffd;
+        desc.satisfy_txin(After(100), &mut txin).unwrap();
+        assert_eq!(txin.sequence, 0xfffffffd);
+    }
+
+    #[test]
+    fn satisfy_try_lookup_sig() {
+        use miniscript::satisfy::SatisfierError;
+
+        // A remote signer which only implements `try_lookup_sig`
+        struct Remote(Option<BitcoinSig>);
+        impl Satisfier<PublicKey> for Remote {
+            fn try_lookup_sig(&self, _: &PublicKey) -> Result<Option<BitcoinSig>, SatisfierError> {
+                self.0
+                    .map(Some)
+                    .ok_or(SatisfierError("signer unreachable".to_owned()))
+            }
+        }
+
+        let pk: PublicKey = "020000000000000000000000000000000000000000000000000000000000000002"
+            .parse()
+            .unwrap();
+        let sig = secp256k1::Signature::from_compact(&[1; 64]).unwrap();
+        let mut sigser = sig.serialize_der().to_vec();
+        sigser.push(0x01);
+        let signer = Remote(Some((sig, dogecoin::SigHashType::All)));
+
+        let pkh = Descriptor::new_pkh(pk);
+        let (_, script_sig) = pkh.get_satisfaction(&signer).unwrap();
+        let expected = script::Builder::new()
+            .push_slice(&sigser[..])
+            .push_key(&pk)
+            .into_script();
+        assert_eq!(script_sig, expected);
+        match pkh.get_satisfaction(Remote(None)) {
+            Err(Error::Satisfier(ref e)) if e.0 == "signer unreachable" => {}
+            x => panic!("unexpected result {:?}", x),
+        }
+
+        let wpkh = Descriptor::new_wpkh(pk).unwrap();
+        let (witness, _) = wpkh.get_satisfaction(&signer).unwrap();
+        assert_eq!(witness, vec![sigser, pk.to_bytes()]);
+        match wpkh.get_satisfaction(Remote(None)) {
+            Err(Error::Satisfier(ref e)) if e.0 == "signer unreachable" => {}
+            x => panic!("unexpected result {:?}", x),
+        }
+    }
+
+    #[test]
+    fn legacy_pk_satisfaction() {
+        let secp = secp256k1::Secp256k1::new();
+        let (sk, pk) = test_key();
+        let sig = secp.sign(&secp256k1::Message::from_slice(&[2; 32][..]).unwrap(), &sk);
+        let mut sigser = sig.serialize_der().to_vec();
+        sigser.push(0x01);
+        let mut sigs = HashMap::new();
+        sigs.insert(pk, (sig, dogecoin::SigHashType::All));
+
+        // p2pk puts the bare signature in the scriptSig
+        let desc = StdDescriptor::from_str(&format!("pk({})", pk)).unwrap();
+        let script_sig = script::Builder::new().push_slice(&sigser).into_script();
+        assert_eq!(
+            desc.get_satisfaction(&sigs).unwrap(),
+            (vec![], script_sig.clone())
+        );
+
+        // ...replacing any witness left on the input
+        let mut txin = dogecoin::TxIn {
+            previous_output: dogecoin::OutPoint::default(),
+            script_sig: dogecoin::Script::new(),
+            sequence: 0xffffffff,
+            witness: vec![vec![1, 2, 3]],
+        };
+        desc.satisfy(&mut txin, &sigs).unwrap();
+        assert_eq!(txin.script_sig, script_sig);
+        assert!(txin.witness.is_empty());
+
+        // The assembled spend is accepted by the interpreter
+        let mut interpreter = interpreter::Interpreter::from_txdata(
+            &desc.script_pubkey(),
+            &txin.script_sig,
+            &txin.witness,
+            0,
+            0,
+        )
+        .unwrap();
+        assert!(interpreter.is_legacy());
+        interpreter.verify_structure().unwrap();
+    }
+
     #[test]
     fn after_is_cltv() {
         let descriptor = Descriptor::<dogecoin::PublicKey>::from_str("wsh(after(1000))").unwrap();
@@ -1446,4 +1801,57 @@ pk(03f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8))";
         let descriptor: Descriptor<DescriptorPublicKey> = descriptor_str.parse().unwrap();
         assert_eq!(descriptor.to_string(), "sh(wsh(pk(xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL)))#6c6hwr22");
     }
+
+    #[test]
+    fn sortedmulti_satisfaction() {
+        let secp = secp256k1::Secp256k1::new();
+        let msg = secp256k1::Message::from_slice(&[2; 32][..]).unwrap();
+        let mut keys = vec![];
+        for i in 1..4 {
+            let sk = secp256k1::SecretKey::from_slice(&[i; 32][..]).unwrap();
+            let pk = dogecoin::PublicKey {
+                key: secp256k1::PublicKey::from_secret_key(&secp, &sk),
+                compressed: true,
+            };
+            let sig = secp.sign(&msg, &sk);
+            let mut sigser = sig.serialize_der().to_vec();
+            sigser.push(0x01);
+            keys.push((pk, sig, sigser));
+        }
+        keys.sort_by(|a, b| a.0.key.serialize().cmp(&b.0.key.serialize()));
+        let (a, b, c) = (&keys[0], &keys[1], &keys[2]);
+
+        let desc =
+            StdDescriptor::from_str(&format!("wsh(sortedmulti(2,{},{},{}))", c.0, a.0, b.0))
+                .unwrap();
+        let mut sigs = HashMap::new();
+        sigs.insert(c.0, (c.1, dogecoin::SigHashType::All));
+        sigs.insert(a.0, (a.1, dogecoin::SigHashType::All));
+
+        // Signatures follow the sorted keys of the script, not the descriptor
+        let (witness, script_sig) = desc.get_satisfaction(&sigs).unwrap();
+        assert_eq!(
+            witness,
+            vec![
+                vec![],
+                a.2.clone(),
+                c.2.clone(),
+                desc.explicit_script().into_bytes()
+            ]
+        );
+        assert_eq!(script_sig, dogecoin::Script::new());
+
+        let mut interpreter = interpreter::Interpreter::from_txdata(
+            &desc.script_pubkey(),
+            &script_sig,
+            &witness,
+            0,
+            0,
+        )
+        .unwrap();
+        let signed: Result<Vec<_>, _> = interpreter
+            .iter(|pk: &dogecoin::PublicKey, (sig, _)| secp.verify(&msg, &sig, &pk.key).is_ok())
+            .collect();
+        assert_eq!(signed.unwrap().len(), 2);
+    }
 }
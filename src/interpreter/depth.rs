@@ -0,0 +1,231 @@
+// Miniscript
+// Written in 2026 by rust-miniscript developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Stack depth of script execution
+//!
+//! Replays a script opcode by opcode to find the most elements it holds on
+//! its stacks, which the interpreter's own stack cannot tell: it only holds
+//! the results of fragments, not the keys, hashes and numbers the script
+//! pushes on the way, nor what is moved to the alternate stack.
+
+use std::cmp;
+
+use dogecoin::blockdata::opcodes;
+use dogecoin::blockdata::opcodes::all::*;
+use dogecoin::blockdata::script::{self, Instruction};
+use dogecoin::hashes::{hash160, ripemd160, sha256, sha256d, Hash};
+
+/// The most elements held on the main and alternate stacks together while
+/// executing `script` on `stack`, including the elements of `stack`.
+///
+/// Signature checks succeed exactly when their signatures are non-empty, as
+/// in any spend the interpreter accepts; timelocks are not checked.
+/// Execution stops at the first opcode which fails or which Miniscript does
+/// not use, and the depth reached until then is returned.
+pub fn max_depth(script: &script::Script, mut stack: Vec<Vec<u8>>) -> usize {
+    let mut alt = vec![];
+    // Whether each enclosing `OP_IF` branch is being executed
+    let mut exec = vec![];
+    let mut max = stack.len();
+    for instruction in script.instructions() {
+        let executing = exec.iter().all(|e| *e);
+        let res = match instruction {
+            Ok(Instruction::PushBytes(bytes)) => {
+                if executing {
+                    stack.push(bytes.to_vec());
+                }
+                Some(())
+            }
+            Ok(Instruction::Op(op)) => step(op, executing, &mut stack, &mut alt, &mut exec),
+            Err(_) => None,
+        };
+        if res.is_none() {
+            break;
+        }
+        max = cmp::max(max, stack.len() + alt.len());
+    }
+    max
+}
+
+/// Executes a single opcode, or returns `None` if it fails
+fn step(
+    op: opcodes::All,
+    executing: bool,
+    stack: &mut Vec<Vec<u8>>,
+    alt: &mut Vec<Vec<u8>>,
+    exec: &mut Vec<bool>,
+) -> Option<()> {
+    match op {
+        OP_IF | OP_NOTIF => {
+            let branch = if executing {
+                let cond = cast_to_bool(&stack.pop()?);
+                cond == (op == OP_IF)
+            } else {
+                false
+            };
+            exec.push(branch);
+            return Some(());
+        }
+        OP_ELSE => {
+            let branch = exec.last_mut()?;
+            *branch = !*branch;
+            return Some(());
+        }
+        OP_ENDIF => {
+            exec.pop()?;
+            return Some(());
+        }
+        _ if !executing => return Some(()),
+        _ => {}
+    }
+
+    let code = op.into_u8();
+    if code >= OP_PUSHNUM_1.into_u8() && code <= OP_PUSHNUM_16.into_u8() {
+        let n = code - OP_PUSHNUM_1.into_u8() + 1;
+        stack.push(scriptint(n as i64));
+        return Some(());
+    }
+    match op {
+        OP_DUP => {
+            let top = stack.last()?.clone();
+            stack.push(top);
+        }
+        OP_IFDUP => {
+            let top = stack.last()?.clone();
+            if cast_to_bool(&top) {
+                stack.push(top);
+            }
+        }
+        OP_SWAP => {
+            let len = stack.len();
+            if len < 2 {
+                return None;
+            }
+            stack.swap(len - 1, len - 2);
+        }
+        OP_TOALTSTACK => alt.push(stack.pop()?),
+        OP_FROMALTSTACK => stack.push(alt.pop()?),
+        OP_SIZE => {
+            let size = stack.last()?.len();
+            stack.push(scriptint(size as i64));
+        }
+        OP_VERIFY => verify(stack)?,
+        OP_EQUAL | OP_EQUALVERIFY => {
+            let (a, b) = (stack.pop()?, stack.pop()?);
+            push_bool(stack, a == b);
+            if op == OP_EQUALVERIFY {
+                verify(stack)?;
+            }
+        }
+        OP_0NOTEQUAL => {
+            let n = pop_num(stack)?;
+            push_bool(stack, n != 0);
+        }
+        OP_ADD => {
+            let (a, b) = (pop_num(stack)?, pop_num(stack)?);
+            stack.push(scriptint(a + b));
+        }
+        OP_BOOLAND | OP_BOOLOR => {
+            let (a, b) = (pop_num(stack)? != 0, pop_num(stack)? != 0);
+            push_bool(stack, if op == OP_BOOLAND { a && b } else { a || b });
+        }
+        OP_SHA256 => {
+            let hash = sha256::Hash::hash(&stack.pop()?);
+            stack.push(hash[..].to_vec());
+        }
+        OP_HASH256 => {
+            let hash = sha256d::Hash::hash(&stack.pop()?);
+            stack.push(hash[..].to_vec());
+        }
+        OP_RIPEMD160 => {
+            let hash = ripemd160::Hash::hash(&stack.pop()?);
+            stack.push(hash[..].to_vec());
+        }
+        OP_HASH160 => {
+            let hash = hash160::Hash::hash(&stack.pop()?);
+            stack.push(hash[..].to_vec());
+        }
+        OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+            stack.pop()?;
+            let sig = stack.pop()?;
+            push_bool(stack, !sig.is_empty());
+            if op == OP_CHECKSIGVERIFY {
+                verify(stack)?;
+            }
+        }
+        OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+            let n = pop_num(stack)?;
+            for _ in 0..n {
+                stack.pop()?;
+            }
+            let k = pop_num(stack)?;
+            let mut sat = true;
+            for _ in 0..k {
+                sat &= !stack.pop()?.is_empty();
+            }
+            // The extra element consumed by `OP_CHECKMULTISIG`
+            stack.pop()?;
+            push_bool(stack, sat);
+            if op == OP_CHECKMULTISIGVERIFY {
+                verify(stack)?;
+            }
+        }
+        OP_CLTV | OP_CSV => {
+            stack.last()?;
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Pops the top element, failing if it is false
+fn verify(stack: &mut Vec<Vec<u8>>) -> Option<()> {
+    if cast_to_bool(&stack.pop()?) {
+        Some(())
+    } else {
+        None
+    }
+}
+
+fn push_bool(stack: &mut Vec<Vec<u8>>, b: bool) {
+    stack.push(if b { vec![1] } else { vec![] });
+}
+
+/// Minimal encoding of a script number
+fn scriptint(n: i64) -> Vec<u8> {
+    let mut abs = n.abs() as u64;
+    let mut bytes = vec![];
+    while abs > 0 {
+        bytes.push(abs as u8);
+        abs >>= 8;
+    }
+    // The top bit of the last byte is the sign
+    match bytes.last().cloned() {
+        Some(last) if last & 0x80 != 0 => bytes.push(if n < 0 { 0x80 } else { 0 }),
+        Some(last) if n < 0 => *bytes.last_mut().unwrap() = last | 0x80,
+        _ => {}
+    }
+    bytes
+}
+
+fn pop_num(stack: &mut Vec<Vec<u8>>) -> Option<i64> {
+    script::read_scriptint(&stack.pop()?).ok()
+}
+
+/// Whether an element is true, i.e. is not a zero or negative zero
+fn cast_to_bool(elem: &[u8]) -> bool {
+    match elem.split_last() {
+        Some((last, rest)) => rest.iter().any(|b| *b != 0) || (*last != 0 && *last != 0x80),
+        None => false,
+    }
+}
@@ -12,7 +12,8 @@
 // If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
 //
 
-use dogecoin::hashes::{hash160, hex::ToHex};
+use dogecoin::hashes::hex::{self, ToHex};
+use dogecoin::hashes::hash160;
 use dogecoin::{self, secp256k1};
 use std::{error, fmt};
 
@@ -21,12 +22,22 @@ use std::{error, fmt};
 pub enum Error {
     /// Could not satisfy, absolute locktime not met
     AbsoluteLocktimeNotMet(u32),
+    /// The script contains an `OP_CODESEPARATOR`, which would change the
+    /// script code committed to by signatures, and is not supported
+    CodeSeparator,
+    /// The input is a coinbase input, which spends no coin so has no
+    /// script to interpret
+    CoinbaseInput,
     /// General Interpreter error.
     CouldNotEvaluate,
     /// We expected a push (including a `OP_1` but no other numeric pushes)
     ExpectedPush,
     /// The preimage to the hash function must be exactly 32 bytes.
     HashPreimageLengthMismatch,
+    /// Transaction data given as hex could not be decoded
+    HexParse(hex::Error),
+    /// A revealed hash preimage did not satisfy every hashlock in the script
+    InconsistentPreimage(Vec<u8>),
     /// Incorrect scriptPubKey (pay-to-pubkeyhash) for the provided public key
     IncorrectPubkeyHash,
     /// Incorrect scriptPubKey for the provided redeem script
@@ -35,14 +46,25 @@ pub enum Error {
     IncorrectWPubkeyHash,
     /// Incorrect scriptPubKey for the provided witness script
     IncorrectWScriptHash,
+    /// The transaction has no input at the given index
+    InputIndexOutOfRange(usize),
     /// MultiSig missing at least `1` witness elements out of `k + 1` required
     InsufficientSignaturesMultiSig,
+    /// A timelock operand is not a valid locktime under BIP65 or BIP112,
+    /// e.g. zero, or a relative locktime setting the disable or reserved bits
+    InvalidLocktimeValue(u32),
+    /// An element revealed as a public key for a pubkeyhash check was neither
+    /// 33 nor 65 bytes long
+    InvalidPubkeyLength(usize),
     /// Signature failed to verify
     InvalidSignature(dogecoin::PublicKey),
     /// Last byte of this signature isn't a standard sighash type
     NonStandardSigHash(Vec<u8>),
     /// Miniscript error
     Miniscript(::Error),
+    /// The output spent by an input was neither created by the package
+    /// being verified nor given as a prevout
+    MissingPrevout(dogecoin::OutPoint),
     /// MultiSig requires 1 extra zero element apart from the `k` signatures
     MissingExtraZeroMultiSig,
     /// Script abortion because of incorrect dissatisfaction for multisig.
@@ -54,6 +76,9 @@ pub enum Error {
     NonEmptyWitness,
     ///ScriptSig must be empty for pure segwit transactions
     NonEmptyScriptSig,
+    /// The script has more than the 201 non-push opcodes allowed by
+    /// consensus, counting the keys of `CHECKMULTISIG`s
+    OpCountExceeded(usize),
     /// Script abortion because of incorrect dissatisfaction for Checksig.
     /// Any input witness apart from sat(sig) or nsat(0) leads to
     /// this error. This is network standardness assumption and miniscript only
@@ -65,12 +90,19 @@ pub enum Error {
     /// Parse Error while parsing a `stack::Element::Push` as a Pubkey. Both
     /// 33 byte and 65 bytes are supported.
     PubkeyParseError,
+    /// A stack element exceeded the 520-byte consensus limit on pushes
+    PushSizeExceeded(usize),
     /// Could not satisfy, relative locktime not met
     RelativeLocktimeNotMet(u32),
     /// Forward-secp related errors
     Secp(secp256k1::Error),
     /// Miniscript requires the entire top level script to be satisfied.
     ScriptSatisfactionError,
+    /// The script exceeded the size limit for its spend type
+    ScriptSizeExceeded(usize),
+    /// The redeem script has more than the 15 signature operations allowed
+    /// by standardness for P2SH
+    SigOpsExceeded(usize),
     /// An uncompressed public key was encountered in a context where it is
     /// disallowed (e.g. in a Segwit script or p2wpkh output)
     UncompressedPubkey,
@@ -83,9 +115,17 @@ pub enum Error {
     /// was expecting a stack boolean `stack::Element::Satisfied` or
     /// `stack::Element::Dissatisfied`
     UnexpectedStackElementPush,
+    /// Segwit v0 witness had more items than its satisfaction could consume
+    UnexpectedWitnessItems,
+    /// A witness program of a version other than 0, or a v0 program which
+    /// is neither P2WPKH nor P2WSH, which this interpreter cannot evaluate
+    UnsupportedWitnessVersion(u8),
     /// Verify expects stack top element exactly to be `stack::Element::Satisfied`.
     /// This error is raised even if the stack top is `stack::Element::Push`.
     VerifyFailed,
+    /// The witness had more than the 100 stack items allowed by
+    /// standardness for P2WSH
+    WitnessStackItemsExceeded(usize),
 }
 
 #[doc(hidden)]
@@ -95,6 +135,13 @@ impl From<secp256k1::Error> for Error {
     }
 }
 
+#[doc(hidden)]
+impl From<hex::Error> for Error {
+    fn from(e: hex::Error) -> Error {
+        Error::HexParse(e)
+    }
+}
+
 #[doc(hidden)]
 impl From<::Error> for Error {
     fn from(e: ::Error) -> Error {
@@ -120,15 +167,28 @@ impl fmt::Display for Error {
                 n
             ),
             Error::ExpectedPush => f.write_str("expected push in script"),
+            Error::CodeSeparator => f.write_str("OP_CODESEPARATOR is not supported"),
+            Error::CoinbaseInput => f.write_str("coinbase input spends no coin"),
             Error::CouldNotEvaluate => f.write_str("Interpreter Error: Could not evaluate"),
             Error::HashPreimageLengthMismatch => f.write_str("Hash preimage should be 32 bytes"),
+            Error::HexParse(ref e) => write!(f, "hex parse error: {}", e),
+            Error::InconsistentPreimage(ref preimage) => write!(
+                f,
+                "preimage {} does not satisfy every hashlock",
+                preimage.to_hex()
+            ),
             Error::IncorrectPubkeyHash => f.write_str("public key did not match scriptpubkey"),
             Error::IncorrectScriptHash => f.write_str("redeem script did not match scriptpubkey"),
             Error::IncorrectWPubkeyHash => {
                 f.write_str("public key did not match scriptpubkey (segwit v0)")
             }
             Error::IncorrectWScriptHash => f.write_str("witness script did not match scriptpubkey"),
+            Error::InputIndexOutOfRange(idx) => write!(f, "transaction has no input {}", idx),
             Error::InsufficientSignaturesMultiSig => f.write_str("Insufficient signatures for CMS"),
+            Error::InvalidLocktimeValue(n) => write!(f, "invalid timelock operand {:#x}", n),
+            Error::InvalidPubkeyLength(len) => {
+                write!(f, "revealed pubkey has length {}, expected 33 or 65", len)
+            }
             Error::InvalidSignature(pk) => write!(f, "bad signature with pk {}", pk),
             Error::NonStandardSigHash(ref sig) => {
                 write!(
@@ -140,18 +200,31 @@ impl fmt::Display for Error {
             Error::NonEmptyWitness => f.write_str("legacy spend had nonempty witness"),
             Error::NonEmptyScriptSig => f.write_str("segwit spend had nonempty scriptsig"),
             Error::Miniscript(ref e) => write!(f, "parse error: {}", e),
+            Error::MissingPrevout(ref outpoint) => write!(f, "unknown prevout {}", outpoint),
             Error::MissingExtraZeroMultiSig => f.write_str("CMS missing extra zero"),
             Error::MultiSigEvaluationError => {
                 f.write_str("CMS script aborted, incorrect satisfaction/dissatisfaction")
             }
+            Error::OpCountExceeded(n) => {
+                write!(f, "script has {} opcodes, exceeding the 201 opcode limit", n)
+            }
             Error::PkEvaluationError(ref key) => write!(f, "Incorrect Signature for pk {}", key),
             Error::PkHashVerifyFail(ref hash) => write!(f, "Pubkey Hash check failed {}", hash),
             Error::PubkeyParseError => f.write_str("could not parse pubkey"),
+            Error::PushSizeExceeded(len) => {
+                write!(f, "stack element of {} bytes exceeds the 520 byte limit", len)
+            }
             Error::RelativeLocktimeNotMet(n) => {
                 write!(f, "required relative locktime CSV of {} blocks, not met", n)
             }
             Error::ScriptSatisfactionError => f.write_str("Top level script must be satisfied"),
+            Error::ScriptSizeExceeded(len) => {
+                write!(f, "script of {} bytes exceeds the size limit", len)
+            }
             Error::Secp(ref e) => fmt::Display::fmt(e, f),
+            Error::SigOpsExceeded(n) => {
+                write!(f, "redeem script has {} sigops, exceeding the 15 sigop limit", n)
+            }
             Error::UncompressedPubkey => {
                 f.write_str("uncompressed pubkey in non-legacy descriptor")
             }
@@ -160,9 +233,18 @@ impl fmt::Display for Error {
             }
             Error::UnexpectedStackElementPush => write!(f, "Got {}, expected Stack Boolean", 1),
             Error::UnexpectedStackEnd => f.write_str("unexpected end of stack"),
+            Error::UnexpectedWitnessItems => {
+                f.write_str("witness had items not consumed by the satisfaction")
+            }
+            Error::UnsupportedWitnessVersion(v) => {
+                write!(f, "unsupported witness program of version {}", v)
+            }
             Error::VerifyFailed => {
                 f.write_str("Expected Satisfied Boolean at stack top for VERIFY")
             }
+            Error::WitnessStackItemsExceeded(n) => {
+                write!(f, "witness has {} stack items, exceeding the 100 item limit", n)
+            }
         }
     }
 }
@@ -13,6 +13,7 @@
 //
 
 use dogecoin;
+use dogecoin::blockdata::opcodes;
 use dogecoin::hashes::{hash160, sha256, Hash};
 
 use super::{stack, Error, Stack};
@@ -45,12 +46,31 @@ fn pk_from_stackelem<'a>(
     pk_from_slice(slice, require_compressed)
 }
 
+/// Rejects scripts containing an `OP_CODESEPARATOR`. Signatures commit to
+/// the script following the last one executed, but the interpreter always
+/// computes sighashes over the whole script, so would verify them wrongly.
+fn check_no_codeseparator(script: &dogecoin::Script) -> Result<(), Error> {
+    let is_codesep = |ins| match ins {
+        Ok(dogecoin::blockdata::script::Instruction::Op(op)) => {
+            op == opcodes::all::OP_CODESEPARATOR
+        }
+        _ => false,
+    };
+    if script.instructions().any(is_codesep) {
+        Err(Error::CodeSeparator)
+    } else {
+        Ok(())
+    }
+}
+
 fn script_from_stackelem<'a>(
     elem: &stack::Element<'a>,
 ) -> Result<Miniscript<dogecoin::PublicKey, NoChecks>, Error> {
     match *elem {
         stack::Element::Push(sl) => {
-            Miniscript::parse_insane(&dogecoin::Script::from(sl.to_owned())).map_err(Error::from)
+            let script = dogecoin::Script::from(sl.to_owned());
+            check_no_codeseparator(&script)?;
+            Miniscript::parse_insane(&script).map_err(Error::from)
         }
         stack::Element::Satisfied => Miniscript::from_ast(::Terminal::True).map_err(Error::from),
         stack::Element::Dissatisfied => {
@@ -59,6 +79,47 @@ fn script_from_stackelem<'a>(
     }
 }
 
+/// Parses the Miniscript pushed as a witness or redeem script, together with
+/// the script its hash should commit to. If `nonminimal_timelocks` is given,
+/// non-minimal timelock operands are accepted and recorded there, and the
+/// script is then the pushed one rather than the (differing) re-encoding.
+fn script_and_ms_from_stackelem<'a>(
+    elem: &stack::Element<'a>,
+    nonminimal_timelocks: Option<&mut Vec<u32>>,
+) -> Result<(Miniscript<dogecoin::PublicKey, NoChecks>, dogecoin::Script), Error> {
+    match (elem, nonminimal_timelocks) {
+        (&stack::Element::Push(sl), Some(nonminimal_timelocks)) => {
+            let script = dogecoin::Script::from(sl.to_owned());
+            check_no_codeseparator(&script)?;
+            let (ms, nonminimal) = Miniscript::parse_insane_allowing_nonminimal_timelocks(&script)?;
+            let script = if nonminimal.is_empty() {
+                ms.encode()
+            } else {
+                script
+            };
+            nonminimal_timelocks.extend(nonminimal);
+            Ok((ms, script))
+        }
+        (_, _) => {
+            let ms = script_from_stackelem(elem)?;
+            let script = ms.encode();
+            Ok((ms, script))
+        }
+    }
+}
+
+/// Checks that a segwit v0 witness has no more items left, once the public key
+/// has been removed, than its signature check consumes. Any extra items would
+/// be left on the stack and fail the cleanstack rule. Witness scripts need no
+/// such check, as the interpreter rejects leftover stack once they have run.
+fn check_witness_items(max_items: usize, wit_stack: &Stack) -> Result<(), Error> {
+    if wit_stack.as_slice().len() > max_items {
+        Err(Error::UnexpectedWitnessItems)
+    } else {
+        Ok(())
+    }
+}
+
 /// Helper type to indicate the origin of the bare pubkey that the interpereter uses
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum PubkeyType {
@@ -87,6 +148,15 @@ pub enum Inner {
     Script(Miniscript<dogecoin::PublicKey, NoChecks>, ScriptType),
 }
 
+/// The version of a witness program, given its first byte
+fn witness_version(program: &[u8]) -> u8 {
+    if program[0] == 0 {
+        0
+    } else {
+        program[0] - opcodes::all::OP_PUSHNUM_1.into_u8() + 1
+    }
+}
+
 // The `Script` returned by this method is always generated/cloned ... when
 // rust-bitcoin is updated to use a copy-on-write internal representation we
 // should revisit this and return references to the actual txdata wherever
@@ -98,6 +168,31 @@ pub fn from_txdata<'txin>(
     script_sig: &'txin dogecoin::Script,
     witness: &'txin [Vec<u8>],
 ) -> Result<(Inner, Stack<'txin>, dogecoin::Script), Error> {
+    from_txdata_inner(spk, script_sig, witness, None)
+}
+
+/// Parses as `from_txdata` does, but accepts non-minimally encoded timelock
+/// operands in the Miniscript, additionally returning their values
+pub fn from_txdata_allowing_nonminimal_timelocks<'txin>(
+    spk: &dogecoin::Script,
+    script_sig: &'txin dogecoin::Script,
+    witness: &'txin [Vec<u8>],
+) -> Result<(Inner, Stack<'txin>, dogecoin::Script, Vec<u32>), Error> {
+    let mut nonminimal = vec![];
+    let (inner, stack, script_code) =
+        from_txdata_inner(spk, script_sig, witness, Some(&mut nonminimal))?;
+    Ok((inner, stack, script_code, nonminimal))
+}
+
+fn from_txdata_inner<'txin>(
+    spk: &dogecoin::Script,
+    script_sig: &'txin dogecoin::Script,
+    witness: &'txin [Vec<u8>],
+    nonminimal_timelocks: Option<&mut Vec<u32>>,
+) -> Result<(Inner, Stack<'txin>, dogecoin::Script), Error> {
+    // Both stacks go through `Element::from`, so an empty push becomes
+    // `Dissatisfied` whether it is an `OP_0` in the scriptSig or an empty
+    // witness element, and is treated as false either way
     let mut ssig_stack: Stack = script_sig
         .instructions_minimal()
         .map(stack::Element::from_instruction)
@@ -130,6 +225,11 @@ pub fn from_txdata<'txin>(
         } else {
             match ssig_stack.pop() {
                 Some(elem) => {
+                    if let stack::Element::Push(slice) = elem {
+                        if slice.len() != 33 && slice.len() != 65 {
+                            return Err(Error::InvalidPubkeyLength(slice.len()));
+                        }
+                    }
                     let pk = pk_from_stackelem(&elem, false)?;
                     if *spk == dogecoin::Script::new_p2pkh(&pk.to_pubkeyhash().into()) {
                         Ok((
@@ -153,6 +253,7 @@ pub fn from_txdata<'txin>(
                 Some(elem) => {
                     let pk = pk_from_stackelem(&elem, true)?;
                     if *spk == dogecoin::Script::new_v0_wpkh(&pk.to_pubkeyhash().into()) {
+                        check_witness_items(1, &wit_stack)?;
                         Ok((
                             Inner::PublicKey(pk, PubkeyType::Wpkh),
                             wit_stack,
@@ -172,8 +273,8 @@ pub fn from_txdata<'txin>(
         } else {
             match wit_stack.pop() {
                 Some(elem) => {
-                    let miniscript = script_from_stackelem(&elem)?;
-                    let script = miniscript.encode();
+                    let (miniscript, script) =
+                        script_and_ms_from_stackelem(&elem, nonminimal_timelocks)?;
                     let scripthash = sha256::Hash::hash(&script[..]);
                     if *spk == dogecoin::Script::new_v0_wsh(&scripthash.into()) {
                         Ok((
@@ -209,6 +310,7 @@ pub fn from_txdata<'txin>(
                                         == &dogecoin::Script::new_v0_wpkh(&pk.to_pubkeyhash().into())
                                             [..]
                                     {
+                                        check_witness_items(1, &wit_stack)?;
                                         Ok((
                                             Inner::PublicKey(pk, PubkeyType::ShWpkh),
                                             wit_stack,
@@ -228,8 +330,10 @@ pub fn from_txdata<'txin>(
                                 if !ssig_stack.is_empty() {
                                     Err(Error::NonEmptyScriptSig)
                                 } else {
-                                    let miniscript = script_from_stackelem(&elem)?;
-                                    let script = miniscript.encode();
+                                    let (miniscript, script) = script_and_ms_from_stackelem(
+                                        &elem,
+                                        nonminimal_timelocks,
+                                    )?;
                                     let scripthash = sha256::Hash::hash(&script[..]);
                                     if slice == &dogecoin::Script::new_v0_wsh(&scripthash.into())[..]
                                     {
@@ -245,11 +349,16 @@ pub fn from_txdata<'txin>(
                             }
                             None => Err(Error::UnexpectedStackEnd),
                         };
+                    // ** p2sh-wrapped future witness program **
+                    } else if dogecoin::Script::from(slice.to_vec()).is_witness_program() {
+                        // Only reached once the redeem script matched the
+                        // script hash above
+                        return Err(Error::UnsupportedWitnessVersion(witness_version(slice)));
                     }
                 }
                 // normal p2sh
-                let miniscript = script_from_stackelem(&elem)?;
-                let script = miniscript.encode();
+                let (miniscript, script) =
+                    script_and_ms_from_stackelem(&elem, nonminimal_timelocks)?;
                 if wit_stack.is_empty() {
                     let scripthash = hash160::Hash::hash(&script[..]);
                     if *spk == dogecoin::Script::new_p2sh(&scripthash.into()) {
@@ -267,10 +376,22 @@ pub fn from_txdata<'txin>(
             }
             None => Err(Error::UnexpectedStackEnd),
         }
+    // ** future witness program **
+    } else if spk.is_witness_program() {
+        Err(Error::UnsupportedWitnessVersion(witness_version(spk.as_bytes())))
     // ** bare script **
     } else {
         if wit_stack.is_empty() {
-            let miniscript = Miniscript::parse_insane(spk)?;
+            check_no_codeseparator(spk)?;
+            let miniscript = match nonminimal_timelocks {
+                Some(nonminimal_timelocks) => {
+                    let (ms, nonminimal) =
+                        Miniscript::parse_insane_allowing_nonminimal_timelocks(spk)?;
+                    nonminimal_timelocks.extend(nonminimal);
+                    ms
+                }
+                None => Miniscript::parse_insane(spk)?,
+            };
             Ok((
                 Inner::Script(miniscript, ScriptType::Bare),
                 ssig_stack,
@@ -436,6 +557,13 @@ mod tests {
         let err = from_txdata(&comp.pkh_spk, &uncomp.pkh_sig_justkey, &[]).unwrap_err();
         assert_eq!(err.to_string(), "public key did not match scriptpubkey");
 
+        // pkh, pushed element is not a pubkey at all
+        let truncated = script::Builder::new()
+            .push_slice(&fixed.pk_comp.to_bytes()[..32])
+            .into_script();
+        let err = from_txdata(&comp.pkh_spk, &truncated, &[]).unwrap_err();
+        assert_eq!(err.to_string(), "revealed pubkey has length 32, expected 33 or 65");
+
         // pkh, right pubkey, no signature
         let (inner, stack, script_code) =
             from_txdata(&comp.pkh_spk, &comp.pkh_sig_justkey, &[]).expect("parse txdata");
@@ -512,6 +640,15 @@ mod tests {
         // Scriptsig is nonempty
         let err = from_txdata(&comp.wpkh_spk, &comp.pk_sig, &comp.wpkh_stack_justkey).unwrap_err();
         assert_eq!(err.to_string(), "segwit spend had nonempty scriptsig");
+
+        // Extra witness item before the signature
+        let mut extra_stack = comp.wpkh_stack.clone();
+        extra_stack.insert(0, vec![]);
+        let err = from_txdata(&comp.wpkh_spk, &blank_script, &extra_stack).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "witness had items not consumed by the satisfaction"
+        );
     }
 
     #[test]
@@ -675,6 +812,15 @@ mod tests {
             .into_script();
         let err = from_txdata(&spk, &script_sig, &wit_stack).unwrap_err();
         assert_eq!(&err.to_string(), "segwit spend had nonempty scriptsig");
+
+        // more witness items than the satisfaction uses are left on the
+        // stack, for the interpreter to reject once the script has run
+        let extra_stack = vec![vec![], preimage.to_vec(), witness_script.to_bytes()];
+        let (_, stack, _) = from_txdata(&spk, &blank_script, &extra_stack).expect("parse txdata");
+        assert_eq!(
+            stack,
+            Stack::from(vec![stack::Element::Dissatisfied, stack::Element::Push(preimage)])
+        );
     }
 
     #[test]
@@ -720,4 +866,71 @@ mod tests {
         assert_eq!(stack, Stack::from(vec![]));
         assert_eq!(script_code, witness_script);
     }
+
+    #[test]
+    fn code_separator() {
+        let fixed = fixed_test_data();
+        let redeem_script = script::Builder::new()
+            .push_opcode(opcodes::all::OP_CODESEPARATOR)
+            .push_key(&fixed.pk_comp)
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script();
+        let rs_hash = hash160::Hash::hash(&redeem_script[..]).into();
+        let blank_script = dogecoin::Script::new();
+
+        // bare
+        let err = from_txdata(&redeem_script, &blank_script, &[]).unwrap_err();
+        assert_eq!(&err.to_string(), "OP_CODESEPARATOR is not supported");
+
+        // sh
+        let spk = Script::new_p2sh(&rs_hash);
+        let script_sig = script::Builder::new()
+            .push_slice(&redeem_script[..])
+            .into_script();
+        let err = from_txdata(&spk, &script_sig, &[]).unwrap_err();
+        assert_eq!(&err.to_string(), "OP_CODESEPARATOR is not supported");
+
+        // wsh
+        let wit_hash = sha256::Hash::hash(&redeem_script[..]).into();
+        let spk = Script::new_v0_wsh(&wit_hash);
+        let wit_stack = vec![redeem_script.to_bytes()];
+        let err = from_txdata(&spk, &blank_script, &wit_stack).unwrap_err();
+        assert_eq!(&err.to_string(), "OP_CODESEPARATOR is not supported");
+    }
+
+    #[test]
+    fn future_witness_version() {
+        let blank_script = dogecoin::Script::new();
+        let v1_program = script::Builder::new()
+            .push_opcode(opcodes::all::OP_PUSHNUM_1)
+            .push_slice(&[0x42; 32])
+            .into_script();
+
+        let err = from_txdata(&v1_program, &blank_script, &[vec![0x42; 64]]).unwrap_err();
+        assert_eq!(&err.to_string(), "unsupported witness program of version 1");
+
+        // A v0 program of the wrong length is no better
+        let v0_program = script::Builder::new()
+            .push_int(0)
+            .push_slice(&[0x42; 24])
+            .into_script();
+        let err = from_txdata(&v0_program, &blank_script, &[]).unwrap_err();
+        assert_eq!(&err.to_string(), "unsupported witness program of version 0");
+
+        // p2sh-wrapped
+        let script_sig = script::Builder::new()
+            .push_slice(&v1_program[..])
+            .into_script();
+        let rs_hash = hash160::Hash::hash(&v1_program[..]).into();
+        let spk = Script::new_p2sh(&rs_hash);
+        let err = from_txdata(&spk, &script_sig, &[vec![0x42; 64]]).unwrap_err();
+        assert_eq!(&err.to_string(), "unsupported witness program of version 1");
+
+        // The script hash is checked before the witness version
+        let spk = Script::new_p2sh(&hash160::Hash::hash(&[0x42; 34]).into());
+        match from_txdata(&spk, &script_sig, &[vec![0x42; 64]]) {
+            Err(Error::IncorrectScriptHash) => {}
+            x => panic!("expected incorrect script hash, got {:?}", x),
+        }
+    }
 }
@@ -19,29 +19,56 @@
 //! assuming that the spent coin was descriptor controlled.
 //!
 
-use dogecoin::hashes::{hash160, ripemd160, sha256, sha256d};
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+
+use dogecoin::blockdata::opcodes;
+use dogecoin::hashes::hex::FromHex;
+use dogecoin::hashes::{hash160, ripemd160, sha256, sha256d, Hash};
 use dogecoin::util::bip143;
 use dogecoin::{self, secp256k1};
 use miniscript::context::NoChecks;
+use miniscript::iter::PkPkh;
+use miniscript::limits::{
+    MAX_OPS_PER_SCRIPT, MAX_P2SH_SIGOPS, MAX_SCRIPT_ELEMENT_SIZE, MAX_SCRIPT_SIZE,
+    MAX_STANDARD_P2WSH_SCRIPT_SIZE, MAX_STANDARD_P2WSH_STACK_ITEMS, SEQUENCE_LOCKTIME_TYPE_FLAG,
+};
+use miniscript::satisfy::{aggregate_relative_locktimes, FragmentId};
+use miniscript::types;
 use miniscript::ScriptContext;
 use Miniscript;
+use util::varint_len;
 use Terminal;
 use {BitcoinSig, Descriptor, ToPublicKey};
 
+mod depth;
 mod error;
 mod inner;
+mod raw;
 mod stack;
 
 pub use self::error::Error;
+pub use self::raw::{interpret_script, PartialConstraint};
+pub use self::stack::{Element, PublicStack, WitnessStackElement};
 use self::stack::Stack;
 
 /// An iterable Miniscript-structured representation of the spending of a coin
+#[derive(Clone)]
 pub struct Interpreter<'txin> {
     inner: inner::Inner,
     stack: Stack<'txin>,
+    /// The stack the script starts executing on, as `stack` is consumed by
+    /// iteration
+    initial_stack: Vec<Vec<u8>>,
     script_code: dogecoin::Script,
+    script_sig: &'txin dogecoin::Script,
+    witness: &'txin [Vec<u8>],
     age: u32,
     height: u32,
+    median_time_past: Option<u32>,
+    allow_nonstandard_cms_dummy: bool,
+    nonminimal_timelocks: Vec<u32>,
 }
 
 impl<'txin> Interpreter<'txin> {
@@ -59,32 +86,143 @@ impl<'txin> Interpreter<'txin> {
         height: u32,
     ) -> Result<Self, Error> {
         let (inner, stack, script_code) = inner::from_txdata(spk, script_sig, witness)?;
+        Self::from_parts(inner, stack, script_code, script_sig, witness, age, height, vec![])
+    }
+
+    /// Constructs an interpreter for input `input_idx` of `tx`, spending a
+    /// coin with scriptPubKey `spk`. Coinbase inputs spend no coin, so are
+    /// rejected with `Error::CoinbaseInput`, letting tools which walk every
+    /// input of a block skip them. Fails with `Error::InputIndexOutOfRange`
+    /// if `tx` has no input `input_idx`.
+    pub fn from_tx(
+        spk: &dogecoin::Script,
+        tx: &'txin dogecoin::Transaction,
+        input_idx: usize,
+    ) -> Result<Self, Error> {
+        let txin = tx
+            .input
+            .get(input_idx)
+            .ok_or(Error::InputIndexOutOfRange(input_idx))?;
+        if txin.previous_output.is_null() {
+            return Err(Error::CoinbaseInput);
+        }
+        Self::from_txdata(
+            spk,
+            &txin.script_sig,
+            &txin.witness,
+            tx.lock_time,
+            txin.sequence,
+        )
+    }
+
+    /// Constructs an interpreter as `from_txdata` does, but accepts scripts
+    /// whose `older` or `after` operands are non-minimally encoded numbers,
+    /// which some historical spends used. Such scripts are not standard, so
+    /// the operand values are available as warnings through
+    /// [Interpreter::nonminimal_timelocks].
+    pub fn from_txdata_allowing_nonminimal_timelocks(
+        spk: &dogecoin::Script,
+        script_sig: &'txin dogecoin::Script,
+        witness: &'txin [Vec<u8>],
+        age: u32,
+        height: u32,
+    ) -> Result<Self, Error> {
+        let (inner, stack, script_code, nonminimal) =
+            inner::from_txdata_allowing_nonminimal_timelocks(spk, script_sig, witness)?;
+        Self::from_parts(inner, stack, script_code, script_sig, witness, age, height, nonminimal)
+    }
+
+    fn from_parts(
+        inner: inner::Inner,
+        stack: Stack<'txin>,
+        script_code: dogecoin::Script,
+        script_sig: &'txin dogecoin::Script,
+        witness: &'txin [Vec<u8>],
+        age: u32,
+        height: u32,
+        nonminimal_timelocks: Vec<u32>,
+    ) -> Result<Self, Error> {
+        // Consensus limits every stack element, though not the witness
+        // script, which is not part of the stack
+        for elem in stack.as_slice() {
+            if let Element::Push(push) = *elem {
+                if push.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    return Err(Error::PushSizeExceeded(push.len()));
+                }
+            }
+        }
+        let mut initial_stack: Vec<_> = stack
+            .as_slice()
+            .iter()
+            .map(|elem| WitnessStackElement::from(elem.clone()).to_bytes())
+            .collect();
+        // The key is popped off the stack to match the spent key hash, but
+        // is still there when the script starts
+        if let inner::Inner::PublicKey(ref pk, ty) = inner {
+            if ty != inner::PubkeyType::Pk {
+                initial_stack.push(pk.to_bytes());
+            }
+        }
         Ok(Interpreter {
             inner,
             stack,
+            initial_stack,
             script_code,
+            script_sig,
+            witness,
             age,
             height,
+            median_time_past: None,
+            allow_nonstandard_cms_dummy: false,
+            nonminimal_timelocks,
         })
     }
 
+    /// The values of any non-minimally encoded timelock operands accepted by
+    /// [Interpreter::from_txdata_allowing_nonminimal_timelocks]. Always empty
+    /// for an interpreter constructed by [Interpreter::from_txdata].
+    pub fn nonminimal_timelocks(&self) -> &[u32] {
+        &self.nonminimal_timelocks
+    }
+
+    /// Sets whether the dummy element consumed by `OP_CHECKMULTISIG` may be
+    /// any value, rather than only the empty push required by the NULLDUMMY
+    /// standardness rule. Defaults to false; enabling it allows interpreting
+    /// historical spends which predate that rule.
+    pub fn allow_nonstandard_cms_dummy(&mut self, allow: bool) {
+        self.allow_nonstandard_cms_dummy = allow;
+    }
+
+    /// Sets the median-time-past (BIP113) elapsed since the spent coin was
+    /// confirmed, in seconds: the median-time-past of the chain tip less that
+    /// of the block before the one confirming the coin. Once set,
+    /// time-based `older` fragments must also have elapsed, as BIP68
+    /// requires, besides being met by the `nSequence` given as `height`.
+    pub fn set_median_time_past(&mut self, elapsed: u32) {
+        self.median_time_past = Some(elapsed);
+    }
+
     /// Creates an iterator over the satisfied spending conditions
     ///
     /// Returns all satisfied constraints, even if they were redundant (i.e. did
     /// not contribute to the script being satisfied). For example, if a signature
     /// were provided for an `and_b(Pk,false)` fragment, that signature will be
     /// returned, even though the entire and_b must have failed and must not have
-    /// been used.
+    /// been used. Use `iter_contributing` to omit such constraints.
     ///
     /// In case the script is actually dissatisfied, this may return several values
     /// before ultimately returning an error.
     ///
     /// Running the iterator through will consume the internal stack of the
-    /// `Iterpreter`, and it should not be used again after this.
+    /// `Iterpreter`, and it should not be used again after this. The iterator
+    /// borrows the interpreter for `'iter` only, not for `'txin`, so methods
+    /// which do not evaluate the stack, like `matched_script`, can still be
+    /// called once it is dropped.
     pub fn iter<'iter, F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool>(
         &'iter mut self,
         verify_sig: F,
-    ) -> Iter<'txin, 'iter, F> {
+    ) -> Iter<'iter, 'txin, F> {
+        let require_compressed_keys = !self.is_legacy();
         Iter {
             verify_sig: verify_sig,
             public_key: if let inner::Inner::PublicKey(ref pk, _) = self.inner {
@@ -104,8 +242,469 @@ impl<'txin> Interpreter<'txin> {
             stack: &mut self.stack,
             age: self.age,
             height: self.height,
+            median_time_past: self.median_time_past,
+            allow_nonstandard_cms_dummy: self.allow_nonstandard_cms_dummy,
+            require_compressed_keys: require_compressed_keys,
             has_errored: false,
+            hook: None,
+        }
+    }
+
+    /// Creates an iterator over the satisfied spending conditions, as with
+    /// `iter`, which also calls `hook` with each fragment and the current
+    /// stack (top element last) just before the fragment is evaluated.
+    /// Fragments with children are passed to the hook once when they are
+    /// first reached and again after each child has been evaluated.
+    pub fn iter_with_hook<'iter, F, H>(
+        &'iter mut self,
+        verify_sig: F,
+        hook: H,
+    ) -> Iter<'iter, 'txin, F, H>
+    where
+        F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+        H: FnMut(&Terminal<dogecoin::PublicKey, NoChecks>, &[Element<'txin>]),
+    {
+        let iter = self.iter(verify_sig);
+        Iter {
+            verify_sig: iter.verify_sig,
+            public_key: iter.public_key,
+            state: iter.state,
+            stack: iter.stack,
+            age: iter.age,
+            height: iter.height,
+            median_time_past: iter.median_time_past,
+            allow_nonstandard_cms_dummy: iter.allow_nonstandard_cms_dummy,
+            require_compressed_keys: iter.require_compressed_keys,
+            has_errored: iter.has_errored,
+            hook: Some(hook),
+        }
+    }
+
+    /// Checks that the spend satisfies its script, verifying signatures with
+    /// `verify_sig`, stopping at the first failure. Returns `Ok(true)` if
+    /// the script is satisfied; otherwise the error which stopped
+    /// evaluation is returned, e.g. `Error::ScriptSatisfactionError` for a
+    /// spend which runs to completion without satisfying the script.
+    ///
+    /// Like `iter`, this consumes the internal stack of the `Interpreter`.
+    pub fn verify<F>(&mut self, verify_sig: F) -> Result<bool, Error>
+    where
+        F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    {
+        for constraint in self.iter(verify_sig) {
+            constraint?;
+        }
+        Ok(true)
+    }
+
+    /// Checks that the spend is structurally a satisfaction of its script,
+    /// i.e. that it has the right number and kinds of pushes, selects valid
+    /// branches and meets its timelocks, assuming that every signature is
+    /// valid. This is much cheaper than verifying signatures, so it is useful
+    /// as a first pass to reject malformed spends. Signatures which do not
+    /// parse are still reported, but `Error::InvalidSignature` never is.
+    ///
+    /// Like `iter`, this consumes the internal stack of the `Interpreter`.
+    pub fn verify_structure(&mut self) -> Result<(), Error> {
+        for constraint in self.iter(|_, _| true) {
+            constraint?;
+        }
+        Ok(())
+    }
+
+    /// Checks that every hash preimage revealed by the spend satisfies every
+    /// hashlock in the script, not only the one it was revealed for. This is
+    /// meant for HTLC-style scripts whose hashlocks, possibly of different
+    /// hash types, all commit to a single secret; scripts using several
+    /// distinct secrets will always fail this check.
+    ///
+    /// Like `iter`, this consumes the internal stack of the `Interpreter`,
+    /// and will return any error encountered while evaluating the spend.
+    pub fn check_preimage_consistency<F>(&mut self, verify_sig: F) -> Result<(), Error>
+    where
+        F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    {
+        let mut preimages = vec![];
+        for constraint in self.iter(verify_sig) {
+            if let SatisfiedConstraint::HashLock { preimage, .. } = constraint? {
+                preimages.push(preimage);
+            }
+        }
+
+        let ms = match self.inner {
+            inner::Inner::Script(ref ms, _) => ms,
+            inner::Inner::PublicKey(..) => return Ok(()),
+        };
+        for preimage in preimages {
+            for node in ms.iter() {
+                let satisfied = match node.node {
+                    Terminal::Sha256(ref h) => sha256::Hash::hash(preimage) == *h,
+                    Terminal::Hash256(ref h) => sha256d::Hash::hash(preimage) == *h,
+                    Terminal::Ripemd160(ref h) => ripemd160::Hash::hash(preimage) == *h,
+                    Terminal::Hash160(ref h) => hash160::Hash::hash(preimage) == *h,
+                    _ => true,
+                };
+                if !satisfied {
+                    return Err(Error::InconsistentPreimage(preimage.to_vec()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The key and sighash coverage of every signature which verified, in
+    /// the order they were checked, for flagging signatures which do not
+    /// commit to the whole transaction
+    ///
+    /// Like `iter`, this consumes the internal stack of the `Interpreter`,
+    /// and will return any error encountered while evaluating the spend.
+    pub fn sighash_coverage<F>(
+        &mut self,
+        mut verify_sig: F,
+    ) -> Result<Vec<(dogecoin::PublicKey, SigHashCoverage)>, Error>
+    where
+        F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    {
+        let mut coverage = vec![];
+        {
+            let record = |pk: &dogecoin::PublicKey, sig: BitcoinSig| {
+                let valid = verify_sig(pk, sig);
+                if valid {
+                    coverage.push((*pk, SigHashCoverage::from_sighash_type(sig.1)));
+                }
+                valid
+            };
+            for constraint in self.iter(record) {
+                constraint?;
+            }
+        }
+        Ok(coverage)
+    }
+
+    /// The number of signatures checked during the spend, counting those of
+    /// `pk_h` fragments. See `OwnedInterpreter::signed_keys` for the keys
+    /// themselves.
+    ///
+    /// Like `iter`, this consumes the internal stack of the `Interpreter`,
+    /// and will return any error encountered while evaluating the spend.
+    pub fn signature_count<F>(&mut self, verify_sig: F) -> Result<usize, Error>
+    where
+        F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    {
+        let mut count = 0;
+        for constraint in self.iter(verify_sig) {
+            match constraint? {
+                SatisfiedConstraint::PublicKey { .. }
+                | SatisfiedConstraint::PublicKeyHash { .. } => count += 1,
+                _ => {}
+            }
+        }
+        Ok(count)
+    }
+
+    /// The signature constraints of the spend whose signatures have a high
+    /// S value. These are valid by consensus but rejected by relay policy,
+    /// so a spend containing any is unlikely to be accepted to the mempool.
+    /// Since libsecp256k1 only verifies low-S signatures, `verify_sig` is
+    /// given each signature with its S value normalized; the constraints
+    /// hold the signatures as they appear in the witness.
+    ///
+    /// Like `iter`, this consumes the internal stack of the `Interpreter`,
+    /// and will return any error encountered while evaluating the spend.
+    pub fn nonstandard_signatures<'iter, F>(
+        &'iter mut self,
+        mut verify_sig: F,
+    ) -> Result<Vec<SatisfiedConstraint<'iter, 'txin>>, Error>
+    where
+        F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    {
+        let normalized = |sig: &secp256k1::Signature| {
+            let mut sig = *sig;
+            sig.normalize_s();
+            sig
+        };
+        let verify_normalized = |pk: &dogecoin::PublicKey, (sig, ty): BitcoinSig| {
+            verify_sig(pk, (normalized(&sig), ty))
+        };
+        let mut ret = vec![];
+        for constraint in self.iter(verify_normalized) {
+            let constraint = constraint?;
+            let high_s = match constraint {
+                SatisfiedConstraint::PublicKey { ref sig, .. }
+                | SatisfiedConstraint::PublicKeyHash { ref sig, .. } => normalized(sig) != *sig,
+                _ => false,
+            };
+            if high_s {
+                ret.push(constraint);
+            }
+        }
+        Ok(ret)
+    }
+
+    /// For a strict audit, the `and_v` and `and_b` fragments which were
+    /// satisfied although one of their sides contributed no signature,
+    /// preimage or timelock, e.g. because it was a literal `1`. Such a
+    /// script effectively requires fewer conditions than it appears to.
+    /// Fragments which were not executed, or were dissatisfied, are not
+    /// reported.
+    ///
+    /// Like `iter`, this consumes the internal stack of the `Interpreter`,
+    /// and will return any error encountered while evaluating the spend.
+    pub fn trivial_ands<F>(&mut self, verify_sig: F) -> Result<Vec<TrivialAnd>, Error>
+    where
+        F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    {
+        let events = RefCell::new(vec![]);
+        {
+            let hook = |term: &Terminal<dogecoin::PublicKey, NoChecks>, stack: &[Element]| {
+                record_evaluation(&events, term, stack)
+            };
+            for constraint in self.iter_with_hook(verify_sig, hook) {
+                constraint?;
+                events.borrow_mut().push(None);
+            }
+        }
+        let events = events.into_inner();
+        let ms = match self.inner {
+            inner::Inner::Script(ref ms, _) => ms,
+            inner::Inner::PublicKey(..) => return Ok(vec![]),
+        };
+
+        let evaluate = |sub: &Miniscript<dogecoin::PublicKey, NoChecks>| {
+            let is_v = sub.ty.corr.base == types::Base::V;
+            fragment_result(&events, &fragment_terms(sub), is_v)
+        };
+
+        let mut ret = vec![];
+        for (i, frag) in ms.iter().enumerate() {
+            let (left, right) = match frag.node {
+                Terminal::AndV(ref left, ref right) | Terminal::AndB(ref left, ref right) => {
+                    (left, right)
+                }
+                _ => continue,
+            };
+            if let (Some((true, left)), Some((true, right))) = (evaluate(left), evaluate(right)) {
+                if !left || !right {
+                    ret.push(TrivialAnd {
+                        fragment: FragmentId(i),
+                        left: !left,
+                        right: !right,
+                    });
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// The constraints which contributed to satisfying the script, i.e.
+    /// those reported by `iter` less any satisfied within a fragment which
+    /// was ultimately dissatisfied, such as the signature in a dissatisfied
+    /// `and_b(pk(A),pk(B))` whose parent `or_b` was satisfied by its other
+    /// branch. This is the minimal set of conditions the spend relied on.
+    ///
+    /// Like `iter`, this consumes the internal stack of the `Interpreter`,
+    /// and will return any error encountered while evaluating the spend.
+    pub fn iter_contributing<'iter, F>(
+        &'iter mut self,
+        verify_sig: F,
+    ) -> Result<Vec<SatisfiedConstraint<'iter, 'txin>>, Error>
+    where
+        F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    {
+        // Each fragment, its parent, the fragments in its subtree, and
+        // whether it is a `V`, with parents before their children
+        let mut fragments = vec![];
+        if let inner::Inner::Script(ref ms, _) = self.inner {
+            let mut todo = vec![(ms, None)];
+            while let Some((frag, parent)) = todo.pop() {
+                let term = &frag.node as *const Terminal<_, _>;
+                let is_v = frag.ty.corr.base == types::Base::V;
+                fragments.push((term, parent, fragment_terms(frag), is_v));
+                todo.extend(frag.branches().into_iter().map(|sub| (sub, Some(term))));
+            }
+        }
+
+        let events = RefCell::new(vec![]);
+        let mut constraints = vec![];
+        {
+            let hook = |term: &Terminal<dogecoin::PublicKey, NoChecks>, stack: &[Element]| {
+                record_evaluation(&events, term, stack)
+            };
+            for constraint in self.iter_with_hook(verify_sig, hook) {
+                let constraint = constraint?;
+                let mut events = events.borrow_mut();
+                let term = events.iter().rev().filter_map(|event| event.map(|e| e.0)).next();
+                constraints.push((term, constraint));
+                events.push(None);
+            }
+        }
+        let events = events.into_inner();
+        if fragments.is_empty() {
+            return Ok(constraints.into_iter().map(|(_, c)| c).collect());
+        }
+
+        // A fragment contributes if it and all its ancestors were satisfied
+        let mut contributing = HashSet::new();
+        for &(term, parent, ref terms, is_v) in &fragments {
+            let parent_contributes = parent.map_or(true, |p| contributing.contains(&p));
+            let satisfied = fragment_result(&events, terms, is_v).map_or(false, |r| r.0);
+            if parent_contributes && satisfied {
+                contributing.insert(term);
+            }
+        }
+        Ok(constraints
+            .into_iter()
+            .filter(|&(term, _)| term.map_or(false, |term| contributing.contains(&term)))
+            .map(|(_, c)| c)
+            .collect())
+    }
+
+    /// Reports the constraints satisfied by fragments at most `max_depth`
+    /// levels below the root of the script, for a quick classification of a
+    /// spend, e.g. which top-level branch was taken, before interpreting it
+    /// fully. Deeper fragments are reported as `NotEvaluated` where they
+    /// begin; they must still be executed to consume their part of the
+    /// witness, but their signatures are assumed valid rather than checked
+    /// and their constraints are not reported.
+    ///
+    /// Like `iter`, this consumes the internal stack of the `Interpreter`,
+    /// and will return any error encountered while evaluating the spend.
+    pub fn iter_depth_limited<'iter, F>(
+        &'iter mut self,
+        mut verify_sig: F,
+        max_depth: usize,
+    ) -> Result<Vec<DepthLimitedItem<'iter, 'txin>>, Error>
+    where
+        F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    {
+        let mut fragments = HashMap::new();
+        if let inner::Inner::Script(ref ms, _) = self.inner {
+            let mut ids = HashMap::new();
+            for (i, frag) in ms.iter().enumerate() {
+                ids.insert(&frag.node as *const Terminal<_, _>, FragmentId(i));
+            }
+            let mut todo = vec![(ms, 0)];
+            while let Some((frag, depth)) = todo.pop() {
+                let term = &frag.node as *const Terminal<_, _>;
+                fragments.insert(term, (ids[&term], depth));
+                todo.extend(frag.branches().into_iter().map(|sub| (sub, depth + 1)));
+            }
+        }
+
+        // The hook records the fragment being evaluated, so that signatures
+        // and constraints can be attributed to it
+        let deep = Cell::new(false);
+        let items = RefCell::new(vec![]);
+        {
+            let hook = |term: &Terminal<dogecoin::PublicKey, NoChecks>, _: &[Element]| {
+                let (id, depth) = fragments[&(term as *const _)];
+                deep.set(depth > max_depth);
+                let item = DepthLimitedItem::NotEvaluated(id);
+                let mut items = items.borrow_mut();
+                if depth == max_depth + 1 && items.last() != Some(&item) {
+                    items.push(item);
+                }
+            };
+            let verify_sig = |pk: &dogecoin::PublicKey, sig: BitcoinSig| {
+                deep.get() || verify_sig(pk, sig)
+            };
+            for constraint in self.iter_with_hook(verify_sig, hook) {
+                let constraint = constraint?;
+                if !deep.get() {
+                    items.borrow_mut().push(DepthLimitedItem::Constraint(constraint));
+                }
+            }
+        }
+        Ok(items.into_inner())
+    }
+
+    /// A summary of the path the spend took through its script: the
+    /// fragments which contributed a constraint, and the keys which signed.
+    /// Spends of the same script along the same path, e.g. a vault's hot
+    /// path, have equal fingerprints, so comparing the fingerprint of a new
+    /// spend with the expected one lets a monitor spot one which took
+    /// another path, e.g. recovery. Branches contributing no constraint do
+    /// not affect the fingerprint.
+    ///
+    /// Like `iter`, this consumes the internal stack of the `Interpreter`,
+    /// and will return any error encountered while evaluating the spend.
+    pub fn path_taken<F>(&mut self, verify_sig: F) -> Result<PathFingerprint, Error>
+    where
+        F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    {
+        let mut ids = HashMap::new();
+        if let inner::Inner::Script(ref ms, _) = self.inner {
+            for (i, frag) in ms.iter().enumerate() {
+                ids.insert(&frag.node as *const Terminal<_, _>, FragmentId(i));
+            }
+        }
+
+        // Constraints are reported by the last fragment given to the hook
+        let current = Cell::new(None);
+        let mut fragments = vec![];
+        let mut keys = vec![];
+        {
+            let hook = |term: &Terminal<dogecoin::PublicKey, NoChecks>, _: &[Element]| {
+                current.set(ids.get(&(term as *const _)).cloned());
+            };
+            for constraint in self.iter_with_hook(verify_sig, hook) {
+                match constraint? {
+                    SatisfiedConstraint::PublicKey { key, .. } => keys.push(*key),
+                    SatisfiedConstraint::PublicKeyHash { key, .. } => keys.push(key),
+                    _ => {}
+                }
+                fragments.extend(current.get());
+            }
+        }
+        fragments.sort();
+        fragments.dedup();
+        keys.sort();
+        keys.dedup();
+        Ok(PathFingerprint {
+            fragments: fragments,
+            keys: keys,
+        })
+    }
+
+    /// For each `multi` fragment of the script, in the order given by
+    /// `Miniscript::iter`, the keys which did not provide a valid signature,
+    /// e.g. for a co-signing service to report which signers it is still
+    /// waiting on. Spends of scripts without a `multi` return no lists.
+    ///
+    /// Like `iter`, this consumes the internal stack of the `Interpreter`,
+    /// and will return any error encountered while evaluating the spend.
+    pub fn multi_unsigned_keys<F>(
+        &mut self,
+        verify_sig: F,
+    ) -> Result<Vec<Vec<dogecoin::PublicKey>>, Error>
+    where
+        F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    {
+        // Keys are identified by address, as the same key may appear in
+        // several fragments
+        let mut signed = HashSet::new();
+        for constraint in self.iter(verify_sig) {
+            if let SatisfiedConstraint::PublicKey { key, .. } = constraint? {
+                signed.insert(key as *const dogecoin::PublicKey);
+            }
         }
+
+        let ms = match self.inner {
+            inner::Inner::Script(ref ms, _) => ms,
+            inner::Inner::PublicKey(..) => return Ok(vec![]),
+        };
+        Ok(ms
+            .iter()
+            .filter_map(|node| match node.node {
+                Terminal::Multi(_, ref keys) => Some(
+                    keys.iter()
+                        .filter(|key| !signed.contains(&(*key as *const _)))
+                        .cloned()
+                        .collect(),
+                ),
+                _ => None,
+            })
+            .collect())
     }
 
     /// Outputs a "descriptor" string which reproduces the spent coins
@@ -134,17 +733,267 @@ impl<'txin> Interpreter<'txin> {
         }
     }
 
+    /// Snapshot of the elements remaining on the stack. Before iterating,
+    /// these are the elements the Miniscript will consume; after iterating
+    /// a satisfied spend, none remain.
+    pub fn stack_snapshot(&self) -> PublicStack {
+        PublicStack::new(
+            self.stack
+                .as_slice()
+                .iter()
+                .map(|elem| WitnessStackElement::from(*elem))
+                .collect(),
+        )
+    }
+
+    /// The type of the spent scriptPubKey, as inferred from the spend
+    pub fn spk_type(&self) -> SpkType {
+        match self.inner {
+            inner::Inner::PublicKey(_, inner::PubkeyType::Pk) => SpkType::Pk,
+            inner::Inner::PublicKey(_, inner::PubkeyType::Pkh) => SpkType::Pkh,
+            inner::Inner::PublicKey(_, inner::PubkeyType::Wpkh) => SpkType::Wpkh,
+            inner::Inner::PublicKey(_, inner::PubkeyType::ShWpkh) => SpkType::ShWpkh,
+            inner::Inner::Script(_, inner::ScriptType::Bare) => SpkType::Bare,
+            inner::Inner::Script(_, inner::ScriptType::Sh) => SpkType::Sh,
+            inner::Inner::Script(_, inner::ScriptType::Wsh) => SpkType::Wsh,
+            inner::Inner::Script(_, inner::ScriptType::ShWsh) => SpkType::ShWsh,
+        }
+    }
+
     /// Whether this is a pre-segwit spend
     pub fn is_legacy(&self) -> bool {
+        match self.spk_type() {
+            SpkType::Pk => true,
+            SpkType::Pkh => true,
+            SpkType::Wpkh => false,
+            SpkType::ShWpkh => false, // lol "sorta"
+            SpkType::Bare => true,
+            SpkType::Sh => true,
+            SpkType::Wsh => false,
+            SpkType::ShWsh => false, // lol "sorta"
+        }
+    }
+
+    /// The redeem script or witness script revealed by the spending
+    /// transaction, whose hash matched the spent output. Returns `None`
+    /// for bare script and public key spends, which reveal no such script.
+    pub fn matched_script(&self) -> Option<&dogecoin::Script> {
+        match self.inner {
+            inner::Inner::PublicKey(..) => None,
+            inner::Inner::Script(_, inner::ScriptType::Bare) => None,
+            inner::Inner::Script(_, inner::ScriptType::Sh)
+            | inner::Inner::Script(_, inner::ScriptType::Wsh)
+            | inner::Inner::Script(_, inner::ScriptType::ShWsh) => Some(&self.script_code),
+        }
+    }
+
+    /// The Miniscript parsed from the spent script, for analysing its
+    /// fragments directly. Returns `None` for public key spends, which
+    /// are not interpreted as Miniscript.
+    pub fn miniscript(&self) -> Option<&Miniscript<dogecoin::PublicKey, NoChecks>> {
         match self.inner {
-            inner::Inner::PublicKey(_, inner::PubkeyType::Pk) => true,
-            inner::Inner::PublicKey(_, inner::PubkeyType::Pkh) => true,
-            inner::Inner::PublicKey(_, inner::PubkeyType::Wpkh) => false,
-            inner::Inner::PublicKey(_, inner::PubkeyType::ShWpkh) => false, // lol "sorta"
-            inner::Inner::Script(_, inner::ScriptType::Bare) => true,
-            inner::Inner::Script(_, inner::ScriptType::Sh) => true,
-            inner::Inner::Script(_, inner::ScriptType::Wsh) => false,
-            inner::Inner::Script(_, inner::ScriptType::ShWsh) => false, // lol "sorta"
+            inner::Inner::PublicKey(..) => None,
+            inner::Inner::Script(ref ms, _) => Some(ms),
+        }
+    }
+
+    /// The public keys the spent script references, in order of first
+    /// appearance, without evaluating the spend: the keys of every `pk` and
+    /// `multi` fragment, and for each `pkh` fragment the key with matching
+    /// hash pushed by the spending transaction, if any. Public key spends
+    /// return their single key.
+    ///
+    /// This does not consume the internal stack, so may be called before
+    /// iterating.
+    pub fn public_keys(&self) -> Vec<dogecoin::PublicKey> {
+        let ms = match self.inner {
+            inner::Inner::PublicKey(pk, _) => return vec![pk],
+            inner::Inner::Script(ref ms, _) => ms,
+        };
+        let mut ret = vec![];
+        for pk_pkh in ms.iter_pk_pkh() {
+            let pk = match pk_pkh {
+                PkPkh::PlainPubkey(pk) => Some(pk),
+                PkPkh::HashedPubkey(hash) => self
+                    .stack
+                    .as_slice()
+                    .iter()
+                    .filter_map(|elem| match *elem {
+                        Element::Push(push) if hash160::Hash::hash(push) == hash => {
+                            dogecoin::PublicKey::from_slice(push).ok()
+                        }
+                        _ => None,
+                    })
+                    .next(),
+            };
+            if let Some(pk) = pk {
+                if !ret.contains(&pk) {
+                    ret.push(pk);
+                }
+            }
+        }
+        ret
+    }
+
+    /// Checks the spend against the script limits which apply to it, and
+    /// returns the first one violated:
+    ///
+    /// * at most 201 non-push opcodes, counting the keys of every `multi`
+    ///   as if it were executed, so this may reject a spend which skips
+    ///   some `multi` fragments
+    /// * a script of at most 10000 bytes, or 520 bytes for a P2SH redeem
+    ///   script, or 3600 bytes for a witness script (a standardness limit)
+    /// * at most 100 witness stack items for P2WSH (a standardness limit)
+    /// * stack elements of at most 520 bytes
+    /// * at most 15 sigops in a P2SH redeem script (a standardness limit)
+    ///
+    /// The stack is checked as it currently is, so this should be called
+    /// before iterating.
+    pub fn check_consensus_limits(&self) -> Result<(), Error> {
+        for elem in self.stack.as_slice() {
+            if let Element::Push(push) = *elem {
+                if push.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    return Err(Error::PushSizeExceeded(push.len()));
+                }
+            }
+        }
+        let (ms, script_type) = match self.inner {
+            inner::Inner::Script(ref ms, script_type) => (ms, script_type),
+            inner::Inner::PublicKey(..) => return Ok(()),
+        };
+        let script = &self.script_code;
+
+        let op_count = op_count(ms, script);
+        let mut sigops = 0;
+        let mut last_op = None;
+        for ins in script.instructions() {
+            if let Ok(dogecoin::blockdata::script::Instruction::Op(op)) = ins {
+                sigops += match op {
+                    opcodes::all::OP_CHECKSIG | opcodes::all::OP_CHECKSIGVERIFY => 1,
+                    opcodes::all::OP_CHECKMULTISIG | opcodes::all::OP_CHECKMULTISIGVERIFY => {
+                        match last_op.map(|op: opcodes::All| op.into_u8()) {
+                            Some(n)
+                                if n >= opcodes::all::OP_PUSHNUM_1.into_u8()
+                                    && n <= opcodes::all::OP_PUSHNUM_16.into_u8() =>
+                            {
+                                (n - opcodes::all::OP_PUSHNUM_1.into_u8() + 1) as usize
+                            }
+                            _ => 20,
+                        }
+                    }
+                    _ => 0,
+                };
+                last_op = Some(op);
+            } else {
+                last_op = None;
+            }
+        }
+        if op_count > MAX_OPS_PER_SCRIPT {
+            return Err(Error::OpCountExceeded(op_count));
+        }
+
+        let max_size = match script_type {
+            inner::ScriptType::Bare => MAX_SCRIPT_SIZE,
+            inner::ScriptType::Sh => MAX_SCRIPT_ELEMENT_SIZE,
+            inner::ScriptType::Wsh | inner::ScriptType::ShWsh => MAX_STANDARD_P2WSH_SCRIPT_SIZE,
+        };
+        if script.len() > max_size {
+            return Err(Error::ScriptSizeExceeded(script.len()));
+        }
+
+        match script_type {
+            inner::ScriptType::Wsh | inner::ScriptType::ShWsh => {
+                // Not counting the witness script itself
+                let items = self.witness.len().saturating_sub(1);
+                if items > MAX_STANDARD_P2WSH_STACK_ITEMS {
+                    return Err(Error::WitnessStackItemsExceeded(items));
+                }
+            }
+            inner::ScriptType::Sh if sigops > MAX_P2SH_SIGOPS => {
+                return Err(Error::SigOpsExceeded(sigops));
+            }
+            inner::ScriptType::Sh | inner::ScriptType::Bare => {}
+        }
+        Ok(())
+    }
+
+    /// Whether input `input_idx` of `tx`, which this interpreter's spend is
+    /// taken from, could have its sequence number set to signal
+    /// replaceability (BIP125) while still meeting every `older` constraint
+    /// on the path its witness takes. Such a sequence number exists unless
+    /// those constraints mix blocks and time, or `tx` has a version below 2
+    /// and so does not enforce relative timelocks at all. Since signatures
+    /// commit to sequence numbers, the input must be signed again after the
+    /// change.
+    ///
+    /// Returns `false` if the witness does not follow a valid path through
+    /// the script. Signatures are not checked. The stack is evaluated as it
+    /// currently is, so this should be called before iterating. Also
+    /// returns `false` if `tx` has no input `input_idx`.
+    pub fn rbf_compatible(&self, tx: &dogecoin::Transaction, input_idx: usize) -> bool {
+        match tx.input.get(input_idx) {
+            Some(txin) if txin.sequence < 0xffff_fffe => return true,
+            Some(_) => {}
+            None => return false,
+        }
+        // Evaluate a copy without timelocks, to find those on the path
+        let mut copy = self.clone();
+        copy.age = u32::max_value();
+        copy.height = u32::max_value();
+        copy.median_time_past = None;
+        copy.nonminimal_timelocks = vec![];
+        let mut olders = vec![];
+        for constraint in copy.iter(|_, _| true) {
+            match constraint {
+                Ok(SatisfiedConstraint::RelativeTimeLock { time }) => olders.push(*time),
+                Ok(_) => {}
+                Err(_) => return false,
+            }
+        }
+        // Any consistent set of relative timelocks is met by a sequence
+        // number with the disable flag clear, which signals replaceability
+        olders.is_empty() || (tx.version >= 2 && aggregate_relative_locktimes(&olders).is_ok())
+    }
+
+    /// Size and opcode count of the script returned by `matched_script`,
+    /// for checking it against the standardness limits on witness scripts
+    /// and the consensus limit on executed opcodes
+    pub fn script_stats(&self) -> Option<ScriptStats> {
+        match (self.matched_script(), &self.inner) {
+            (Some(script), &inner::Inner::Script(ref ms, _)) => Some(ScriptStats {
+                size: script.len(),
+                op_count: op_count(ms, script),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The most elements held on the main and alternate stacks together
+    /// while executing the script of the spend, which consensus limits to
+    /// 1000. This counts the witness elements the script starts with, but
+    /// not the witness script, and assumes every non-empty signature is
+    /// valid.
+    pub fn max_stack_depth(&self) -> usize {
+        depth::max_depth(&self.script_code, self.initial_stack.clone())
+    }
+
+    /// The weight, in weight units, of the scriptSig and witness of the
+    /// interpreted spend. Non-witness bytes count 4 WU each and witness bytes
+    /// 1 WU each; for legacy spends no witness is serialized at all. The
+    /// fixed-size outpoint and `nSequence` fields of the input are not included.
+    pub fn weight_units(&self) -> usize {
+        let script_sig_len = self.script_sig.len();
+        let script_sig_weight = 4 * (varint_len(script_sig_len) + script_sig_len);
+        if self.is_legacy() {
+            script_sig_weight
+        } else {
+            script_sig_weight
+                + varint_len(self.witness.len())
+                + self
+                    .witness
+                    .iter()
+                    .map(|elem| varint_len(elem.len()) + elem.len())
+                    .sum::<usize>()
         }
     }
 
@@ -158,6 +1007,35 @@ impl<'txin> Interpreter<'txin> {
         Descriptor::from_str(&self.inferred_descriptor_string())
     }
 
+    /// The address of the spent output, for reconciling against the address
+    /// it was expected to pay. Returns `None` for pay-to-pubkey and bare
+    /// script spends, which have no address, and for segwit spends of
+    /// uncompressed keys.
+    pub fn inferred_address(&self, network: dogecoin::Network) -> Option<dogecoin::Address> {
+        match self.inner {
+            inner::Inner::PublicKey(_, inner::PubkeyType::Pk) => None,
+            inner::Inner::PublicKey(ref pk, inner::PubkeyType::Pkh) => {
+                Some(dogecoin::Address::p2pkh(pk, network))
+            }
+            inner::Inner::PublicKey(ref pk, inner::PubkeyType::Wpkh) => {
+                dogecoin::Address::p2wpkh(pk, network).ok()
+            }
+            inner::Inner::PublicKey(ref pk, inner::PubkeyType::ShWpkh) => {
+                dogecoin::Address::p2shwpkh(pk, network).ok()
+            }
+            inner::Inner::Script(_, inner::ScriptType::Bare) => None,
+            inner::Inner::Script(ref ms, inner::ScriptType::Sh) => {
+                Some(dogecoin::Address::p2sh(&ms.encode(), network))
+            }
+            inner::Inner::Script(ref ms, inner::ScriptType::Wsh) => {
+                Some(dogecoin::Address::p2wsh(&ms.encode(), network))
+            }
+            inner::Inner::Script(ref ms, inner::ScriptType::ShWsh) => {
+                Some(dogecoin::Address::p2shwsh(&ms.encode(), network))
+            }
+        }
+    }
+
     /// Returns a sighash over the entire transaction which can be used to verify signatures
     /// in the descriptor
     ///
@@ -189,55 +1067,676 @@ impl<'txin> Interpreter<'txin> {
         unsigned_tx: &'a dogecoin::Transaction,
         input_idx: usize,
         amount: u64,
+    ) -> impl Fn(&dogecoin::PublicKey, BitcoinSig) -> bool + 'a {
+        let mut sighash_cache = TxSigHashCache::new(unsigned_tx);
+        self.sighash_verify_with_cache(secp, &mut sighash_cache, input_idx, amount)
+    }
+
+    /// Returns a closure which can be given to the `iter` method to check all
+    /// signatures, as with `sighash_verify`, but computing sighashes for the
+    /// transaction of `sighash_cache`. Reusing one cache for every input of a
+    /// transaction avoids recomputing the BIP143 hashes of its inputs and
+    /// outputs for each.
+    pub fn sighash_verify_with_cache<'a, C: secp256k1::Verification>(
+        &self,
+        secp: &'a secp256k1::Secp256k1<C>,
+        sighash_cache: &mut TxSigHashCache,
+        input_idx: usize,
+        amount: u64,
     ) -> impl Fn(&dogecoin::PublicKey, BitcoinSig) -> bool + 'a {
         // Precompute all sighash types because the borrowck doesn't like us
         // pulling self into the closure
-        let sighashes = [
-            self.sighash_message(unsigned_tx, input_idx, amount, dogecoin::SigHashType::All),
-            self.sighash_message(unsigned_tx, input_idx, amount, dogecoin::SigHashType::None),
-            self.sighash_message(unsigned_tx, input_idx, amount, dogecoin::SigHashType::Single),
-            self.sighash_message(
-                unsigned_tx,
-                input_idx,
-                amount,
-                dogecoin::SigHashType::AllPlusAnyoneCanPay,
-            ),
-            self.sighash_message(
-                unsigned_tx,
-                input_idx,
-                amount,
-                dogecoin::SigHashType::NonePlusAnyoneCanPay,
-            ),
-            self.sighash_message(
-                unsigned_tx,
-                input_idx,
-                amount,
-                dogecoin::SigHashType::SinglePlusAnyoneCanPay,
-            ),
-        ];
-
+        let sighashes = self.sighash_messages(sighash_cache, input_idx, amount);
         move |pk: &dogecoin::PublicKey, (sig, sighash_type)| {
-            // This is an awkward way to do this lookup, but it lets us do exhaustiveness
-            // checking in case future rust-bitcoin versions add new sighash types
-            let sighash = match sighash_type {
-                dogecoin::SigHashType::All => sighashes[0],
-                dogecoin::SigHashType::None => sighashes[1],
-                dogecoin::SigHashType::Single => sighashes[2],
-                dogecoin::SigHashType::AllPlusAnyoneCanPay => sighashes[3],
-                dogecoin::SigHashType::NonePlusAnyoneCanPay => sighashes[4],
-                dogecoin::SigHashType::SinglePlusAnyoneCanPay => sighashes[5],
+            secp.verify(&sighashes.get(sighash_type), &sig, &pk.key).is_ok()
+        }
+    }
+
+    /// Checks that the spend satisfies its script as `verify` does, with
+    /// signatures checked against `unsigned_tx` as by `sighash_verify`, and
+    /// returns the satisfied constraints. The spend is first evaluated
+    /// assuming every signature valid, to collect the signatures it relies
+    /// on, which are then checked together. libsecp256k1 offers no batch
+    /// verification of ECDSA signatures, so they are checked one by one.
+    ///
+    /// If any collected signature is invalid, e.g. because a `multi`
+    /// signature was tried against a key it does not belong to, the spend
+    /// is evaluated again checking each signature as it is reached, so the
+    /// result is always that of `iter` with `sighash_verify`.
+    ///
+    /// Like `iter`, this consumes the internal stack of the `Interpreter`.
+    pub fn verify_batch<'iter, C: secp256k1::Verification>(
+        &'iter mut self,
+        secp: &secp256k1::Secp256k1<C>,
+        unsigned_tx: &dogecoin::Transaction,
+        input_idx: usize,
+        amount: u64,
+    ) -> Result<Vec<SatisfiedConstraint<'iter, 'txin>>, Error> {
+        let mut sighash_cache = TxSigHashCache::new(unsigned_tx);
+        let sighashes = self.sighash_messages(&mut sighash_cache, input_idx, amount);
+
+        let mut sigs = vec![];
+        let dry_run = {
+            let record = |pk: &dogecoin::PublicKey, (sig, sighash_type)| {
+                sigs.push((*pk, sig, sighashes.get(sighash_type)));
+                true
             };
-            secp.verify(&sighash, &sig, &pk.key).is_ok()
+            self.clone().verify(record)
+        };
+        let all_valid = dry_run.is_ok()
+            && sigs
+                .iter()
+                .all(|&(ref pk, ref sig, ref msg)| secp.verify(msg, sig, &pk.key).is_ok());
+
+        if all_valid {
+            self.iter(|_, _| true).collect()
+        } else {
+            self.iter(|pk: &dogecoin::PublicKey, (sig, sighash_type)| {
+                secp.verify(&sighashes.get(sighash_type), &sig, &pk.key).is_ok()
+            })
+            .collect()
         }
     }
+
+    /// The message signed by each sighash type for this spend
+    fn sighash_messages(
+        &self,
+        sighash_cache: &mut TxSigHashCache,
+        input_idx: usize,
+        amount: u64,
+    ) -> SigHashMessages {
+        let unsigned_tx = sighash_cache.tx;
+        let bip143_cache = &mut sighash_cache.bip143;
+        let mut sighash = |sighash_type: dogecoin::SigHashType| {
+            let hash = if self.is_legacy() {
+                unsigned_tx.signature_hash(input_idx, &self.script_code, sighash_type.as_u32())
+            } else {
+                bip143_cache.signature_hash(input_idx, &self.script_code, amount, sighash_type)
+            };
+            secp256k1::Message::from_slice(&hash[..])
+                .expect("cryptographically unreachable for this to fail")
+        };
+        SigHashMessages([
+            sighash(dogecoin::SigHashType::All),
+            sighash(dogecoin::SigHashType::None),
+            sighash(dogecoin::SigHashType::Single),
+            sighash(dogecoin::SigHashType::AllPlusAnyoneCanPay),
+            sighash(dogecoin::SigHashType::NonePlusAnyoneCanPay),
+            sighash(dogecoin::SigHashType::SinglePlusAnyoneCanPay),
+        ])
+    }
 }
 
-/// Type of HashLock used for SatisfiedConstraint structure
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub enum HashLockType<'intp> {
-    ///SHA 256 hashlock
-    Sha256(&'intp sha256::Hash),
-    ///Hash 256 hashlock
+/// A transaction along with the BIP143 hashes of its inputs and outputs,
+/// computed as required, for checking the signatures of its inputs. Unlike
+/// `bip143::SigHashCache`, which it wraps, it keeps the transaction at hand
+/// for legacy sighashes too.
+pub struct TxSigHashCache<'tx> {
+    tx: &'tx dogecoin::Transaction,
+    bip143: bip143::SigHashCache<&'tx dogecoin::Transaction>,
+}
+
+impl<'tx> TxSigHashCache<'tx> {
+    /// Creates a cache over `tx`, computing nothing yet
+    pub fn new(tx: &'tx dogecoin::Transaction) -> Self {
+        TxSigHashCache {
+            tx: tx,
+            bip143: bip143::SigHashCache::new(tx),
+        }
+    }
+
+    /// The transaction whose sighashes are cached
+    pub fn transaction(&self) -> &'tx dogecoin::Transaction {
+        self.tx
+    }
+}
+
+/// The message signed by each sighash type for a spend
+#[derive(Copy, Clone)]
+struct SigHashMessages([secp256k1::Message; 6]);
+
+impl SigHashMessages {
+    fn get(&self, sighash_type: dogecoin::SigHashType) -> secp256k1::Message {
+        // This is an awkward way to do this lookup, but it lets us do exhaustiveness
+        // checking in case future rust-bitcoin versions add new sighash types
+        match sighash_type {
+            dogecoin::SigHashType::All => self.0[0],
+            dogecoin::SigHashType::None => self.0[1],
+            dogecoin::SigHashType::Single => self.0[2],
+            dogecoin::SigHashType::AllPlusAnyoneCanPay => self.0[3],
+            dogecoin::SigHashType::NonePlusAnyoneCanPay => self.0[4],
+            dogecoin::SigHashType::SinglePlusAnyoneCanPay => self.0[5],
+        }
+    }
+}
+
+/// The type of scriptPubKey spent by an `Interpreter`'s spend
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum SpkType {
+    /// Bare public key (pay-to-pubkey)
+    Pk,
+    /// Pay-to-pubkey-hash
+    Pkh,
+    /// Pay-to-witness-pubkey-hash
+    Wpkh,
+    /// Pay-to-witness-pubkey-hash nested in P2SH
+    ShWpkh,
+    /// Bare script, other than a public key
+    Bare,
+    /// Pay-to-script-hash
+    Sh,
+    /// Pay-to-witness-script-hash
+    Wsh,
+    /// Pay-to-witness-script-hash nested in P2SH
+    ShWsh,
+}
+
+/// Size statistics of a matched redeem or witness script
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ScriptStats {
+    /// Length of the script in bytes
+    pub size: usize,
+    /// Number of opcodes in the script counted against the 201-opcode limit,
+    /// as by `Interpreter::check_consensus_limits`: the non-push opcodes,
+    /// plus the keys of every `multi` as if it were executed
+    pub op_count: usize,
+}
+
+/// An `and_v` or `and_b` fragment which was satisfied with a side which
+/// contributed no constraint, as reported by `Interpreter::trivial_ands`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct TrivialAnd {
+    /// The fragment, by its position in `Miniscript::iter`
+    pub fragment: FragmentId,
+    /// Whether the left side contributed no constraint
+    pub left: bool,
+    /// Whether the right side contributed no constraint
+    pub right: bool,
+}
+
+/// The path taken by a spend through its script, as reported by
+/// `Interpreter::path_taken`
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct PathFingerprint {
+    /// The fragments which contributed a constraint, by their position in
+    /// `Miniscript::iter`, in that order
+    pub fragments: Vec<FragmentId>,
+    /// The keys which provided a valid signature, sorted
+    pub keys: Vec<dogecoin::PublicKey>,
+}
+
+/// An item reported by `Interpreter::iter_depth_limited`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DepthLimitedItem<'intp, 'txin> {
+    /// A constraint satisfied by a fragment within the depth limit
+    Constraint(SatisfiedConstraint<'intp, 'txin>),
+    /// A fragment just past the depth limit, by its position in
+    /// `Miniscript::iter`, whose constraints were not evaluated
+    NotEvaluated(FragmentId),
+}
+
+/// Number of opcodes of `ms`, encoded as `script`, counted against the
+/// 201-opcode limit. Core counts the non-push opcodes, and the keys of each
+/// `CHECKMULTISIG` it executes, which are counted here for every `multi`.
+fn op_count(ms: &Miniscript<dogecoin::PublicKey, NoChecks>, script: &dogecoin::Script) -> usize {
+    let multi_keys: usize = ms
+        .iter()
+        .map(|node| match node.node {
+            Terminal::Multi(_, ref keys) => keys.len(),
+            _ => 0,
+        })
+        .sum();
+    let non_push = script
+        .instructions()
+        .filter(|ins| match *ins {
+            Ok(dogecoin::blockdata::script::Instruction::Op(op)) => {
+                op.into_u8() > opcodes::all::OP_PUSHNUM_16.into_u8()
+            }
+            _ => false,
+        })
+        .count();
+    multi_keys + non_push
+}
+
+/// An event recorded while interpreting a script: a fragment about to be
+/// evaluated and whether the top of the stack is satisfied, or `None` for a
+/// constraint, which belongs to the fragment of the preceding event
+type EvaluationEvent = Option<(*const Terminal<dogecoin::PublicKey, NoChecks>, bool)>;
+
+/// Records an evaluation event from an `Interpreter::iter_with_hook` hook
+fn record_evaluation(
+    events: &RefCell<Vec<EvaluationEvent>>,
+    term: &Terminal<dogecoin::PublicKey, NoChecks>,
+    stack: &[Element],
+) {
+    let satisfied = stack.last() == Some(&Element::Satisfied);
+    events.borrow_mut().push(Some((term as *const _, satisfied)));
+}
+
+/// The fragments in the subtree of `sub`, as recorded in evaluation events
+fn fragment_terms(
+    sub: &Miniscript<dogecoin::PublicKey, NoChecks>,
+) -> HashSet<*const Terminal<dogecoin::PublicKey, NoChecks>> {
+    sub.iter()
+        .map(|ms| &ms.node as *const Terminal<dogecoin::PublicKey, NoChecks>)
+        .collect()
+}
+
+/// Whether a sub-fragment, given by the fragments in its subtree and
+/// whether it is a `V`, was satisfied, and whether it contributed any
+/// constraint, or `None` if it was not executed. Its evaluation is a
+/// contiguous run of events, after which its result is on the top of the
+/// stack; `V` fragments abort the script rather than fail.
+fn fragment_result(
+    events: &[EvaluationEvent],
+    in_sub: &HashSet<*const Terminal<dogecoin::PublicKey, NoChecks>>,
+    is_v: bool,
+) -> Option<(bool, bool)> {
+    let start = events.iter().position(|event| match *event {
+        Some((term, _)) => in_sub.contains(&term),
+        None => false,
+    })?;
+    let mut constrained = false;
+    let mut end = start;
+    while end < events.len() {
+        match events[end] {
+            Some((term, _)) if !in_sub.contains(&term) => break,
+            Some(_) => {}
+            None => constrained = true,
+        }
+        end += 1;
+    }
+    let satisfied = is_v
+        || match events.get(end) {
+            Some(&Some((_, satisfied))) => satisfied,
+            Some(&None) => false,
+            // The script completed successfully
+            None => true,
+        };
+    Some((satisfied, constrained))
+}
+
+/// Owned copy of the data spending a coin, which an `Interpreter` can borrow.
+/// Useful when the data does not already live in a decoded transaction, e.g.
+/// when it was copied as hex from RPC output or a block explorer.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OwnedTxData {
+    /// The scriptPubKey of the spent output
+    pub spk: dogecoin::Script,
+    /// The scriptSig of the spending input
+    pub script_sig: dogecoin::Script,
+    /// The witness of the spending input
+    pub witness: Vec<Vec<u8>>,
+}
+
+impl OwnedTxData {
+    /// Decodes the scriptPubKey, scriptSig and witness elements from hex
+    pub fn from_hex(
+        spk_hex: &str,
+        script_sig_hex: &str,
+        witness_hex: &[&str],
+    ) -> Result<Self, Error> {
+        Ok(OwnedTxData {
+            spk: dogecoin::Script::from(Vec::<u8>::from_hex(spk_hex)?),
+            script_sig: dogecoin::Script::from(Vec::<u8>::from_hex(script_sig_hex)?),
+            witness: witness_hex
+                .iter()
+                .map(|elem| Vec::<u8>::from_hex(elem))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Constructs an interpreter over this spending data, as with
+    /// `Interpreter::from_txdata`
+    pub fn interpreter(&self, age: u32, height: u32) -> Result<Interpreter, Error> {
+        Interpreter::from_txdata(&self.spk, &self.script_sig, &self.witness, age, height)
+    }
+}
+
+/// An interpreter which owns the data of the spend it interprets, and so
+/// can outlive the transaction it was taken from
+///
+/// Since the constraints returned by `Interpreter::iter` borrow the spend
+/// data, iterating is done through a borrowing `Interpreter` obtained from
+/// the `interpreter` method. The remaining methods return owned results.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OwnedInterpreter {
+    txdata: OwnedTxData,
+    age: u32,
+    height: u32,
+    allow_nonstandard_cms_dummy: bool,
+}
+
+impl OwnedInterpreter {
+    /// Constructs an interpreter from owned spending data, checking the data
+    /// as `Interpreter::from_txdata` does
+    pub fn new(txdata: OwnedTxData, age: u32, height: u32) -> Result<Self, Error> {
+        txdata.interpreter(age, height)?;
+        Ok(OwnedInterpreter {
+            txdata: txdata,
+            age: age,
+            height: height,
+            allow_nonstandard_cms_dummy: false,
+        })
+    }
+
+    /// The spending data being interpreted
+    pub fn txdata(&self) -> &OwnedTxData {
+        &self.txdata
+    }
+
+    /// Sets whether the dummy element consumed by `OP_CHECKMULTISIG` may be
+    /// any value, as with `Interpreter::allow_nonstandard_cms_dummy`
+    pub fn allow_nonstandard_cms_dummy(&mut self, allow: bool) {
+        self.allow_nonstandard_cms_dummy = allow;
+    }
+
+    /// Constructs a fresh `Interpreter` borrowing the owned spending data
+    pub fn interpreter(&self) -> Interpreter {
+        let mut interpreter = self
+            .txdata
+            .interpreter(self.age, self.height)
+            .expect("spend data checked on construction");
+        interpreter.allow_nonstandard_cms_dummy(self.allow_nonstandard_cms_dummy);
+        interpreter
+    }
+
+    /// Whether the spend satisfies its script, i.e. whether iterating over
+    /// its constraints finishes without an error
+    pub fn is_satisfied<F>(&self, verify_sig: F) -> bool
+    where
+        F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    {
+        self.interpreter().iter(verify_sig).all(|res| res.is_ok())
+    }
+
+    /// The public keys whose signatures were checked during the spend,
+    /// including keys revealed for `pk_h` fragments. Returns the first
+    /// error encountered if the spend does not satisfy its script.
+    pub fn signed_keys<F>(&self, verify_sig: F) -> Result<Vec<dogecoin::PublicKey>, Error>
+    where
+        F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    {
+        let mut interpreter = self.interpreter();
+        let mut keys = vec![];
+        for constraint in interpreter.iter(verify_sig) {
+            match constraint? {
+                SatisfiedConstraint::PublicKey { key, .. } => keys.push(*key),
+                SatisfiedConstraint::PublicKeyHash { key, .. } => keys.push(key),
+                _ => {}
+            }
+        }
+        Ok(keys)
+    }
+
+    /// The number of signatures checked during the spend, as with
+    /// `Interpreter::signature_count`
+    pub fn signature_count<F>(&self, verify_sig: F) -> Result<usize, Error>
+    where
+        F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    {
+        self.interpreter().signature_count(verify_sig)
+    }
+}
+
+/// Verifies every input of a package of transactions, such as a parent and
+/// a child spending it to bump its fee. Transactions must be ordered so that
+/// parents come before their children; an input spending an output of an
+/// earlier transaction in the package is checked against that output, and
+/// any other input against its entry in `prevouts`. Timelocks are checked
+/// against each transaction's `nLockTime` and each input's `nSequence`.
+///
+/// On failure returns the index of the transaction and input which failed,
+/// along with the error, which is `Error::MissingPrevout` for inputs whose
+/// spent output is not known.
+pub fn verify_package<C: secp256k1::Verification>(
+    txs: &[dogecoin::Transaction],
+    prevouts: &HashMap<dogecoin::OutPoint, dogecoin::TxOut>,
+    secp: &secp256k1::Secp256k1<C>,
+) -> Result<(), (usize, usize, Error)> {
+    let mut package_outputs = HashMap::new();
+    for (tx_idx, tx) in txs.iter().enumerate() {
+        for (input_idx, txin) in tx.input.iter().enumerate() {
+            let prevout = match package_outputs.get(&txin.previous_output) {
+                Some(prevout) => *prevout,
+                None => match prevouts.get(&txin.previous_output) {
+                    Some(prevout) => prevout,
+                    None => {
+                        let err = Error::MissingPrevout(txin.previous_output);
+                        return Err((tx_idx, input_idx, err));
+                    }
+                },
+            };
+            verify_input(tx, input_idx, prevout, secp).map_err(|e| (tx_idx, input_idx, e))?;
+        }
+        let txid = tx.txid();
+        for (vout, txout) in tx.output.iter().enumerate() {
+            package_outputs.insert(dogecoin::OutPoint::new(txid, vout as u32), txout);
+        }
+    }
+    Ok(())
+}
+
+/// Whether two witnesses both satisfy the same segwit `spk`, with an empty
+/// scriptSig and assuming every signature is valid. Two distinct witnesses
+/// which both satisfy it show that a third party could replace one with
+/// the other, e.g. in hand-built witnesses which do not follow the
+/// non-malleable satisfaction rules.
+///
+/// Returns an error if either witness cannot be parsed against `spk`, e.g.
+/// because its witness script does not match.
+pub fn witnesses_equivalent(
+    spk: &dogecoin::Script,
+    w1: &[Vec<u8>],
+    w2: &[Vec<u8>],
+    age: u32,
+    height: u32,
+) -> Result<bool, Error> {
+    let empty = dogecoin::Script::new();
+    for witness in &[w1, w2] {
+        let mut interpreter = Interpreter::from_txdata(spk, &empty, witness, age, height)?;
+        if interpreter.verify_structure().is_err() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Summary of the conditions satisfied by all inputs of a transaction
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransactionReport {
+    /// Number of signatures verified, including repeated signatures by the
+    /// same key
+    pub sig_count: usize,
+    /// Distinct keys which provided a valid signature, in order of first
+    /// appearance
+    pub signed_keys: Vec<dogecoin::PublicKey>,
+    /// Distinct hash preimages revealed, in order of first appearance
+    pub preimages: Vec<Vec<u8>>,
+    /// The largest `after` operand satisfied by any input
+    pub max_absolute_timelock: Option<u32>,
+    /// The largest `older` operand satisfied by any input. Relative
+    /// timelocks apply to each input separately, and time-based ones always
+    /// compare greater than height-based ones.
+    pub max_relative_timelock: Option<u32>,
+}
+
+impl TransactionReport {
+    fn add_constraint(&mut self, constraint: &SatisfiedConstraint) {
+        match *constraint {
+            SatisfiedConstraint::PublicKey { key, .. } => self.add_signature(*key),
+            SatisfiedConstraint::PublicKeyHash { key, .. } => self.add_signature(key),
+            SatisfiedConstraint::HashLock { preimage, .. } => {
+                if !self.preimages.iter().any(|p| &p[..] == preimage) {
+                    self.preimages.push(preimage.to_vec());
+                }
+            }
+            SatisfiedConstraint::AbsoluteTimeLock { time } => {
+                self.max_absolute_timelock = cmp::max(self.max_absolute_timelock, Some(*time));
+            }
+            SatisfiedConstraint::RelativeTimeLock { time } => {
+                self.max_relative_timelock = cmp::max(self.max_relative_timelock, Some(*time));
+            }
+        }
+    }
+
+    fn add_signature(&mut self, key: dogecoin::PublicKey) {
+        self.sig_count += 1;
+        if !self.signed_keys.contains(&key) {
+            self.signed_keys.push(key);
+        }
+    }
+}
+
+/// Interprets every input of `tx`, verifying signatures, and merges the
+/// conditions they satisfy into a single report. The outputs spent by the
+/// inputs are looked up in `prevouts`.
+///
+/// On failure returns the index of the input which failed, along with the
+/// error, which is `Error::MissingPrevout` for inputs whose spent output is
+/// not known.
+pub fn transaction_report<C: secp256k1::Verification>(
+    tx: &dogecoin::Transaction,
+    prevouts: &HashMap<dogecoin::OutPoint, dogecoin::TxOut>,
+    secp: &secp256k1::Secp256k1<C>,
+) -> Result<TransactionReport, (usize, Error)> {
+    let mut report = TransactionReport::default();
+    for (input_idx, txin) in tx.input.iter().enumerate() {
+        let prevout = match prevouts.get(&txin.previous_output) {
+            Some(prevout) => prevout,
+            None => return Err((input_idx, Error::MissingPrevout(txin.previous_output))),
+        };
+        interpret_input(tx, input_idx, prevout, secp, |c| report.add_constraint(c))
+            .map_err(|e| (input_idx, e))?;
+    }
+    Ok(report)
+}
+
+/// The conditions satisfied by a single input, as reported by
+/// `BlockInterpreter`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpendSummary {
+    /// The value of the spent output
+    pub value: u64,
+    /// The conditions satisfied by the input
+    pub report: TransactionReport,
+}
+
+/// Interprets every input of a block which spends an output known to
+/// `resolver`, verifying signatures, yielding the txid and index of each
+/// such input along with a summary of the conditions it satisfies
+///
+/// `resolver` returns the scriptPubKey and value of a spent output, or
+/// `None` for outputs which are not of interest, whose spending inputs are
+/// skipped. Coinbase inputs spend no output and are always skipped.
+pub struct BlockInterpreter<'blk, C: 'blk + secp256k1::Verification, R> {
+    block: &'blk dogecoin::Block,
+    resolver: R,
+    secp: &'blk secp256k1::Secp256k1<C>,
+    tx_idx: usize,
+    input_idx: usize,
+}
+
+impl<'blk, C, R> BlockInterpreter<'blk, C, R>
+where
+    C: secp256k1::Verification,
+    R: Fn(&dogecoin::OutPoint) -> Option<(dogecoin::Script, u64)>,
+{
+    /// Constructs an interpreter over the inputs of `block`
+    pub fn new(
+        block: &'blk dogecoin::Block,
+        resolver: R,
+        secp: &'blk secp256k1::Secp256k1<C>,
+    ) -> Self {
+        BlockInterpreter {
+            block: block,
+            resolver: resolver,
+            secp: secp,
+            tx_idx: 0,
+            input_idx: 0,
+        }
+    }
+}
+
+impl<'blk, C, R> Iterator for BlockInterpreter<'blk, C, R>
+where
+    C: secp256k1::Verification,
+    R: Fn(&dogecoin::OutPoint) -> Option<(dogecoin::Script, u64)>,
+{
+    type Item = (dogecoin::Txid, usize, Result<SpendSummary, Error>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(tx) = self.block.txdata.get(self.tx_idx) {
+            let input_idx = self.input_idx;
+            if input_idx >= tx.input.len() {
+                self.tx_idx += 1;
+                self.input_idx = 0;
+                continue;
+            }
+            self.input_idx += 1;
+
+            let txin = &tx.input[input_idx];
+            if txin.previous_output.is_null() {
+                continue;
+            }
+            let (script_pubkey, value) = match (self.resolver)(&txin.previous_output) {
+                Some(prevout) => prevout,
+                None => continue,
+            };
+            let prevout = dogecoin::TxOut {
+                value: value,
+                script_pubkey: script_pubkey,
+            };
+            let mut report = TransactionReport::default();
+            let res = interpret_input(tx, input_idx, &prevout, self.secp, |c| {
+                report.add_constraint(c)
+            });
+            let res = res.map(|()| SpendSummary {
+                value: value,
+                report: report,
+            });
+            return Some((tx.txid(), input_idx, res));
+        }
+        None
+    }
+}
+
+fn verify_input<C: secp256k1::Verification>(
+    tx: &dogecoin::Transaction,
+    input_idx: usize,
+    prevout: &dogecoin::TxOut,
+    secp: &secp256k1::Secp256k1<C>,
+) -> Result<(), Error> {
+    interpret_input(tx, input_idx, prevout, secp, |_| {})
+}
+
+/// Interprets an input, verifying signatures, and passes each satisfied
+/// constraint to `visit`
+fn interpret_input<C, G>(
+    tx: &dogecoin::Transaction,
+    input_idx: usize,
+    prevout: &dogecoin::TxOut,
+    secp: &secp256k1::Secp256k1<C>,
+    mut visit: G,
+) -> Result<(), Error>
+where
+    C: secp256k1::Verification,
+    G: FnMut(&SatisfiedConstraint),
+{
+    let mut interpreter = Interpreter::from_tx(&prevout.script_pubkey, tx, input_idx)?;
+    let verify_sig = interpreter.sighash_verify(secp, tx, input_idx, prevout.value);
+    for constraint in interpreter.iter(verify_sig) {
+        visit(&constraint?);
+    }
+    Ok(())
+}
+
+/// Type of HashLock used for SatisfiedConstraint structure
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum HashLockType<'intp> {
+    ///SHA 256 hashlock
+    Sha256(&'intp sha256::Hash),
+    ///Hash 256 hashlock
     Hash256(&'intp sha256d::Hash),
     ///Hash160 hashlock
     Hash160(&'intp hash160::Hash),
@@ -256,6 +1755,8 @@ pub enum SatisfiedConstraint<'intp, 'txin> {
         key: &'intp dogecoin::PublicKey,
         /// corresponding signature
         sig: secp256k1::Signature,
+        /// The sighash type of the signature
+        sighash_type: dogecoin::SigHashType,
     },
     ///PublicKeyHash, corresponding pubkey and signature
     PublicKeyHash {
@@ -265,6 +1766,8 @@ pub enum SatisfiedConstraint<'intp, 'txin> {
         key: dogecoin::PublicKey,
         /// Corresponding signature for the hash
         sig: secp256k1::Signature,
+        /// The sighash type of the signature
+        sighash_type: dogecoin::SigHashType,
     },
     ///Hashlock and preimage for SHA256
     HashLock {
@@ -285,6 +1788,89 @@ pub enum SatisfiedConstraint<'intp, 'txin> {
     },
 }
 
+/// Which outputs of a transaction a signature commits to
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OutputCoverage {
+    /// Every output (`SIGHASH_ALL`)
+    All,
+    /// No outputs (`SIGHASH_NONE`)
+    None,
+    /// Only the output with the same index as the signed input
+    /// (`SIGHASH_SINGLE`)
+    Single,
+}
+
+/// The parts of a transaction a signature commits to, as determined by its
+/// sighash type
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SigHashCoverage {
+    /// The sighash type of the signature
+    pub sighash_type: dogecoin::SigHashType,
+    /// Whether every input is committed to, rather than only the signed one
+    /// (`SIGHASH_ANYONECANPAY`)
+    pub all_inputs: bool,
+    /// Which outputs are committed to
+    pub outputs: OutputCoverage,
+}
+
+impl SigHashCoverage {
+    /// Compute the coverage of a sighash type
+    pub fn from_sighash_type(sighash_type: dogecoin::SigHashType) -> SigHashCoverage {
+        let (all_inputs, outputs) = match sighash_type {
+            dogecoin::SigHashType::All => (true, OutputCoverage::All),
+            dogecoin::SigHashType::None => (true, OutputCoverage::None),
+            dogecoin::SigHashType::Single => (true, OutputCoverage::Single),
+            dogecoin::SigHashType::AllPlusAnyoneCanPay => (false, OutputCoverage::All),
+            dogecoin::SigHashType::NonePlusAnyoneCanPay => (false, OutputCoverage::None),
+            dogecoin::SigHashType::SinglePlusAnyoneCanPay => (false, OutputCoverage::Single),
+        };
+        SigHashCoverage {
+            sighash_type: sighash_type,
+            all_inputs: all_inputs,
+            outputs: outputs,
+        }
+    }
+
+    /// Whether the signature commits to every input and output
+    pub fn is_complete(&self) -> bool {
+        self.all_inputs && self.outputs == OutputCoverage::All
+    }
+}
+
+/// The encoding of an ECDSA signature checked by the interpreter, useful for
+/// telling whether the signer ground its nonce for a low R value
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SignatureForm {
+    /// Length of the DER encoding, excluding the sighash byte
+    pub der_len: usize,
+    /// Whether R is below 2^255, so is encoded in at most 32 bytes
+    pub low_r: bool,
+}
+
+impl SignatureForm {
+    /// Compute the form of a signature
+    pub fn from_sig(sig: &secp256k1::Signature) -> SignatureForm {
+        SignatureForm {
+            der_len: sig.serialize_der().len(),
+            low_r: sig.serialize_compact()[0] < 0x80,
+        }
+    }
+}
+
+impl<'intp, 'txin> SatisfiedConstraint<'intp, 'txin> {
+    /// The form of the signature satisfying this constraint, if it is a
+    /// `PublicKey` or `PublicKeyHash` constraint
+    pub fn signature_form(&self) -> Option<SignatureForm> {
+        match *self {
+            SatisfiedConstraint::PublicKey { ref sig, .. }
+            | SatisfiedConstraint::PublicKeyHash { ref sig, .. } => {
+                Some(SignatureForm::from_sig(sig))
+            }
+            _ => None,
+        }
+    }
+}
+
 ///This is used by the interpreter to know which evaluation state a AstemElem is.
 ///This is required because whenever a same node(for eg. OrB) appears on the stack, we don't
 ///know if the left child has been evaluated or not. And based on the result on
@@ -311,21 +1897,33 @@ struct NodeEvaluationState<'intp> {
 ///
 /// In case the script is actually dissatisfied, this may return several values
 /// before ultimately returning an error.
-pub struct Iter<'intp, 'txin: 'intp, F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool> {
+///
+/// `H` is the type of the hook given to `Interpreter::iter_with_hook`, if any.
+pub struct Iter<
+    'intp,
+    'txin: 'intp,
+    F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    H = fn(&Terminal<dogecoin::PublicKey, NoChecks>, &[Element<'txin>]),
+> {
     verify_sig: F,
     public_key: Option<&'intp dogecoin::PublicKey>,
     state: Vec<NodeEvaluationState<'intp>>,
     stack: &'intp mut Stack<'txin>,
     age: u32,
     height: u32,
+    median_time_past: Option<u32>,
+    allow_nonstandard_cms_dummy: bool,
+    require_compressed_keys: bool,
     has_errored: bool,
+    hook: Option<H>,
 }
 
 ///Iterator for Iter
-impl<'intp, 'txin: 'intp, F> Iterator for Iter<'intp, 'txin, F>
+impl<'intp, 'txin: 'intp, F, H> Iterator for Iter<'intp, 'txin, F, H>
 where
     NoChecks: ScriptContext,
     F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    H: FnMut(&Terminal<dogecoin::PublicKey, NoChecks>, &[Element<'txin>]),
 {
     type Item = Result<SatisfiedConstraint<'intp, 'txin>, Error>;
 
@@ -343,11 +1941,57 @@ where
     }
 }
 
-impl<'intp, 'txin: 'intp, F> Iter<'intp, 'txin, F>
+/// Iterator over satisfied constraints paired with the witness pushes which
+/// satisfied them, returned by `Iter::with_raw`
+pub struct WithRaw<
+    'intp,
+    'txin: 'intp,
+    F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    H = fn(&Terminal<dogecoin::PublicKey, NoChecks>, &[Element<'txin>]),
+> {
+    iter: Iter<'intp, 'txin, F, H>,
+}
+
+impl<'intp, 'txin: 'intp, F, H> Iterator for WithRaw<'intp, 'txin, F, H>
+where
+    NoChecks: ScriptContext,
+    F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    H: FnMut(&Terminal<dogecoin::PublicKey, NoChecks>, &[Element<'txin>]),
+{
+    type Item = Result<(SatisfiedConstraint<'intp, 'txin>, &'txin [u8]), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let constraint = match self.iter.next()? {
+            Ok(constraint) => constraint,
+            Err(e) => return Some(Err(e)),
+        };
+        let raw = match constraint {
+            SatisfiedConstraint::PublicKey { .. } | SatisfiedConstraint::PublicKeyHash { .. } => {
+                self.iter.stack.last_push().unwrap_or(&[])
+            }
+            SatisfiedConstraint::HashLock { preimage, .. } => preimage,
+            SatisfiedConstraint::RelativeTimeLock { .. }
+            | SatisfiedConstraint::AbsoluteTimeLock { .. } => &[],
+        };
+        Some(Ok((constraint, raw)))
+    }
+}
+
+impl<'intp, 'txin: 'intp, F, H> Iter<'intp, 'txin, F, H>
 where
     NoChecks: ScriptContext,
     F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+    H: FnMut(&Terminal<dogecoin::PublicKey, NoChecks>, &[Element<'txin>]),
 {
+    /// Adapts the iterator to yield, along with each constraint, the witness
+    /// push which satisfied it: the serialized signature, including its
+    /// sighash byte, for `PublicKey` and `PublicKeyHash` constraints, and the
+    /// preimage for `HashLock` constraints. Timelocks consume no push, so
+    /// are paired with an empty slice.
+    pub fn with_raw(self) -> WithRaw<'intp, 'txin, F, H> {
+        WithRaw { iter: self }
+    }
+
     /// Helper function to push a NodeEvaluationState on state stack
     fn push_evaluation_state(
         &mut self,
@@ -365,6 +2009,9 @@ where
     /// Helper function to step the iterator
     fn iter_next(&mut self) -> Option<Result<SatisfiedConstraint<'intp, 'txin>, Error>> {
         while let Some(node_state) = self.state.pop() {
+            if let Some(ref mut hook) = self.hook {
+                hook(&node_state.node.node, self.stack.as_slice());
+            }
             //non-empty stack
             match node_state.node.node {
                 Terminal::True => {
@@ -388,7 +2035,11 @@ where
                 Terminal::PkH(ref pkh) => {
                     debug_assert_eq!(node_state.n_evaluated, 0);
                     debug_assert_eq!(node_state.n_satisfied, 0);
-                    let res = self.stack.evaluate_pkh(&mut self.verify_sig, pkh);
+                    let res = self.stack.evaluate_pkh(
+                        &mut self.verify_sig,
+                        pkh,
+                        self.require_compressed_keys,
+                    );
                     if res.is_some() {
                         return res;
                     }
@@ -405,6 +2056,13 @@ where
                     debug_assert_eq!(node_state.n_evaluated, 0);
                     debug_assert_eq!(node_state.n_satisfied, 0);
                     let res = self.stack.evaluate_older(n, self.height);
+                    // Time-based locks count units of 512 seconds
+                    if let (Some(&Ok(_)), Some(elapsed)) = (res.as_ref(), self.median_time_past)
+                    {
+                        if n & SEQUENCE_LOCKTIME_TYPE_FLAG != 0 && elapsed / 512 < n & 0xffff {
+                            return Some(Err(Error::RelativeLocktimeNotMet(*n)));
+                        }
+                    }
                     if res.is_some() {
                         return res;
                     }
@@ -487,9 +2145,14 @@ where
                 Terminal::NonZero(ref sub) => {
                     debug_assert_eq!(node_state.n_evaluated, 0);
                     debug_assert_eq!(node_state.n_satisfied, 0);
+                    // The `OP_IF` here is driven by `OP_SIZE OP_0NOTEQUAL`, which
+                    // always yields a minimal boolean, so unlike `d:`/`or_i`/`andor`
+                    // any non-empty push (e.g. a signature) selects the sub.
                     match self.stack.last() {
                         Some(&stack::Element::Dissatisfied) => (),
-                        Some(_) => self.push_evaluation_state(sub, 0, 0),
+                        Some(&stack::Element::Satisfied) | Some(&stack::Element::Push(_)) => {
+                            self.push_evaluation_state(sub, 0, 0)
+                        }
                         None => return Some(Err(Error::UnexpectedStackEnd)),
                     }
                 }
@@ -647,7 +2310,11 @@ where
                                 let sigs = self.stack.split_off(len - (k + 1));
                                 let nonsat = sigs
                                     .iter()
-                                    .map(|sig| *sig == stack::Element::Dissatisfied)
+                                    .enumerate()
+                                    .map(|(i, sig)| {
+                                        *sig == stack::Element::Dissatisfied
+                                            || (i == 0 && self.allow_nonstandard_cms_dummy)
+                                    })
                                     .filter(|empty| *empty)
                                     .count();
                                 if nonsat == *k + 1 {
@@ -684,10 +2351,14 @@ where
                 Terminal::Multi(k, ref subs) => {
                     if node_state.n_satisfied == k {
                         //multi-sig bug: Pop extra 0
-                        if let Some(stack::Element::Dissatisfied) = self.stack.pop() {
-                            self.stack.push(stack::Element::Satisfied);
-                        } else {
-                            return Some(Err(Error::MissingExtraZeroMultiSig));
+                        match self.stack.pop() {
+                            Some(stack::Element::Dissatisfied) => {
+                                self.stack.push(stack::Element::Satisfied)
+                            }
+                            Some(_) if self.allow_nonstandard_cms_dummy => {
+                                self.stack.push(stack::Element::Satisfied)
+                            }
+                            _ => return Some(Err(Error::MissingExtraZeroMultiSig)),
                         }
                     } else if node_state.n_evaluated == subs.len() {
                         return Some(Err(Error::MultiSigEvaluationError));
@@ -723,12 +2394,16 @@ where
         //Pk based descriptor
         if let Some(pk) = self.public_key {
             if let Some(stack::Element::Push(sig)) = self.stack.pop() {
-                if let Ok(sig) = verify_sersig(&mut self.verify_sig, &pk, &sig) {
+                if let Ok((sig, sighash_type)) = verify_sersig(&mut self.verify_sig, &pk, &sig) {
                     //Signature check successful, set public_key to None to
                     //terminate the next() function in the subsequent call
                     self.public_key = None;
                     self.stack.push(stack::Element::Satisfied);
-                    return Some(Ok(SatisfiedConstraint::PublicKey { key: pk, sig }));
+                    return Some(Ok(SatisfiedConstraint::PublicKey {
+                        key: pk,
+                        sig,
+                        sighash_type,
+                    }));
                 } else {
                     return Some(Err(Error::PkEvaluationError(pk.clone().to_public_key())));
                 }
@@ -752,7 +2427,7 @@ fn verify_sersig<'txin, F>(
     verify_sig: F,
     pk: &dogecoin::PublicKey,
     sigser: &[u8],
-) -> Result<secp256k1::Signature, Error>
+) -> Result<BitcoinSig, Error>
 where
     F: FnOnce(&dogecoin::PublicKey, BitcoinSig) -> bool,
 {
@@ -761,7 +2436,7 @@ where
             .map_err(|_| Error::NonStandardSigHash([sig, &[*sighash_byte]].concat().to_vec()))?;
         let sig = secp256k1::Signature::from_der(sig)?;
         if verify_sig(pk, (sig, sighashtype)) {
-            Ok(sig)
+            Ok((sig, sighashtype))
         } else {
             Err(Error::InvalidSignature(*pk))
         }
@@ -775,9 +2450,11 @@ mod tests {
 
     use super::*;
     use dogecoin;
+    use dogecoin::hashes::hex::ToHex;
     use dogecoin::hashes::{hash160, ripemd160, sha256, sha256d, Hash};
     use dogecoin::secp256k1::{self, Secp256k1, VerifyOnly};
     use miniscript::context::NoChecks;
+    use miniscript::limits::SEQUENCE_LOCKTIME_DISABLE_FLAG;
     use BitcoinSig;
     use Miniscript;
     use MiniscriptKey;
@@ -845,7 +2522,11 @@ mod tests {
                 }],
                 age: 1002,
                 height: 1002,
+                median_time_past: None,
+                allow_nonstandard_cms_dummy: false,
+                require_compressed_keys: false,
                 has_errored: false,
+                hook: None,
             }
         };
 
@@ -877,6 +2558,23 @@ mod tests {
             vec![SatisfiedConstraint::PublicKey {
                 key: &pks[0],
                 sig: secp_sigs[0].clone(),
+                sighash_type: dogecoin::SigHashType::All,
+            }]
+        );
+
+        //Check the sighash type is reported
+        let mut single_sig = der_sigs[0].clone();
+        *single_sig.last_mut().unwrap() = 0x83;
+        let mut stack = Stack::from(vec![stack::Element::Push(&single_sig)]);
+        let mut vfyfn = vfyfn_.clone(); // sigh rust 1.29...
+        let constraints = from_stack(&mut vfyfn, &mut stack, &pk);
+        let pk_satisfied: Result<Vec<SatisfiedConstraint>, Error> = constraints.collect();
+        assert_eq!(
+            pk_satisfied.unwrap(),
+            vec![SatisfiedConstraint::PublicKey {
+                key: &pks[0],
+                sig: secp_sigs[0].clone(),
+                sighash_type: dogecoin::SigHashType::SinglePlusAnyoneCanPay,
             }]
         );
 
@@ -902,9 +2600,42 @@ mod tests {
                 keyhash: &pks[1].to_pubkeyhash(),
                 key: pks[1].clone(),
                 sig: secp_sigs[1].clone(),
+                sighash_type: dogecoin::SigHashType::All,
             }]
         );
 
+        //Check Pkh failure with an element too short to be a pubkey
+        let mut stack = Stack::from(vec![
+            stack::Element::Push(&der_sigs[1]),
+            stack::Element::Push(&pk_bytes[..32]),
+        ]);
+        let mut vfyfn = vfyfn_.clone(); // sigh rust 1.29...
+        let constraints = from_stack(&mut vfyfn, &mut stack, &pkh);
+        let pkh_err: Result<Vec<SatisfiedConstraint>, Error> = constraints.collect();
+        match pkh_err {
+            Err(Error::InvalidPubkeyLength(32)) => {}
+            x => panic!("expected invalid pubkey length, got {:?}", x),
+        }
+
+        //Check timelocks with invalid operands
+        let bad_older = ms_str!("older({})", 0x0001_0005);
+        let disabled_older = ms_str!("older({})", SEQUENCE_LOCKTIME_DISABLE_FLAG | 5);
+        let bad_after = ms_str!("after({})", 0x8000_0000u32);
+        for (ms, n) in vec![
+            (bad_older, 0x0001_0005),
+            (disabled_older, SEQUENCE_LOCKTIME_DISABLE_FLAG | 5),
+            (bad_after, 0x8000_0000),
+        ] {
+            let mut stack = Stack::from(vec![]);
+            let mut vfyfn = vfyfn_.clone(); // sigh rust 1.29...
+            let constraints = from_stack(&mut vfyfn, &mut stack, &ms);
+            let timelock_err: Result<Vec<SatisfiedConstraint>, Error> = constraints.collect();
+            match timelock_err {
+                Err(Error::InvalidLocktimeValue(m)) => assert_eq!(m, n),
+                x => panic!("expected invalid locktime, got {:?}", x),
+            }
+        }
+
         //Check After
         let mut stack = Stack::from(vec![]);
         let mut vfyfn = vfyfn_.clone(); // sigh rust 1.29...
@@ -999,11 +2730,13 @@ mod tests {
                 SatisfiedConstraint::PublicKey {
                     key: &pks[0],
                     sig: secp_sigs[0].clone(),
+                    sighash_type: dogecoin::SigHashType::All,
                 },
                 SatisfiedConstraint::PublicKeyHash {
                     keyhash: &pks[1].to_pubkeyhash(),
                     key: pks[1].clone(),
                     sig: secp_sigs[1].clone(),
+                    sighash_type: dogecoin::SigHashType::All,
                 }
             ]
         );
@@ -1024,6 +2757,7 @@ mod tests {
                 SatisfiedConstraint::PublicKey {
                     key: &pks[0],
                     sig: secp_sigs[0].clone(),
+                    sighash_type: dogecoin::SigHashType::All,
                 },
                 SatisfiedConstraint::HashLock {
                     hash: HashLockType::Sha256(&sha256_hash),
@@ -1053,6 +2787,7 @@ mod tests {
                 SatisfiedConstraint::PublicKey {
                     key: &pks[0],
                     sig: secp_sigs[0].clone(),
+                    sighash_type: dogecoin::SigHashType::All,
                 },
                 SatisfiedConstraint::HashLock {
                     hash: HashLockType::Sha256(&sha256_hash),
@@ -1078,6 +2813,7 @@ mod tests {
                 keyhash: &pks[1].to_pubkeyhash(),
                 key: pks[1].clone(),
                 sig: secp_sigs[1].clone(),
+                sighash_type: dogecoin::SigHashType::All,
             }]
         );
 
@@ -1111,6 +2847,7 @@ mod tests {
             vec![SatisfiedConstraint::PublicKey {
                 key: &pks[0],
                 sig: secp_sigs[0].clone(),
+                sighash_type: dogecoin::SigHashType::All,
             }]
         );
 
@@ -1129,6 +2866,7 @@ mod tests {
             vec![SatisfiedConstraint::PublicKey {
                 key: &pks[0],
                 sig: secp_sigs[0].clone(),
+                sighash_type: dogecoin::SigHashType::All,
             }]
         );
 
@@ -1147,6 +2885,7 @@ mod tests {
             vec![SatisfiedConstraint::PublicKey {
                 key: &pks[0],
                 sig: secp_sigs[0].clone(),
+                sighash_type: dogecoin::SigHashType::All,
             }]
         );
 
@@ -1176,14 +2915,17 @@ mod tests {
                 SatisfiedConstraint::PublicKey {
                     key: &pks[2],
                     sig: secp_sigs[2].clone(),
+                    sighash_type: dogecoin::SigHashType::All,
                 },
                 SatisfiedConstraint::PublicKey {
                     key: &pks[1],
                     sig: secp_sigs[1].clone(),
+                    sighash_type: dogecoin::SigHashType::All,
                 },
                 SatisfiedConstraint::PublicKey {
                     key: &pks[0],
                     sig: secp_sigs[0].clone(),
+                    sighash_type: dogecoin::SigHashType::All,
                 }
             ]
         );
@@ -1213,14 +2955,17 @@ mod tests {
                 SatisfiedConstraint::PublicKey {
                     key: &pks[0],
                     sig: secp_sigs[0].clone(),
+                    sighash_type: dogecoin::SigHashType::All,
                 },
                 SatisfiedConstraint::PublicKey {
                     key: &pks[1],
                     sig: secp_sigs[1].clone(),
+                    sighash_type: dogecoin::SigHashType::All,
                 },
                 SatisfiedConstraint::PublicKey {
                     key: &pks[2],
                     sig: secp_sigs[2].clone(),
+                    sighash_type: dogecoin::SigHashType::All,
                 },
             ]
         );
@@ -1245,5 +2990,1833 @@ mod tests {
 
         let multi_error: Result<Vec<SatisfiedConstraint>, Error> = constraints.collect();
         assert!(multi_error.is_err());
+
+        // Multi with a non-empty dummy element is only accepted in lax mode
+        let dummy = [0x02];
+        let mut stack = Stack::from(vec![
+            stack::Element::Push(&dummy),
+            stack::Element::Push(&der_sigs[1]),
+            stack::Element::Push(&der_sigs[0]),
+        ]);
+        let elem = ms_str!("multi(2,{},{},{})", pks[2], pks[1], pks[0]);
+        let mut vfyfn = vfyfn_.clone(); // sigh rust 1.29...
+        let constraints = from_stack(&mut vfyfn, &mut stack, &elem);
+        let multi_error: Result<Vec<SatisfiedConstraint>, Error> = constraints.collect();
+        match multi_error {
+            Err(Error::MissingExtraZeroMultiSig) => {}
+            x => panic!("expected MissingExtraZeroMultiSig, got {:?}", x),
+        }
+
+        let mut stack = Stack::from(vec![
+            stack::Element::Push(&dummy),
+            stack::Element::Push(&der_sigs[1]),
+            stack::Element::Push(&der_sigs[0]),
+        ]);
+        let mut vfyfn = vfyfn_.clone(); // sigh rust 1.29...
+        let mut constraints = from_stack(&mut vfyfn, &mut stack, &elem);
+        constraints.allow_nonstandard_cms_dummy = true;
+        let multi_satisfied: Result<Vec<SatisfiedConstraint>, Error> = constraints.collect();
+        assert_eq!(
+            multi_satisfied.unwrap(),
+            vec![
+                SatisfiedConstraint::PublicKey {
+                    key: &pks[0],
+                    sig: secp_sigs[0].clone(),
+                    sighash_type: dogecoin::SigHashType::All,
+                },
+                SatisfiedConstraint::PublicKey {
+                    key: &pks[1],
+                    sig: secp_sigs[1].clone(),
+                    sighash_type: dogecoin::SigHashType::All,
+                },
+            ]
+        );
+
+        // Conditionals must be driven by a minimal boolean: a 0x02 push is
+        // "true" to bitcoind's non-minimal IF, but rejected under MINIMALIF
+        let two = [0x02];
+        let mut stack = Stack::from(vec![
+            stack::Element::Push(&der_sigs[0]),
+            stack::Element::Push(&two),
+        ]);
+        let elem = ms_str!("or_i(c:pk_k({}),c:pk_k({}))", pks[0], pks[1]);
+        let mut vfyfn = vfyfn_.clone(); // sigh rust 1.29...
+        let constraints = from_stack(&mut vfyfn, &mut stack, &elem);
+        let or_i_error: Result<Vec<SatisfiedConstraint>, Error> = constraints.collect();
+        match or_i_error {
+            Err(Error::UnexpectedStackElementPush) => {}
+            x => panic!("expected UnexpectedStackElementPush, got {:?}", x),
+        }
+
+        let mut stack = Stack::from(vec![
+            stack::Element::Push(&der_sigs[0]),
+            stack::Element::Push(&two),
+            stack::Element::Push(&der_sigs[2]),
+        ]);
+        let elem = ms_str!(
+            "andor(c:pk_k({}),or_i(c:pk_k({}),c:pk_k({})),c:pk_k({}))",
+            pks[2],
+            pks[0],
+            pks[1],
+            pks[3]
+        );
+        let mut vfyfn = vfyfn_.clone(); // sigh rust 1.29...
+        let constraints = from_stack(&mut vfyfn, &mut stack, &elem);
+        let andor_error: Result<Vec<SatisfiedConstraint>, Error> = constraints.collect();
+        match andor_error {
+            Err(Error::UnexpectedStackElementPush) => {}
+            x => panic!("expected UnexpectedStackElementPush, got {:?}", x),
+        }
+
+        let mut stack = Stack::from(vec![stack::Element::Push(&two)]);
+        let elem = ms_str!("and_v(vdv:older(1000),c:pk_k({}))", pks[0]);
+        let mut vfyfn = vfyfn_.clone(); // sigh rust 1.29...
+        let constraints = from_stack(&mut vfyfn, &mut stack, &elem);
+        let dup_if_error: Result<Vec<SatisfiedConstraint>, Error> = constraints.collect();
+        match dup_if_error {
+            Err(Error::UnexpectedStackElementPush) => {}
+            x => panic!("expected UnexpectedStackElementPush, got {:?}", x),
+        }
+
+        // `j:` checks the size of its input, so a signature push is fine
+        let mut stack = Stack::from(vec![stack::Element::Push(&der_sigs[0])]);
+        let elem = ms_str!("jc:pk_k({})", pks[0]);
+        let mut vfyfn = vfyfn_.clone(); // sigh rust 1.29...
+        let constraints = from_stack(&mut vfyfn, &mut stack, &elem);
+        let non_zero_satisfied: Result<Vec<SatisfiedConstraint>, Error> = constraints.collect();
+        assert_eq!(
+            non_zero_satisfied.unwrap(),
+            vec![SatisfiedConstraint::PublicKey {
+                key: &pks[0],
+                sig: secp_sigs[0].clone(),
+                sighash_type: dogecoin::SigHashType::All,
+            }]
+        );
+    }
+
+    #[test]
+    fn matched_script() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(1);
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!("c:pk_k({})", pks[0]);
+        let script = ms.encode();
+        let empty = dogecoin::Script::new();
+
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let witness = vec![der_sigs[0].clone(), script.to_bytes()];
+        let interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(interpreter.matched_script(), Some(&script));
+        assert_eq!(interpreter.miniscript(), Some(&ms));
+
+        let script_sig = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&der_sigs[0])
+            .push_slice(&script[..])
+            .into_script();
+        let spk = dogecoin::Script::new_p2sh(&script.script_hash());
+        let interpreter = Interpreter::from_txdata(&spk, &script_sig, &[], 0, 0).unwrap();
+        assert_eq!(interpreter.matched_script(), Some(&script));
+
+        let script_sig = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&der_sigs[0])
+            .into_script();
+        let interpreter = Interpreter::from_txdata(&script, &script_sig, &[], 0, 0).unwrap();
+        assert_eq!(interpreter.matched_script(), None);
+        // A bare `pk` is interpreted as a pay-to-pubkey spend
+        assert_eq!(interpreter.miniscript(), None);
+
+        let spk = dogecoin::Script::new_p2pkh(&pks[0].to_pubkeyhash().into());
+        let script_sig = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&der_sigs[0])
+            .push_key(&pks[0])
+            .into_script();
+        let interpreter = Interpreter::from_txdata(&spk, &script_sig, &[], 0, 0).unwrap();
+        assert_eq!(interpreter.matched_script(), None);
+        assert_eq!(interpreter.miniscript(), None);
+    }
+
+    #[test]
+    fn verify_structure() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(2);
+        let empty = dogecoin::Script::new();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("and_v(vc:pk_k({}),older(10))", pks[0]);
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+
+        // A well-formed signature from the wrong key passes
+        let witness = vec![der_sigs[1].clone(), script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 10, 10).unwrap();
+        interpreter.verify_structure().unwrap();
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 10, 10).unwrap();
+        let constraints: Result<Vec<_>, _> = interpreter.iter(|_, _| false).collect();
+        match constraints {
+            Err(Error::InvalidSignature(pk)) => assert_eq!(pk, pks[0]),
+            x => panic!("expected invalid signature, got {:?}", x),
+        }
+
+        // ...but an unmet timelock does not
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 9, 9).unwrap();
+        match interpreter.verify_structure() {
+            Err(Error::RelativeLocktimeNotMet(10)) => {}
+            x => panic!("expected unmet locktime, got {:?}", x),
+        }
+
+        // ...nor a missing signature
+        let witness = vec![script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 10, 10).unwrap();
+        match interpreter.verify_structure() {
+            Err(Error::UnexpectedStackEnd) => {}
+            x => panic!("expected end of stack, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn iter_contributing() {
+        let (pks, der_sigs, secp_sigs, _, _) = setup_keys_sigs(3);
+        let empty = dogecoin::Script::new();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!(
+            "or_b(and_b(c:pk_k({}),sc:pk_k({})),sc:pk_k({}))",
+            pks[0],
+            pks[1],
+            pks[2]
+        );
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        // The first key signs, but the second does not, so only the third
+        // key's branch satisfies the `or_b`
+        let witness = vec![
+            der_sigs[2].clone(),
+            vec![],
+            der_sigs[0].clone(),
+            script.to_bytes(),
+        ];
+
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        let constraints: Result<Vec<_>, _> = interpreter.iter(|_, _| true).collect();
+        assert_eq!(constraints.unwrap().len(), 2);
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(
+            interpreter.iter_contributing(|_, _| true).unwrap(),
+            vec![SatisfiedConstraint::PublicKey {
+                key: &pks[2],
+                sig: secp_sigs[2],
+                sighash_type: dogecoin::SigHashType::All,
+            }]
+        );
+
+        // With both branches satisfied, all constraints contribute
+        let witness = vec![
+            der_sigs[2].clone(),
+            der_sigs[1].clone(),
+            der_sigs[0].clone(),
+            script.to_bytes(),
+        ];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(interpreter.iter_contributing(|_, _| true).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn timelocks_not_met() {
+        let empty = dogecoin::Script::new();
+        let interpret = |ms: &str, age: u32, height: u32| {
+            let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!("{}", ms);
+            let script = ms.encode();
+            let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+            let witness = vec![script.to_bytes()];
+            let mut interpreter =
+                Interpreter::from_txdata(&spk, &empty, &witness, age, height).unwrap();
+            let constraints: Result<Vec<_>, _> = interpreter.iter(|_, _| true).collect();
+            constraints.map(|c| c.len())
+        };
+
+        match interpret("after(1000)", 999, 0) {
+            Err(Error::AbsoluteLocktimeNotMet(1000)) => {}
+            x => panic!("expected unmet absolute locktime, got {:?}", x),
+        }
+        assert_eq!(interpret("after(1000)", 1000, 0).unwrap(), 1);
+        assert_eq!(interpret("after(1000)", 1001, 0).unwrap(), 1);
+
+        match interpret("older(1000)", 0, 999) {
+            Err(Error::RelativeLocktimeNotMet(1000)) => {}
+            x => panic!("expected unmet relative locktime, got {:?}", x),
+        }
+        assert_eq!(interpret("older(1000)", 0, 1000).unwrap(), 1);
+        assert_eq!(interpret("older(1000)", 0, 1001).unwrap(), 1);
+    }
+
+    #[test]
+    fn max_stack_depth() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(2);
+        let empty = dogecoin::Script::new();
+        let wsh_depth = |ms: Miniscript<_, NoChecks>, mut witness: Vec<_>| {
+            let script = ms.encode();
+            let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+            witness.push(script.to_bytes());
+            let interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+            interpreter.max_stack_depth()
+        };
+
+        // The witness script is not counted, but the key pushed by the
+        // script is
+        let ms: Miniscript<dogecoin::PublicKey, _> =
+            ms_str!("or_d(c:pk_k({}),c:pk_k({}))", pks[0], pks[1]);
+        assert_eq!(wsh_depth(ms, vec![der_sigs[1].clone(), vec![]]), 3);
+        // As is the result moved to the altstack while checking B
+        let ms = ms_str!("and_b(c:pk_k({}),ac:pk_k({}))", pks[0], pks[1]);
+        let witness = vec![der_sigs[1].clone(), der_sigs[0].clone()];
+        assert_eq!(wsh_depth(ms, witness), 3);
+        // Dummy, two signatures, k, two keys and n
+        let ms = ms_str!("multi(2,{},{})", pks[0], pks[1]);
+        let witness = vec![vec![], der_sigs[0].clone(), der_sigs[1].clone()];
+        assert_eq!(wsh_depth(ms, witness), 7);
+
+        // Signature, key, its copy and the spent key hash
+        let spk = dogecoin::Script::new_p2pkh(&pks[0].to_pubkeyhash().into());
+        let script_sig = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&der_sigs[0])
+            .push_key(&pks[0])
+            .into_script();
+        let interpreter = Interpreter::from_txdata(&spk, &script_sig, &[], 0, 0).unwrap();
+        assert_eq!(interpreter.max_stack_depth(), 4);
+    }
+
+    #[test]
+    fn iter_borrow() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(1);
+        let empty = dogecoin::Script::new();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!("c:pk_k({})", pks[0]);
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let witness = vec![der_sigs[0].clone(), script.to_bytes()];
+
+        // This only compiles if the iterator's borrow of the interpreter
+        // ends before the transaction data does
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(interpreter.iter(|_, _| true).count(), 1);
+        assert_eq!(interpreter.matched_script(), Some(&script));
+    }
+
+    #[test]
+    fn iter_with_hook() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(2);
+        let empty = dogecoin::Script::new();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("and_v(vc:pk_k({}),c:pk_k({}))", pks[0], pks[1]);
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let witness = vec![der_sigs[1].clone(), der_sigs[0].clone(), script.to_bytes()];
+
+        let mut trace = vec![];
+        {
+            let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+            let constraints: Result<Vec<_>, _> = interpreter
+                .iter_with_hook(
+                    |_, _| true,
+                    |term, stack| trace.push((term.to_string(), stack.len())),
+                )
+                .collect();
+            assert_eq!(constraints.unwrap().len(), 2);
+        }
+        assert_eq!(&trace[0], &(ms.to_string(), 2));
+        // The first key's check sees both signatures, the second only its own
+        assert!(trace.contains(&(format!("pk_k({})", pks[0]), 2)));
+        assert!(trace.contains(&(format!("pk_k({})", pks[1]), 1)));
+    }
+
+    #[test]
+    fn uncompressed_pkh_in_segwit() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(1);
+        let uncompressed = dogecoin::PublicKey {
+            compressed: false,
+            key: pks[0].key,
+        };
+        let empty = dogecoin::Script::new();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("c:pk_h({})", uncompressed.to_pubkeyhash());
+        let script = ms.encode();
+
+        // The hash matches, but segwit scripts only allow compressed keys
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let witness = vec![der_sigs[0].clone(), uncompressed.to_bytes(), script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        match interpreter.verify_structure() {
+            Err(Error::UncompressedPubkey) => {}
+            x => panic!("expected uncompressed pubkey error, got {:?}", x),
+        }
+
+        // The same spend is fine under p2sh
+        let script_sig = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&der_sigs[0])
+            .push_slice(&uncompressed.to_bytes())
+            .push_slice(&script[..])
+            .into_script();
+        let spk = dogecoin::Script::new_p2sh(&script.script_hash());
+        let mut interpreter = Interpreter::from_txdata(&spk, &script_sig, &[], 0, 0).unwrap();
+        interpreter.verify_structure().unwrap();
+    }
+
+    #[test]
+    fn script_stats() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(2);
+        let empty = dogecoin::Script::new();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("and_v(vc:pk_k({}),c:pk_h({}))", pks[0], pks[1].to_pubkeyhash());
+        let script = ms.encode();
+
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let witness = vec![
+            der_sigs[1].clone(),
+            pks[1].to_bytes(),
+            der_sigs[0].clone(),
+            script.to_bytes(),
+        ];
+        let interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        // CHECKSIGVERIFY DUP HASH160 EQUALVERIFY CHECKSIG
+        assert_eq!(
+            interpreter.script_stats(),
+            Some(ScriptStats {
+                size: script.len(),
+                op_count: 5,
+            })
+        );
+
+        // The keys of a `multi` count as in check_consensus_limits
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("multi(1,{},{})", pks[0], pks[1]);
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let witness = vec![vec![], der_sigs[0].clone(), script.to_bytes()];
+        let interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(
+            interpreter.script_stats(),
+            Some(ScriptStats {
+                size: script.len(),
+                op_count: 3,
+            })
+        );
+
+        let spk = dogecoin::Script::new_p2pkh(&pks[0].to_pubkeyhash().into());
+        let script_sig = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&der_sigs[0])
+            .push_key(&pks[0])
+            .into_script();
+        let interpreter = Interpreter::from_txdata(&spk, &script_sig, &[], 0, 0).unwrap();
+        assert_eq!(interpreter.script_stats(), None);
+    }
+
+    #[test]
+    fn weight_units() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(1);
+        let empty = dogecoin::Script::new();
+
+        let spk = dogecoin::Script::new_v0_wpkh(&pks[0].to_pubkeyhash().into());
+        let witness = vec![der_sigs[0].clone(), pks[0].to_bytes()];
+        let interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        // empty scriptSig, plus item count and two length-prefixed items
+        assert_eq!(
+            interpreter.weight_units(),
+            4 + 1 + 1 + der_sigs[0].len() + 1 + 33
+        );
+
+        let spk = dogecoin::Script::new_p2pkh(&pks[0].to_pubkeyhash().into());
+        let script_sig = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&der_sigs[0])
+            .push_key(&pks[0])
+            .into_script();
+        let interpreter = Interpreter::from_txdata(&spk, &script_sig, &[], 0, 0).unwrap();
+        assert_eq!(interpreter.weight_units(), 4 * (1 + script_sig.len()));
+    }
+
+    #[test]
+    fn preimage_consistency() {
+        let secret = [0xab; 32];
+        let other = [0xcd; 32];
+        let sha = sha256::Hash::hash(&secret);
+        let empty = dogecoin::Script::new();
+
+        let check = |ms: &Miniscript<dogecoin::PublicKey, NoChecks>, mut wit: Vec<Vec<u8>>| {
+            let script = ms.encode();
+            let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+            wit.push(script.to_bytes());
+            let mut interpreter = Interpreter::from_txdata(&spk, &empty, &wit, 0, 0).unwrap();
+            interpreter.check_preimage_consistency(|_, _| true)
+        };
+
+        // Both hashlocks commit to the same secret
+        let ms = ms_str!(
+            "or_i(sha256({}),hash160({}))",
+            sha,
+            hash160::Hash::hash(&secret)
+        );
+        assert!(check(&ms, vec![secret.to_vec(), vec![1]]).is_ok());
+        assert!(check(&ms, vec![secret.to_vec(), vec![]]).is_ok());
+
+        // The hash160 lock commits to a different secret
+        let ms = ms_str!(
+            "or_i(sha256({}),hash160({}))",
+            sha,
+            hash160::Hash::hash(&other)
+        );
+        match check(&ms, vec![secret.to_vec(), vec![1]]) {
+            Err(Error::InconsistentPreimage(ref p)) if *p == secret.to_vec() => {}
+            x => panic!("expected InconsistentPreimage, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn owned_txdata_from_hex() {
+        let (pks, der_sigs, secp_sigs, _, _) = setup_keys_sigs(1);
+        let spk = dogecoin::Script::new_v0_wpkh(&pks[0].to_pubkeyhash().into());
+        let data = OwnedTxData::from_hex(
+            &spk.to_bytes().to_hex(),
+            "",
+            &[&der_sigs[0].to_hex(), &pks[0].to_bytes().to_hex()],
+        )
+        .unwrap();
+        assert_eq!(data.spk, spk);
+        assert_eq!(data.witness, vec![der_sigs[0].clone(), pks[0].to_bytes()]);
+
+        let mut interpreter = data.interpreter(0, 0).unwrap();
+        assert_eq!(interpreter.inferred_descriptor_string(), format!("wpkh({})", pks[0]));
+        let constraints: Result<Vec<_>, _> = interpreter.iter(|_, _| true).collect();
+        assert_eq!(
+            constraints.unwrap(),
+            vec![SatisfiedConstraint::PublicKey {
+                key: &pks[0],
+                sig: secp_sigs[0],
+                sighash_type: dogecoin::SigHashType::All,
+            }]
+        );
+
+        match OwnedTxData::from_hex("zz", "", &[]) {
+            Err(Error::HexParse(_)) => {}
+            x => panic!("expected hex error, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn owned_interpreter() {
+        let (pks, der_sigs, secp_sigs, _, _) = setup_keys_sigs(2);
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("and_v(vc:pk_k({}),c:pk_h({}))", pks[0], pks[1].to_pubkeyhash());
+        let script = ms.encode();
+
+        let owned = {
+            // The interpreter outlives the buffers it was built from
+            let witness = vec![
+                der_sigs[1].clone(),
+                pks[1].to_bytes(),
+                der_sigs[0].clone(),
+                script.to_bytes(),
+            ];
+            let txdata = OwnedTxData {
+                spk: dogecoin::Script::new_v0_wsh(&script.wscript_hash()),
+                script_sig: dogecoin::Script::new(),
+                witness: witness,
+            };
+            OwnedInterpreter::new(txdata, 0, 0).unwrap()
+        };
+        assert!(owned.is_satisfied(|_, _| true));
+        assert!(!owned.is_satisfied(|_, _| false));
+        assert_eq!(owned.signed_keys(|_, _| true).unwrap(), vec![pks[0], pks[1]]);
+        assert!(owned.signed_keys(|pk, _| *pk == pks[0]).is_err());
+        assert_eq!(owned.signature_count(|_, _| true).unwrap(), 2);
+        assert_eq!(owned.interpreter().signature_count(|_, _| true).unwrap(), 2);
+        assert!(owned.signature_count(|_, _| false).is_err());
+
+        let mut interpreter = owned.interpreter();
+        let constraints: Result<Vec<_>, _> = interpreter.iter(|_, _| true).collect();
+        assert_eq!(
+            constraints.unwrap()[0],
+            SatisfiedConstraint::PublicKey {
+                key: &pks[0],
+                sig: secp_sigs[0],
+                sighash_type: dogecoin::SigHashType::All,
+            }
+        );
+
+        let bad = OwnedTxData {
+            spk: dogecoin::Script::new_v0_wsh(&script.wscript_hash()),
+            script_sig: dogecoin::Script::new(),
+            witness: vec![],
+        };
+        assert!(OwnedInterpreter::new(bad, 0, 0).is_err());
+    }
+
+    #[test]
+    fn signature_form() {
+        let (pks, _, _, _, _) = setup_keys_sigs(1);
+        let mut compact = [0x11; 64];
+        compact[0] = 0x7f;
+        let low_r = secp256k1::Signature::from_compact(&compact).unwrap();
+        compact[0] = 0x80;
+        let high_r = secp256k1::Signature::from_compact(&compact).unwrap();
+
+        let constraint = SatisfiedConstraint::PublicKey {
+            key: &pks[0],
+            sig: low_r,
+            sighash_type: dogecoin::SigHashType::All,
+        };
+        assert_eq!(
+            constraint.signature_form(),
+            Some(SignatureForm {
+                der_len: 70,
+                low_r: true,
+            })
+        );
+        let constraint = SatisfiedConstraint::PublicKeyHash {
+            keyhash: &pks[0].to_pubkeyhash().into(),
+            key: pks[0],
+            sig: high_r,
+            sighash_type: dogecoin::SigHashType::All,
+        };
+        assert_eq!(
+            constraint.signature_form(),
+            Some(SignatureForm {
+                der_len: 71,
+                low_r: false,
+            })
+        );
+        let constraint = SatisfiedConstraint::RelativeTimeLock { time: &10 };
+        assert_eq!(constraint.signature_form(), None);
+    }
+
+    #[test]
+    fn inferred_address() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(1);
+        let empty = dogecoin::Script::new();
+        let network = dogecoin::Network::Bitcoin;
+
+        // pkh
+        let spk = dogecoin::Script::new_p2pkh(&pks[0].pubkey_hash());
+        let script_sig = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&der_sigs[0])
+            .push_key(&pks[0])
+            .into_script();
+        let interpreter = Interpreter::from_txdata(&spk, &script_sig, &[], 0, 0).unwrap();
+        let addr = interpreter.inferred_address(network).unwrap();
+        assert_eq!(addr, dogecoin::Address::p2pkh(&pks[0], network));
+        assert_eq!(addr.script_pubkey(), spk);
+
+        // wsh
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!("c:pk_k({})", pks[0]);
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let witness = vec![der_sigs[0].clone(), script.to_bytes()];
+        let interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        let addr = interpreter.inferred_address(network).unwrap();
+        assert_eq!(addr.script_pubkey(), spk);
+
+        // bare scripts have no address
+        let spk = script;
+        let script_sig = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&der_sigs[0])
+            .into_script();
+        let interpreter = Interpreter::from_txdata(&spk, &script_sig, &[], 0, 0).unwrap();
+        assert_eq!(interpreter.inferred_address(network), None);
+    }
+
+    #[test]
+    fn verify_package() {
+        let (pks, _, _, _, _) = setup_keys_sigs(1);
+        let secp = secp256k1::Secp256k1::new();
+        let mut sk = [0; 32];
+        sk[0] = 1;
+        let sk = secp256k1::SecretKey::from_slice(&sk[..]).unwrap();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!("c:pk_k({})", pks[0]);
+        let spk = ms.encode();
+        let sign = |tx: &dogecoin::Transaction| {
+            let hash = tx.signature_hash(0, &spk, 1);
+            let sig = secp.sign(&secp256k1::Message::from_slice(&hash[..]).unwrap(), &sk);
+            let mut sigser = sig.serialize_der().to_vec();
+            sigser.push(0x01);
+            dogecoin::blockdata::script::Builder::new()
+                .push_slice(&sigser)
+                .into_script()
+        };
+        let spend = |outpoint, value| dogecoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![dogecoin::TxIn {
+                previous_output: outpoint,
+                script_sig: dogecoin::Script::new(),
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            output: vec![dogecoin::TxOut {
+                value: value,
+                script_pubkey: spk.clone(),
+            }],
+        };
+
+        let funding = dogecoin::OutPoint::new(dogecoin::Txid::hash(b"funding"), 0);
+        let mut prevouts = HashMap::new();
+        prevouts.insert(
+            funding,
+            dogecoin::TxOut {
+                value: 100_000,
+                script_pubkey: spk.clone(),
+            },
+        );
+        let mut parent = spend(funding, 90_000);
+        parent.input[0].script_sig = sign(&parent);
+        let mut child = spend(dogecoin::OutPoint::new(parent.txid(), 0), 50_000);
+        child.input[0].script_sig = sign(&child);
+
+        let package = vec![parent.clone(), child.clone()];
+        assert!(super::verify_package(&package, &prevouts, &secp).is_ok());
+
+        // The child's prevout is only known once the parent has been seen
+        let package = vec![child.clone(), parent.clone()];
+        match super::verify_package(&package, &prevouts, &secp) {
+            Err((0, 0, Error::MissingPrevout(outpoint))) => {
+                assert_eq!(outpoint.txid, parent.txid())
+            }
+            x => panic!("expected missing prevout, got {:?}", x),
+        }
+
+        let mut bad_child = child.clone();
+        bad_child.input[0].script_sig = parent.input[0].script_sig.clone();
+        let package = vec![parent.clone(), bad_child];
+        match super::verify_package(&package, &prevouts, &secp) {
+            Err((1, 0, Error::PkEvaluationError(pk))) => assert_eq!(pk, pks[0]),
+            x => panic!("expected failed signature check, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn push_size_exceeded() {
+        let empty = dogecoin::Script::new();
+        let preimage = vec![0xab; 521];
+        let hash = sha256::Hash::hash(&preimage);
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!("sha256({})", hash);
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+
+        let witness = vec![preimage, script.to_bytes()];
+        match Interpreter::from_txdata(&spk, &empty, &witness, 0, 0) {
+            Err(Error::PushSizeExceeded(521)) => {}
+            Err(e) => panic!("expected oversized push, got {:?}", e),
+            Ok(_) => panic!("expected oversized push"),
+        }
+
+        // 520 bytes is fine, though it is still no preimage of this hash
+        let witness = vec![vec![0xab; 520], script.to_bytes()];
+        assert!(Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).is_ok());
+    }
+
+    #[test]
+    fn rbf_compatible() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(1);
+        let rbf_compatible = |ms: &str, version: i32, sequence: u32, input_idx: usize| {
+            let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+                Miniscript::from_str_insane(&ms.replace("K", &pks[0].to_string())).unwrap();
+            let script = ms.encode();
+            let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+            let tx = dogecoin::Transaction {
+                version: version,
+                lock_time: 0,
+                input: vec![dogecoin::TxIn {
+                    previous_output: dogecoin::OutPoint::new(dogecoin::Txid::hash(b"a"), 0),
+                    script_sig: dogecoin::Script::new(),
+                    sequence: sequence,
+                    witness: vec![der_sigs[0].clone(), script.to_bytes()],
+                }],
+                output: vec![],
+            };
+            Interpreter::from_tx(&spk, &tx, 0)
+                .unwrap()
+                .rbf_compatible(&tx, input_idx)
+        };
+
+        assert!(rbf_compatible("pk(K)", 1, 0xffffffff, 0));
+        assert!(rbf_compatible("and_v(v:pk(K),older(10))", 2, 10, 0));
+        // The timelock could be met by a sequence number with the disable
+        // flag clear, though it is not met at the moment
+        assert!(rbf_compatible("and_v(v:pk(K),older(10))", 2, 0xffffffff, 0));
+        assert!(!rbf_compatible("and_v(v:pk(K),older(10))", 1, 0xffffffff, 0));
+        // The units of the timelocks conflict
+        let mixed = "and_v(v:pk(K),and_v(v:older(10),older(4194305)))";
+        assert!(!rbf_compatible(mixed, 2, 0xffffffff, 0));
+        // The timelock is on a branch which was not taken
+        assert!(rbf_compatible("or_d(pk(K),older(10))", 1, 0xffffffff, 0));
+        // The transaction has no such input
+        assert!(!rbf_compatible("pk(K)", 2, 0, 1));
+    }
+
+    #[test]
+    fn median_time_past() {
+        let empty = dogecoin::Script::new();
+        let interpret = |older: u32, height: u32, elapsed: Option<u32>| {
+            let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!("older({})", older);
+            let script = ms.encode();
+            let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+            let witness = vec![script.to_bytes()];
+            let mut interpreter =
+                Interpreter::from_txdata(&spk, &empty, &witness, 0, height).unwrap();
+            if let Some(elapsed) = elapsed {
+                interpreter.set_median_time_past(elapsed);
+            }
+            interpreter.verify_structure().is_ok()
+        };
+
+        // Block-based locks are unaffected
+        assert!(interpret(10, 10, None));
+        assert!(interpret(10, 10, Some(0)));
+        assert!(!interpret(10, 9, Some(1_000_000)));
+
+        // Time-based locks, here of 2 * 512 seconds, need both the sequence
+        // and, once it is set, the elapsed time to reach them
+        let time_lock = SEQUENCE_LOCKTIME_TYPE_FLAG | 2;
+        assert!(interpret(time_lock, time_lock, None));
+        assert!(!interpret(time_lock, time_lock, Some(1023)));
+        assert!(interpret(time_lock, time_lock, Some(1024)));
+        assert!(!interpret(time_lock, 0, Some(1024)));
+        assert!(!interpret(time_lock, time_lock - 1, Some(1_000_000)));
+    }
+
+    #[test]
+    fn from_tx() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(1);
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!("c:pk_k({})", pks[0]);
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let mut tx = dogecoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![dogecoin::TxIn {
+                previous_output: dogecoin::OutPoint::null(),
+                script_sig: dogecoin::Script::from(vec![0x01, 0x02]),
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            output: vec![],
+        };
+        match Interpreter::from_tx(&spk, &tx, 0) {
+            Err(Error::CoinbaseInput) => {}
+            Err(e) => panic!("expected coinbase input, got {:?}", e),
+            Ok(_) => panic!("expected coinbase input"),
+        }
+
+        tx.input[0].previous_output = dogecoin::OutPoint::new(dogecoin::Txid::hash(b"funding"), 0);
+        tx.input[0].script_sig = dogecoin::Script::new();
+        tx.input[0].witness = vec![der_sigs[0].clone(), script.to_bytes()];
+        let interpreter = Interpreter::from_tx(&spk, &tx, 0).unwrap();
+        assert_eq!(interpreter.matched_script(), Some(&script));
+
+        match Interpreter::from_tx(&spk, &tx, 1) {
+            Err(Error::InputIndexOutOfRange(1)) => {}
+            Err(e) => panic!("expected input index out of range, got {:?}", e),
+            Ok(_) => panic!("expected input index out of range"),
+        }
+    }
+
+    #[test]
+    fn sighash_verify_with_cache() {
+        let (pks, _, _, _, _) = setup_keys_sigs(1);
+        let secp = secp256k1::Secp256k1::new();
+        let mut sk = [0; 32];
+        sk[0] = 1;
+        let sk = secp256k1::SecretKey::from_slice(&sk[..]).unwrap();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!("c:pk_k({})", pks[0]);
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+
+        let mut tx = dogecoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        for i in 0..2 {
+            tx.input.push(dogecoin::TxIn {
+                previous_output: dogecoin::OutPoint::new(dogecoin::Txid::hash(&[i]), 0),
+                script_sig: dogecoin::Script::new(),
+                sequence: 0xffffffff,
+                witness: vec![],
+            });
+        }
+        let sigs: Vec<_> = (0..2)
+            .map(|i| {
+                let hash = bip143::SigHashCache::new(&tx).signature_hash(
+                    i,
+                    &script,
+                    10_000,
+                    dogecoin::SigHashType::All,
+                );
+                let msg = secp256k1::Message::from_slice(&hash[..]).unwrap();
+                let mut sigser = secp.sign(&msg, &sk).serialize_der().to_vec();
+                sigser.push(0x01);
+                sigser
+            })
+            .collect();
+        // Lastly, the second input is given the first input's signature
+        let spends = [(0, &sigs[0]), (1, &sigs[1]), (1, &sigs[0])];
+
+        let empty = dogecoin::Script::new();
+        let mut cache = TxSigHashCache::new(&tx);
+        assert_eq!(cache.transaction(), &tx);
+        let mut results = vec![];
+        for &(i, sig) in &spends {
+            let witness = vec![sig.clone(), script.to_bytes()];
+            let mut interpreter =
+                Interpreter::from_txdata(&spk, &empty, &witness, 0, 0xffffffff).unwrap();
+            let verify_sig = interpreter.sighash_verify_with_cache(&secp, &mut cache, i, 10_000);
+            results.push(interpreter.verify(verify_sig).is_ok());
+        }
+        assert_eq!(results, vec![true, true, false]);
+    }
+
+    #[test]
+    fn with_raw() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(2);
+        let preimage = vec![0x42; 32];
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!(
+            "and_v(vc:pk_k({}),and_v(vc:pk_h({}),and_v(v:sha256({}),after(10))))",
+            pks[0],
+            pks[1].to_pubkeyhash(),
+            sha256::Hash::hash(&preimage)
+        );
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let empty = dogecoin::Script::new();
+        let witness = vec![
+            preimage.clone(),
+            der_sigs[1].clone(),
+            pks[1].to_bytes(),
+            der_sigs[0].clone(),
+            script.to_bytes(),
+        ];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 10, 0).unwrap();
+        let raw: Result<Vec<_>, _> = interpreter
+            .iter(|_, _| true)
+            .with_raw()
+            .map(|res| res.map(|(_, raw)| raw))
+            .collect();
+        assert_eq!(
+            raw.unwrap(),
+            vec![&der_sigs[0][..], &der_sigs[1][..], &preimage[..], &[][..]]
+        );
+    }
+
+    #[test]
+    fn verify_batch() {
+        let secp = secp256k1::Secp256k1::new();
+        let sks: Vec<_> = (1..4)
+            .map(|i| secp256k1::SecretKey::from_slice(&[i; 32][..]).unwrap())
+            .collect();
+        let pks: Vec<_> = sks
+            .iter()
+            .map(|sk| dogecoin::PublicKey {
+                key: secp256k1::PublicKey::from_secret_key(&secp, sk),
+                compressed: true,
+            })
+            .collect();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("multi(2,{},{},{})", pks[0], pks[1], pks[2]);
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let tx = dogecoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![dogecoin::TxIn {
+                previous_output: dogecoin::OutPoint::new(dogecoin::Txid::hash(b"a"), 0),
+                script_sig: dogecoin::Script::new(),
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            output: vec![],
+        };
+        let hash = bip143::SigHashCache::new(&tx).signature_hash(
+            0,
+            &script,
+            10_000,
+            dogecoin::SigHashType::All,
+        );
+        let msg = secp256k1::Message::from_slice(&hash[..]).unwrap();
+        let sigs: Vec<_> = sks.iter().map(|sk| secp.sign(&msg, sk)).collect();
+        let sigser = |i: usize| {
+            let mut ret = sigs[i].serialize_der().to_vec();
+            ret.push(0x01);
+            ret
+        };
+        let empty = dogecoin::Script::new();
+        let constraint = |i: usize| SatisfiedConstraint::PublicKey {
+            key: &pks[i],
+            sig: sigs[i],
+            sighash_type: dogecoin::SigHashType::All,
+        };
+
+        let witness = vec![vec![], sigser(0), sigser(1), script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(
+            interpreter.verify_batch(&secp, &tx, 0, 10_000).unwrap(),
+            vec![constraint(1), constraint(0)]
+        );
+
+        // Assuming signatures valid pairs the first with the second key, so
+        // this is found by checking them again as they are reached
+        let witness = vec![vec![], sigser(0), sigser(2), script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(
+            interpreter.verify_batch(&secp, &tx, 0, 10_000).unwrap(),
+            vec![constraint(2), constraint(0)]
+        );
+
+        // A signature of the wrong message fails
+        let witness = vec![vec![], sigser(0), sigser(1), script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert!(interpreter.verify_batch(&secp, &tx, 0, 20_000).is_err());
+    }
+
+    #[test]
+    fn spk_type() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(1);
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!("c:pk_k({})", pks[0]);
+        let script = ms.encode();
+        let empty = dogecoin::Script::new();
+        let spk_type = |spk: &dogecoin::Script, script_sig: &dogecoin::Script, witness| {
+            let interpreter = Interpreter::from_txdata(spk, script_sig, witness, 0, 0).unwrap();
+            (interpreter.spk_type(), interpreter.is_legacy())
+        };
+
+        let sig_only = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&der_sigs[0])
+            .into_script();
+        let sig_pk = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&der_sigs[0])
+            .push_key(&pks[0])
+            .into_script();
+        let sig_script = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&der_sigs[0])
+            .push_slice(&script[..])
+            .into_script();
+        let pk_spk = dogecoin::blockdata::script::Builder::new()
+            .push_key(&pks[0])
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script();
+        let sh_spk = dogecoin::Script::new_p2sh(&script.script_hash());
+        let wsh_spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let wpkh_spk = dogecoin::Script::new_v0_wpkh(&pks[0].wpubkey_hash().unwrap());
+        let pkh_spk = dogecoin::Script::new_p2pkh(&pks[0].pubkey_hash());
+        let shwsh_spk = dogecoin::Script::new_p2sh(&wsh_spk.script_hash());
+        let shwsh_sig = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&wsh_spk[..])
+            .into_script();
+        let shwpkh_spk = dogecoin::Script::new_p2sh(&wpkh_spk.script_hash());
+        let shwpkh_sig = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&wpkh_spk[..])
+            .into_script();
+        let wsh_witness = vec![der_sigs[0].clone(), script.to_bytes()];
+        let wpkh_witness = vec![der_sigs[0].clone(), pks[0].to_bytes()];
+
+        assert_eq!(spk_type(&pk_spk, &sig_only, &[]), (SpkType::Pk, true));
+        assert_eq!(spk_type(&pkh_spk, &sig_pk, &[]), (SpkType::Pkh, true));
+        assert_eq!(spk_type(&wpkh_spk, &empty, &wpkh_witness), (SpkType::Wpkh, false));
+        assert_eq!(
+            spk_type(&shwpkh_spk, &shwpkh_sig, &wpkh_witness),
+            (SpkType::ShWpkh, false)
+        );
+        // `c:pk_k` is a bare public key, so some other script is needed
+        let multi: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!("multi(1,{})", pks[0]);
+        let multi_sig = dogecoin::blockdata::script::Builder::new()
+            .push_int(0)
+            .push_slice(&der_sigs[0])
+            .into_script();
+        assert_eq!(spk_type(&multi.encode(), &multi_sig, &[]), (SpkType::Bare, true));
+        assert_eq!(spk_type(&sh_spk, &sig_script, &[]), (SpkType::Sh, true));
+        assert_eq!(spk_type(&wsh_spk, &empty, &wsh_witness), (SpkType::Wsh, false));
+        assert_eq!(
+            spk_type(&shwsh_spk, &shwsh_sig, &wsh_witness),
+            (SpkType::ShWsh, false)
+        );
+    }
+
+    #[test]
+    fn multi_unsigned_keys() {
+        let (pks, _, secp_sigs, sighash, secp) = setup_keys_sigs(3);
+        let vfyfn =
+            |pk: &dogecoin::PublicKey, (sig, _)| secp.verify(&sighash, &sig, &pk.key).is_ok();
+        let der_sig = |i: usize| {
+            let mut sigser = secp_sigs[i].serialize_der().to_vec();
+            sigser.push(0x01);
+            sigser
+        };
+        let empty = dogecoin::Script::new();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("multi(2,{},{},{})", pks[0], pks[1], pks[2]);
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+
+        let witness = vec![vec![], der_sig(0), der_sig(2), script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(
+            interpreter.multi_unsigned_keys(&vfyfn).unwrap(),
+            vec![vec![pks[1]]]
+        );
+
+        let witness = vec![vec![], der_sig(1), der_sig(2), script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(
+            interpreter.multi_unsigned_keys(&vfyfn).unwrap(),
+            vec![vec![pks[0]]]
+        );
+
+        // A failed spend reports its error
+        let witness = vec![vec![], der_sig(2), der_sig(1), script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert!(interpreter.multi_unsigned_keys(&vfyfn).is_err());
+    }
+
+    #[test]
+    fn sighash_coverage() {
+        let (pks, _, secp_sigs, sighash, secp) = setup_keys_sigs(2);
+        let vfyfn =
+            |pk: &dogecoin::PublicKey, (sig, _)| secp.verify(&sighash, &sig, &pk.key).is_ok();
+        let der_sig = |i: usize, ty: dogecoin::SigHashType| {
+            let mut sigser = secp_sigs[i].serialize_der().to_vec();
+            sigser.push(ty.as_u32() as u8);
+            sigser
+        };
+        let empty = dogecoin::Script::new();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("and_v(vc:pk_k({}),c:pk_k({}))", pks[0], pks[1]);
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+
+        let witness = vec![
+            der_sig(1, dogecoin::SigHashType::SinglePlusAnyoneCanPay),
+            der_sig(0, dogecoin::SigHashType::All),
+            script.to_bytes(),
+        ];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        let coverage = interpreter.sighash_coverage(&vfyfn).unwrap();
+        assert_eq!(coverage.len(), 2);
+        assert_eq!(coverage[0].0, pks[0]);
+        assert!(coverage[0].1.is_complete());
+        assert_eq!(coverage[1].0, pks[1]);
+        assert_eq!(
+            coverage[1].1,
+            SigHashCoverage {
+                sighash_type: dogecoin::SigHashType::SinglePlusAnyoneCanPay,
+                all_inputs: false,
+                outputs: OutputCoverage::Single,
+            }
+        );
+        assert!(!coverage[1].1.is_complete());
+    }
+
+    #[test]
+    fn stack_snapshot() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(1);
+        let empty = dogecoin::Script::new();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("or_d(c:pk_k({}),sha256({}))", pks[0], sha256::Hash::hash(&[0; 32]));
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+
+        let stack = PublicStack::new(vec![
+            WitnessStackElement::Push(vec![0; 32]),
+            WitnessStackElement::Dissatisfied,
+        ]);
+        let mut witness = stack.to_witness();
+        assert_eq!(PublicStack::from_witness(&witness), stack);
+        witness.push(script.to_bytes());
+
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(interpreter.stack_snapshot(), stack);
+        interpreter.verify_structure().unwrap();
+        assert!(interpreter.stack_snapshot().elements().is_empty());
+
+        let witness = vec![der_sigs[0].clone(), vec![1], script.to_bytes()];
+        let interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(
+            interpreter.stack_snapshot().elements(),
+            &[
+                WitnessStackElement::Push(der_sigs[0].clone()),
+                WitnessStackElement::Satisfied,
+            ]
+        );
+    }
+
+    #[test]
+    fn transaction_report() {
+        let (pks, _, _, _, _) = setup_keys_sigs(1);
+        let secp = secp256k1::Secp256k1::new();
+        let mut sk = [0; 32];
+        sk[0] = 1;
+        let sk = secp256k1::SecretKey::from_slice(&sk[..]).unwrap();
+        let preimage = [0x42; 32];
+        let ms_older: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("and_v(vc:pk_k({}),older(10))", pks[0]);
+        let ms_hash: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!(
+            "and_v(vc:pk_k({}),sha256({}))",
+            pks[0],
+            sha256::Hash::hash(&preimage)
+        );
+        let spks = [ms_older.encode(), ms_hash.encode()];
+
+        let mut prevouts = HashMap::new();
+        let mut tx = dogecoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        for (i, spk) in spks.iter().enumerate() {
+            let outpoint = dogecoin::OutPoint::new(dogecoin::Txid::hash(&[i as u8]), 0);
+            prevouts.insert(
+                outpoint,
+                dogecoin::TxOut {
+                    value: 10_000,
+                    script_pubkey: spk.clone(),
+                },
+            );
+            tx.input.push(dogecoin::TxIn {
+                previous_output: outpoint,
+                script_sig: dogecoin::Script::new(),
+                sequence: 10,
+                witness: vec![],
+            });
+        }
+        let sign = |tx: &dogecoin::Transaction, i: usize| {
+            let hash = tx.signature_hash(i, &spks[i], 1);
+            let sig = secp.sign(&secp256k1::Message::from_slice(&hash[..]).unwrap(), &sk);
+            let mut sigser = sig.serialize_der().to_vec();
+            sigser.push(0x01);
+            sigser
+        };
+        let sig0 = sign(&tx, 0);
+        let sig1 = sign(&tx, 1);
+        tx.input[0].script_sig = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&sig0)
+            .into_script();
+        tx.input[1].script_sig = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&preimage)
+            .push_slice(&sig1)
+            .into_script();
+
+        assert_eq!(
+            super::transaction_report(&tx, &prevouts, &secp).unwrap(),
+            TransactionReport {
+                sig_count: 2,
+                signed_keys: vec![pks[0]],
+                preimages: vec![preimage.to_vec()],
+                max_absolute_timelock: None,
+                max_relative_timelock: Some(10),
+            }
+        );
+
+        // The first input's signature is not valid for the second
+        tx.input[1].script_sig = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&preimage)
+            .push_slice(&sig0)
+            .into_script();
+        match super::transaction_report(&tx, &prevouts, &secp) {
+            Err((1, Error::InvalidSignature(pk))) => assert_eq!(pk, pks[0]),
+            x => panic!("expected invalid signature, got {:?}", x),
+        }
+        prevouts.clear();
+        match super::transaction_report(&tx, &prevouts, &secp) {
+            Err((0, Error::MissingPrevout(_))) => {}
+            x => panic!("expected missing prevout, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn block_interpreter() {
+        let (pks, _, _, _, _) = setup_keys_sigs(1);
+        let secp = secp256k1::Secp256k1::new();
+        let mut sk = [0; 32];
+        sk[0] = 1;
+        let sk = secp256k1::SecretKey::from_slice(&sk[..]).unwrap();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("and_v(vc:pk_k({}),older(10))", pks[0]);
+        let spk = ms.encode();
+
+        let coinbase = dogecoin::Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![dogecoin::TxIn {
+                previous_output: dogecoin::OutPoint::null(),
+                script_sig: dogecoin::Script::new(),
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            output: vec![],
+        };
+        let known = dogecoin::OutPoint::new(dogecoin::Txid::hash(b"known"), 0);
+        let unknown = dogecoin::OutPoint::new(dogecoin::Txid::hash(b"unknown"), 0);
+        let mut tx = dogecoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![],
+        };
+        for outpoint in &[unknown, known, known] {
+            tx.input.push(dogecoin::TxIn {
+                previous_output: *outpoint,
+                script_sig: dogecoin::Script::new(),
+                sequence: 10,
+                witness: vec![],
+            });
+        }
+        let hash = tx.signature_hash(1, &spk, 1);
+        let sig = secp.sign(&secp256k1::Message::from_slice(&hash[..]).unwrap(), &sk);
+        let mut sigser = sig.serialize_der().to_vec();
+        sigser.push(0x01);
+        // Valid for the second input only
+        for txin in &mut tx.input {
+            txin.script_sig = dogecoin::blockdata::script::Builder::new()
+                .push_slice(&sigser)
+                .into_script();
+        }
+        let block = dogecoin::Block {
+            header: dogecoin::BlockHeader {
+                version: 1,
+                prev_blockhash: Default::default(),
+                merkle_root: Default::default(),
+                time: 0,
+                bits: 0,
+                nonce: 0,
+            },
+            txdata: vec![coinbase, tx.clone()],
+        };
+
+        let resolver = |outpoint: &dogecoin::OutPoint| {
+            if *outpoint == known {
+                Some((spk.clone(), 10_000))
+            } else {
+                None
+            }
+        };
+        let results: Vec<_> = BlockInterpreter::new(&block, resolver, &secp).collect();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, tx.txid());
+        assert_eq!(results[0].1, 1);
+        let summary = results[0].2.as_ref().unwrap();
+        assert_eq!(summary.value, 10_000);
+        assert_eq!(summary.report.signed_keys, vec![pks[0]]);
+        assert_eq!(summary.report.max_relative_timelock, Some(10));
+        assert_eq!(results[1].1, 2);
+        match results[1].2 {
+            Err(Error::InvalidSignature(pk)) => assert_eq!(pk, pks[0]),
+            ref x => panic!("expected invalid signature, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn public_keys() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(4);
+        let empty = dogecoin::Script::new();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!(
+            "and_v(vc:pk_k({}),or_d(c:pk_h({}),and_v(vc:pk_h({}),multi(1,{},{}))))",
+            pks[0],
+            pks[1].to_pubkeyhash(),
+            pks[2].to_pubkeyhash(),
+            pks[3],
+            pks[0]
+        );
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        // Only the first `pkh` key is revealed
+        let witness = vec![
+            der_sigs[1].clone(),
+            pks[1].to_bytes(),
+            der_sigs[0].clone(),
+            script.to_bytes(),
+        ];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(interpreter.public_keys(), vec![pks[0], pks[1], pks[3]]);
+        interpreter.verify_structure().unwrap();
+
+        let spk = dogecoin::Script::new_v0_wpkh(&pks[0].wpubkey_hash().unwrap());
+        let witness = vec![der_sigs[0].clone(), pks[0].to_bytes()];
+        let interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(interpreter.public_keys(), vec![pks[0]]);
+    }
+
+    #[test]
+    fn verify() {
+        let (pks, der_sigs, _, sighash, secp) = setup_keys_sigs(4);
+        let vfyfn =
+            |pk: &dogecoin::PublicKey, (sig, _)| secp.verify(&sighash, &sig, &pk.key).is_ok();
+        let empty = dogecoin::Script::new();
+
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("and_v(vc:pk_k({}),c:pk_k({}))", pks[0], pks[1]);
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let witness = vec![der_sigs[1].clone(), der_sigs[0].clone(), script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(interpreter.verify(vfyfn).unwrap(), true);
+
+        // The second signature is made by a key not in the multisig
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("multi(2,{},{})", pks[0], pks[1]);
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let witness = vec![
+            vec![],
+            der_sigs[0].clone(),
+            der_sigs[2].clone(),
+            script.to_bytes(),
+        ];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert!(interpreter.verify(vfyfn).is_err());
+        let witness = vec![
+            vec![],
+            der_sigs[0].clone(),
+            der_sigs[1].clone(),
+            script.to_bytes(),
+        ];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(interpreter.verify(vfyfn).unwrap(), true);
+
+        // Satisfying both sides of an or_b uses more witness items than
+        // any satisfaction the script would produce, but is valid
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!(
+            "or_b(j:and_v(vc:pk_k({}),c:pk_k({})),aj:and_v(vc:pk_k({}),c:pk_k({})))",
+            pks[0],
+            pks[1],
+            pks[2],
+            pks[3]
+        );
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let mut witness = vec![
+            der_sigs[3].clone(),
+            der_sigs[2].clone(),
+            der_sigs[1].clone(),
+            der_sigs[0].clone(),
+            script.to_bytes(),
+        ];
+        assert!(witness.len() - 1 > ms.ext.stack_elem_count_sat.unwrap());
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(interpreter.verify(vfyfn).unwrap(), true);
+
+        // ...while items the script does not consume are rejected
+        witness.insert(0, vec![]);
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert!(interpreter.verify(vfyfn).is_err());
+    }
+
+    #[test]
+    fn empty_push_is_false() {
+        let (pks, der_sigs, secp_sigs, sighash, secp) = setup_keys_sigs(2);
+        let vfyfn =
+            |pk: &dogecoin::PublicKey, (sig, _)| secp.verify(&sighash, &sig, &pk.key).is_ok();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("or_i(pk({}),pk({}))", pks[0], pks[1]);
+        let script = ms.encode();
+        let expected = [SatisfiedConstraint::PublicKey {
+            key: &pks[1],
+            sig: secp_sigs[1],
+            sighash_type: dogecoin::SigHashType::All,
+        }];
+
+        // The `OP_IF` selector is a literal empty witness element
+        let empty = dogecoin::Script::new();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let witness = vec![der_sigs[1].clone(), vec![], script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        let constraints: Result<Vec<_>, _> = interpreter.iter(vfyfn).collect();
+        assert_eq!(constraints.unwrap(), expected);
+
+        // ...or an `OP_0` in a scriptSig
+        let spk = dogecoin::Script::new_p2sh(&script.script_hash());
+        let script_sig = dogecoin::blockdata::script::Builder::new()
+            .push_slice(&der_sigs[1])
+            .push_slice(&[])
+            .push_slice(&script[..])
+            .into_script();
+        assert_eq!(script_sig[der_sigs[1].len() + 1], 0x00);
+        let mut interpreter = Interpreter::from_txdata(&spk, &script_sig, &[], 0, 0).unwrap();
+        let constraints: Result<Vec<_>, _> = interpreter.iter(vfyfn).collect();
+        assert_eq!(constraints.unwrap(), expected);
+    }
+
+    #[test]
+    fn timelock_opcodes() {
+        use dogecoin::blockdata::opcodes;
+        use dogecoin::blockdata::script::Builder;
+
+        // `OP_CLTV` and `OP_CSV` are the very opcodes `OP_NOP2` and `OP_NOP3`,
+        // and each timelock fragment is decoded from the opcode actually used,
+        // so the fragment and the opcode cannot disagree
+        let empty = dogecoin::Script::new();
+        let interpret = |opcode, age, height| {
+            let script = Builder::new()
+                .push_int(10)
+                .push_opcode(opcode)
+                .into_script();
+            let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+            let witness = vec![script.to_bytes()];
+            let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, age, height)
+                .expect("parse txdata");
+            let constraints: Result<Vec<_>, _> = interpreter
+                .iter(|_: &dogecoin::PublicKey, _| true)
+                .map(|c| match c? {
+                    SatisfiedConstraint::RelativeTimeLock { time } => Ok(("older", *time)),
+                    SatisfiedConstraint::AbsoluteTimeLock { time } => Ok(("after", *time)),
+                    c => panic!("unexpected constraint {:?}", c),
+                })
+                .collect();
+            constraints
+        };
+        assert_eq!(opcodes::all::OP_CLTV.into_u8(), 0xb1);
+        assert_eq!(opcodes::all::OP_CSV.into_u8(), 0xb2);
+
+        assert_eq!(
+            interpret(opcodes::all::OP_CSV, 0, 10).unwrap(),
+            vec![("older", 10)]
+        );
+        match interpret(opcodes::all::OP_CSV, 10, 0) {
+            Err(Error::RelativeLocktimeNotMet(10)) => {}
+            x => panic!("expected unmet relative locktime, got {:?}", x),
+        }
+        assert_eq!(
+            interpret(opcodes::all::OP_CLTV, 10, 0).unwrap(),
+            vec![("after", 10)]
+        );
+        match interpret(opcodes::all::OP_CLTV, 0, 10) {
+            Err(Error::AbsoluteLocktimeNotMet(10)) => {}
+            x => panic!("expected unmet absolute locktime, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn nonminimal_timelocks() {
+        use dogecoin::blockdata::opcodes;
+        use dogecoin::blockdata::script::Builder;
+
+        let empty = dogecoin::Script::new();
+        // `older(10)` with its operand padded to two bytes
+        let script = Builder::new()
+            .push_slice(&[0x0a, 0x00])
+            .push_opcode(opcodes::all::OP_CSV)
+            .into_script();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let witness = vec![script.to_bytes()];
+
+        assert!(Interpreter::from_txdata(&spk, &empty, &witness, 0, 10).is_err());
+
+        let mut interpreter = Interpreter::from_txdata_allowing_nonminimal_timelocks(
+            &spk, &empty, &witness, 0, 10,
+        )
+        .unwrap();
+        assert_eq!(interpreter.nonminimal_timelocks(), &[10]);
+        assert_eq!(interpreter.matched_script(), Some(&script));
+        let constraints: Result<Vec<_>, _> = interpreter.iter(|_, _| true).collect();
+        assert_eq!(
+            constraints.unwrap(),
+            vec![SatisfiedConstraint::RelativeTimeLock { time: &10 }]
+        );
+
+        let mut interpreter = Interpreter::from_txdata_allowing_nonminimal_timelocks(
+            &spk, &empty, &witness, 0, 9,
+        )
+        .unwrap();
+        assert!(interpreter.iter(|_, _| true).any(|res| res.is_err()));
+
+        // Non-minimal numbers are still rejected outside timelocks
+        let script = Builder::new()
+            .push_slice(&[0x0a, 0x00])
+            .push_opcode(opcodes::all::OP_EQUAL)
+            .into_script();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let witness = vec![vec![0x0a], script.to_bytes()];
+        assert!(Interpreter::from_txdata_allowing_nonminimal_timelocks(
+            &spk, &empty, &witness, 0, 0,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn trivial_ands() {
+        let (pks, der_sigs, _, sighash, secp) = setup_keys_sigs(3);
+        let vfyfn =
+            |pk: &dogecoin::PublicKey, (sig, _)| secp.verify(&sighash, &sig, &pk.key).is_ok();
+        let empty = dogecoin::Script::new();
+        let trivial_ands = |ms: Miniscript<dogecoin::PublicKey, NoChecks>, mut witness: Vec<_>| {
+            let script = ms.encode();
+            let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+            witness.push(script.to_bytes());
+            let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+            interpreter.trivial_ands(vfyfn)
+        };
+
+        let ms = ms_str!("and_b(pk({}),s:pk({}))", pks[0], pks[1]);
+        let witness = vec![der_sigs[1].clone(), der_sigs[0].clone()];
+        assert_eq!(trivial_ands(ms, witness).unwrap(), vec![]);
+        let ms = ms_str!("and_v(v:pk({}),pk({}))", pks[0], pks[1]);
+        let witness = vec![der_sigs[1].clone(), der_sigs[0].clone()];
+        assert_eq!(trivial_ands(ms, witness).unwrap(), vec![]);
+
+        let ms = ms_str!("and_b(pk({}),a:1)", pks[0]);
+        let witness = vec![der_sigs[0].clone()];
+        assert_eq!(
+            trivial_ands(ms, witness).unwrap(),
+            vec![TrivialAnd {
+                fragment: FragmentId(0),
+                left: false,
+                right: true,
+            }]
+        );
+
+        let ms = ms_str!("or_d(pk({}),and_b(1,a:pk({})))", pks[0], pks[1]);
+        let witness = vec![der_sigs[1].clone(), vec![]];
+        assert_eq!(
+            trivial_ands(ms, witness).unwrap(),
+            vec![TrivialAnd {
+                fragment: FragmentId(3),
+                left: true,
+                right: false,
+            }]
+        );
+
+        // A dissatisfied `and_b` is not reported, even with a signature
+        let ms = ms_str!(
+            "or_b(and_b(pk({}),s:pk({})),s:pk({}))",
+            pks[0],
+            pks[1],
+            pks[2]
+        );
+        let witness = vec![der_sigs[2].clone(), vec![], der_sigs[0].clone()];
+        assert_eq!(trivial_ands(ms, witness).unwrap(), vec![]);
+
+        // Errors in the spend are passed on
+        let ms = ms_str!("and_b(pk({}),a:1)", pks[0]);
+        let witness = vec![der_sigs[1].clone()];
+        assert!(trivial_ands(ms, witness).is_err());
+    }
+
+    #[test]
+    fn nonstandard_signatures() {
+        let (pks, der_sigs, secp_sigs, sighash, secp) = setup_keys_sigs(1);
+        let vfyfn =
+            |pk: &dogecoin::PublicKey, (sig, _)| secp.verify(&sighash, &sig, &pk.key).is_ok();
+        let empty = dogecoin::Script::new();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!("pk({})", pks[0]);
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+
+        let witness = vec![der_sigs[0].clone(), script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(interpreter.nonstandard_signatures(vfyfn).unwrap(), vec![]);
+
+        // Negate S modulo the curve order
+        const ORDER: [u8; 32] = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xfe, 0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c,
+            0xd0, 0x36, 0x41, 0x41,
+        ];
+        let mut compact = secp_sigs[0].serialize_compact();
+        let mut borrow = 0;
+        for i in (0..32).rev() {
+            let diff = ORDER[i] as i16 - compact[32 + i] as i16 - borrow;
+            borrow = if diff < 0 { 1 } else { 0 };
+            compact[32 + i] = (diff + 256 * borrow) as u8;
+        }
+        let high_s = secp256k1::Signature::from_compact(&compact).unwrap();
+        let mut sigser = high_s.serialize_der().to_vec();
+        sigser.push(0x01);
+
+        let witness = vec![sigser, script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(
+            interpreter.nonstandard_signatures(vfyfn).unwrap(),
+            vec![SatisfiedConstraint::PublicKey {
+                key: &pks[0],
+                sig: high_s,
+                sighash_type: dogecoin::SigHashType::All,
+            }]
+        );
+    }
+
+    #[test]
+    fn path_taken() {
+        let (pks, der_sigs, _, sighash, secp) = setup_keys_sigs(2);
+        let vfyfn =
+            |pk: &dogecoin::PublicKey, (sig, _)| secp.verify(&sighash, &sig, &pk.key).is_ok();
+        let empty = dogecoin::Script::new();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!(
+            "or_d(pk({}),and_v(v:pk({}),older(10)))",
+            pks[0],
+            pks[1]
+        );
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+
+        let hot = vec![der_sigs[0].clone(), script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &hot, 0, 0).unwrap();
+        let hot_path = interpreter.path_taken(vfyfn).unwrap();
+        assert_eq!(
+            hot_path,
+            PathFingerprint {
+                fragments: vec![FragmentId(2)],
+                keys: vec![pks[0]],
+            }
+        );
+
+        let recovery = vec![der_sigs[1].clone(), vec![], script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &recovery, 0, 10).unwrap();
+        let recovery_path = interpreter.path_taken(vfyfn).unwrap();
+        assert_eq!(
+            recovery_path,
+            PathFingerprint {
+                fragments: vec![FragmentId(6), FragmentId(7)],
+                keys: vec![pks[1]],
+            }
+        );
+        assert!(hot_path != recovery_path);
+
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &recovery, 0, 9).unwrap();
+        assert!(interpreter.path_taken(vfyfn).is_err());
+    }
+
+    #[test]
+    fn iter_depth_limited() {
+        let (pks, der_sigs, secp_sigs, sighash, secp) = setup_keys_sigs(3);
+        let vfyfn =
+            |pk: &dogecoin::PublicKey, (sig, _)| secp.verify(&sighash, &sig, &pk.key).is_ok();
+        let empty = dogecoin::Script::new();
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+            ms_str!("or_d(pk({}),pk({}))", pks[0], pks[1]);
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let witness = vec![der_sigs[1].clone(), vec![], script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(
+            interpreter.iter_depth_limited(vfyfn, 2).unwrap(),
+            vec![DepthLimitedItem::Constraint(SatisfiedConstraint::PublicKey {
+                key: &pks[1],
+                sig: secp_sigs[1],
+                sighash_type: dogecoin::SigHashType::All,
+            })]
+        );
+
+        // The keys lie past depth 1, so their signatures go unchecked
+        let witness = vec![der_sigs[2].clone(), vec![], script.to_bytes()];
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert_eq!(
+            interpreter.iter_depth_limited(vfyfn, 1).unwrap(),
+            vec![
+                DepthLimitedItem::NotEvaluated(FragmentId(2)),
+                DepthLimitedItem::NotEvaluated(FragmentId(4)),
+            ]
+        );
+        let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert!(interpreter.iter_depth_limited(vfyfn, 2).is_err());
+    }
+
+    #[test]
+    fn check_consensus_limits() {
+        let (pks, _, _, _, _) = setup_keys_sigs(1);
+        let empty = dogecoin::Script::new();
+        // A balanced tree of `and_v`s over `n` copies of `leaf`, whose
+        // depth stays small enough for the parser's recursion
+        fn tree(leaf: &str, n: usize) -> String {
+            if n == 1 {
+                leaf.to_owned()
+            } else {
+                let left = tree(leaf, n / 2);
+                // Wrappers are merged, as in `vc:pk_h`
+                let sep = if left.starts_with("c:") { "" } else { ":" };
+                format!("and_v(v{}{},{})", sep, left, tree(leaf, n - n / 2))
+            }
+        }
+        let chain = |leaf: &str, n: usize| {
+            let ms: Miniscript<dogecoin::PublicKey, NoChecks> =
+                Miniscript::from_str_insane(&tree(leaf, n)).unwrap();
+            ms.encode()
+        };
+        let pk_chain = |n| chain(&format!("pk({})", pks[0]), n);
+        let wsh = |script: &dogecoin::Script, items: usize| {
+            let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+            let mut witness = vec![vec![]; items];
+            witness.push(script.to_bytes());
+            (spk, witness)
+        };
+        let sh = |script: &dogecoin::Script| {
+            let spk = dogecoin::Script::new_p2sh(&script.script_hash());
+            let script_sig = dogecoin::blockdata::script::Builder::new()
+                .push_slice(&script[..])
+                .into_script();
+            (spk, script_sig)
+        };
+
+        let script = pk_chain(100);
+        let (spk, witness) = wsh(&script, 100);
+        let interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        assert!(interpreter.check_consensus_limits().is_ok());
+
+        let script = pk_chain(101);
+        let (spk, witness) = wsh(&script, 101);
+        let interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        match interpreter.check_consensus_limits() {
+            Err(Error::WitnessStackItemsExceeded(101)) => {}
+            x => panic!("expected too many witness items, got {:?}", x),
+        }
+
+        let script = pk_chain(110);
+        let (spk, witness) = wsh(&script, 110);
+        let interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+        match interpreter.check_consensus_limits() {
+            Err(Error::ScriptSizeExceeded(n)) => assert_eq!(n, script.len()),
+            x => panic!("expected oversized script, got {:?}", x),
+        }
+
+        let script = pk_chain(202);
+        let interpreter = Interpreter::from_txdata(&script, &empty, &[], 0, 0).unwrap();
+        match interpreter.check_consensus_limits() {
+            Err(Error::OpCountExceeded(202)) => {}
+            x => panic!("expected too many opcodes, got {:?}", x),
+        }
+
+        let script = pk_chain(15);
+        let (spk, script_sig) = sh(&script);
+        let interpreter = Interpreter::from_txdata(&spk, &script_sig, &[], 0, 0).unwrap();
+        match interpreter.check_consensus_limits() {
+            Err(Error::ScriptSizeExceeded(n)) => assert_eq!(n, script.len()),
+            x => panic!("expected oversized redeem script, got {:?}", x),
+        }
+
+        let hash = pks[0].to_pubkeyhash();
+        let script = chain(&format!("c:pk_h({})", hash), 16);
+        let (spk, script_sig) = sh(&script);
+        let interpreter = Interpreter::from_txdata(&spk, &script_sig, &[], 0, 0).unwrap();
+        match interpreter.check_consensus_limits() {
+            Err(Error::SigOpsExceeded(16)) => {}
+            x => panic!("expected too many sigops, got {:?}", x),
+        }
+        let script = chain(&format!("c:pk_h({})", hash), 15);
+        let (spk, script_sig) = sh(&script);
+        let interpreter = Interpreter::from_txdata(&spk, &script_sig, &[], 0, 0).unwrap();
+        assert!(interpreter.check_consensus_limits().is_ok());
+    }
+
+    #[test]
+    fn witnesses_equivalent() {
+        let (pks, der_sigs, _, _, _) = setup_keys_sigs(2);
+        let preimage = vec![0x42; 32];
+        let hash = sha256::Hash::hash(&preimage);
+        // Either key may spend along with the preimage
+        let ms: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!(
+            "and_v(v:sha256({}),or_d(pk({}),pk({})))",
+            hash,
+            pks[0],
+            pks[1]
+        );
+        let script = ms.encode();
+        let spk = dogecoin::Script::new_v0_wsh(&script.wscript_hash());
+        let w1 = vec![der_sigs[0].clone(), preimage.clone(), script.to_bytes()];
+        let w2 = vec![der_sigs[1].clone(), vec![], preimage.clone(), script.to_bytes()];
+        let bad = vec![der_sigs[0].clone(), vec![0x43; 32], script.to_bytes()];
+
+        assert_eq!(super::witnesses_equivalent(&spk, &w1, &w2, 0, 0).unwrap(), true);
+        assert_eq!(super::witnesses_equivalent(&spk, &w1, &bad, 0, 0).unwrap(), false);
+        let other: Miniscript<dogecoin::PublicKey, NoChecks> = ms_str!("pk({})", pks[0]);
+        let other = dogecoin::Script::new_v0_wsh(&other.encode().wscript_hash());
+        assert!(super::witnesses_equivalent(&other, &w1, &w2, 0, 0).is_err());
     }
 }
@@ -0,0 +1,319 @@
+// Miniscript
+// Written in 2026 by rust-miniscript developers
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! Best-effort interpretation of scripts which are not Miniscript
+//!
+
+use dogecoin;
+use dogecoin::blockdata::opcodes::all::*;
+use dogecoin::blockdata::script::{self, Instruction};
+use dogecoin::hashes::{hash160, ripemd160, sha256, sha256d, Hash};
+use dogecoin::secp256k1;
+use BitcoinSig;
+
+use super::verify_sersig;
+
+/// A condition recognized in a script which is not necessarily Miniscript,
+/// and which the witness appears to satisfy
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PartialConstraint<'txin> {
+    /// A key pushed by the script, and a witness signature valid for it
+    PublicKey {
+        /// The key
+        key: dogecoin::PublicKey,
+        /// The signature
+        sig: secp256k1::Signature,
+    },
+    /// A `DUP HASH160 <keyhash> EQUALVERIFY` check, and a witness key
+    /// matching it with a signature valid for that key
+    PublicKeyHash {
+        /// The pubkey hash
+        keyhash: hash160::Hash,
+        /// The revealed key
+        key: dogecoin::PublicKey,
+        /// The signature
+        sig: secp256k1::Signature,
+    },
+    /// A SHA256 hashlock and a witness preimage for it
+    Sha256 {
+        /// The hash
+        hash: sha256::Hash,
+        /// The preimage
+        preimage: &'txin [u8],
+    },
+    /// A HASH256 hashlock and a witness preimage for it
+    Hash256 {
+        /// The hash
+        hash: sha256d::Hash,
+        /// The preimage
+        preimage: &'txin [u8],
+    },
+    /// A RIPEMD160 hashlock and a witness preimage for it
+    Ripemd160 {
+        /// The hash
+        hash: ripemd160::Hash,
+        /// The preimage
+        preimage: &'txin [u8],
+    },
+    /// A HASH160 hashlock and a witness preimage for it
+    Hash160 {
+        /// The hash
+        hash: hash160::Hash,
+        /// The preimage
+        preimage: &'txin [u8],
+    },
+    /// A `CHECKSEQUENCEVERIFY` of this value
+    RelativeTimeLock {
+        /// The relative timelock
+        time: u32,
+    },
+    /// A `CHECKLOCKTIMEVERIFY` of this value
+    AbsoluteTimeLock {
+        /// The absolute timelock
+        time: u32,
+    },
+}
+
+/// Report the conditions in `script` which `witness` appears to satisfy, for
+/// scripts which cannot be interpreted as Miniscript. Rather than executing
+/// the script, this recognizes the patterns Miniscript and common templates
+/// use for signature checks, pubkey hashes, hashlocks and timelocks, and
+/// looks for witness elements satisfying each.
+///
+/// This is no substitute for real script execution: it cannot tell whether
+/// the script as a whole is satisfied, or which branches were taken, and
+/// timelocks are reported without being checked against the transaction.
+/// Instructions after one which fails to parse are ignored.
+pub fn interpret_script<'txin, F>(
+    script: &script::Script,
+    witness: &'txin [Vec<u8>],
+    mut verify_sig: F,
+) -> Vec<PartialConstraint<'txin>>
+where
+    F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+{
+    let mut ins = vec![];
+    for instruction in script.instructions() {
+        match instruction {
+            Ok(instruction) => ins.push(instruction),
+            Err(_) => break,
+        }
+    }
+    let mut ret = vec![];
+
+    for (i, instruction) in ins.iter().enumerate() {
+        let next = ins.get(i + 1);
+        match (read_num(instruction), next) {
+            (Some(time), Some(&Instruction::Op(OP_CSV))) => {
+                ret.push(PartialConstraint::RelativeTimeLock { time: time });
+            }
+            (Some(time), Some(&Instruction::Op(OP_CLTV))) => {
+                ret.push(PartialConstraint::AbsoluteTimeLock { time: time });
+            }
+            _ => {}
+        }
+
+        match *instruction {
+            Instruction::PushBytes(bytes) => {
+                if let Ok(key) = dogecoin::PublicKey::from_slice(bytes) {
+                    for sig in find_sigs(witness, &key, &mut verify_sig) {
+                        ret.push(PartialConstraint::PublicKey { key: key, sig: sig });
+                    }
+                }
+            }
+            Instruction::Op(op) => {
+                let operand = match next {
+                    Some(&Instruction::PushBytes(bytes)) => bytes,
+                    _ => continue,
+                };
+                if op == OP_HASH160 && i > 0 && ins[i - 1] == Instruction::Op(OP_DUP) {
+                    if let Ok(keyhash) = hash160::Hash::from_slice(operand) {
+                        for elem in witness {
+                            let key = match dogecoin::PublicKey::from_slice(elem) {
+                                Ok(key) => key,
+                                Err(_) => continue,
+                            };
+                            if hash160::Hash::hash(elem) != keyhash {
+                                continue;
+                            }
+                            for sig in find_sigs(witness, &key, &mut verify_sig) {
+                                ret.push(PartialConstraint::PublicKeyHash {
+                                    keyhash: keyhash,
+                                    key: key,
+                                    sig: sig,
+                                });
+                            }
+                        }
+                    }
+                    continue;
+                }
+                for preimage in witness {
+                    let constraint = match op {
+                        OP_SHA256 => sha256::Hash::from_slice(operand)
+                            .ok()
+                            .filter(|h| sha256::Hash::hash(preimage) == *h)
+                            .map(|hash| PartialConstraint::Sha256 {
+                                hash: hash,
+                                preimage: preimage,
+                            }),
+                        OP_HASH256 => sha256d::Hash::from_slice(operand)
+                            .ok()
+                            .filter(|h| sha256d::Hash::hash(preimage) == *h)
+                            .map(|hash| PartialConstraint::Hash256 {
+                                hash: hash,
+                                preimage: preimage,
+                            }),
+                        OP_RIPEMD160 => ripemd160::Hash::from_slice(operand)
+                            .ok()
+                            .filter(|h| ripemd160::Hash::hash(preimage) == *h)
+                            .map(|hash| PartialConstraint::Ripemd160 {
+                                hash: hash,
+                                preimage: preimage,
+                            }),
+                        OP_HASH160 => hash160::Hash::from_slice(operand)
+                            .ok()
+                            .filter(|h| hash160::Hash::hash(preimage) == *h)
+                            .map(|hash| PartialConstraint::Hash160 {
+                                hash: hash,
+                                preimage: preimage,
+                            }),
+                        _ => None,
+                    };
+                    if let Some(constraint) = constraint {
+                        ret.push(constraint);
+                    }
+                }
+            }
+        }
+    }
+    ret
+}
+
+/// Witness signatures which are valid for `key`
+fn find_sigs<F>(
+    witness: &[Vec<u8>],
+    key: &dogecoin::PublicKey,
+    verify_sig: &mut F,
+) -> Vec<secp256k1::Signature>
+where
+    F: FnMut(&dogecoin::PublicKey, BitcoinSig) -> bool,
+{
+    witness
+        .iter()
+        .filter(|elem| !elem.is_empty())
+        .filter_map(|elem| verify_sersig(&mut *verify_sig, key, elem).ok())
+        .map(|(sig, _)| sig)
+        .collect()
+}
+
+/// Read a positive timelock operand, pushed either as a script number or,
+/// for small values, as a numeric opcode
+fn read_num(instruction: &Instruction) -> Option<u32> {
+    match *instruction {
+        Instruction::PushBytes(bytes) => match script::read_scriptint(bytes) {
+            Ok(n) if n > 0 && n <= u32::max_value() as i64 => Some(n as u32),
+            _ => None,
+        },
+        Instruction::Op(op) => {
+            let n = op.into_u8();
+            if n >= OP_PUSHNUM_1.into_u8() && n <= OP_PUSHNUM_16.into_u8() {
+                Some((n - OP_PUSHNUM_1.into_u8() + 1) as u32)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dogecoin::blockdata::opcodes;
+    use dogecoin::blockdata::script::Builder;
+
+    #[test]
+    fn interpret_script() {
+        let secp = secp256k1::Secp256k1::new();
+        let msg = secp256k1::Message::from_slice(&[0xab; 32][..]).unwrap();
+        let mut keys = vec![];
+        let mut sigs = vec![];
+        for i in 1..3 {
+            let sk = secp256k1::SecretKey::from_slice(&[i; 32][..]).unwrap();
+            keys.push(dogecoin::PublicKey {
+                key: secp256k1::PublicKey::from_secret_key(&secp, &sk),
+                compressed: true,
+            });
+            sigs.push(secp.sign(&msg, &sk));
+        }
+        let sersig = |sig: &secp256k1::Signature| {
+            let mut ret = sig.serialize_der().to_vec();
+            ret.push(0x01);
+            ret
+        };
+        let preimage = vec![0x42; 32];
+        let hash = sha256::Hash::hash(&preimage);
+
+        // OP_ROT keeps this from being Miniscript
+        let script = Builder::new()
+            .push_key(&keys[0])
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_opcode(OP_SHA256)
+            .push_slice(&hash[..])
+            .push_opcode(OP_EQUALVERIFY)
+            .push_int(10)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .push_int(700_000)
+            .push_opcode(OP_CLTV)
+            .push_opcode(opcodes::all::OP_ROT)
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_slice(&keys[1].pubkey_hash()[..])
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let witness = vec![
+            sersig(&sigs[1]),
+            keys[1].to_bytes(),
+            preimage.clone(),
+            sersig(&sigs[0]),
+        ];
+        let constraints = super::interpret_script(&script, &witness, |pk, (sig, _)| {
+            secp.verify(&msg, &sig, &pk.key).is_ok()
+        });
+        assert_eq!(
+            constraints,
+            vec![
+                PartialConstraint::PublicKey {
+                    key: keys[0],
+                    sig: sigs[0],
+                },
+                PartialConstraint::Sha256 {
+                    hash: hash,
+                    preimage: &preimage[..],
+                },
+                PartialConstraint::RelativeTimeLock { time: 10 },
+                PartialConstraint::AbsoluteTimeLock { time: 700_000 },
+                PartialConstraint::PublicKeyHash {
+                    keyhash: keys[1].pubkey_hash().into(),
+                    key: keys[1],
+                    sig: sigs[1],
+                },
+            ]
+        );
+
+        // Without valid signatures, only the hashlock and timelocks remain
+        let constraints = super::interpret_script(&script, &witness, |_, _| false);
+        assert_eq!(constraints.len(), 3);
+    }
+}
@@ -14,10 +14,13 @@
 
 //! Interpreter stack
 
+use std::{cmp, hash};
+
 use dogecoin;
 use dogecoin::blockdata::{opcodes, script};
 use dogecoin::hashes::{hash160, ripemd160, sha256, sha256d, Hash};
 
+use miniscript::limits::SEQUENCE_LOCKTIME_TYPE_FLAG;
 use {BitcoinSig, ToPublicKey};
 
 use super::{verify_sersig, Error, HashLockType, SatisfiedConstraint};
@@ -71,53 +74,167 @@ impl<'txin> Element<'txin> {
     }
 }
 
+/// Owned copy of a stack `Element`, for inspecting or building stacks
+/// outside of the interpreter
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum WitnessStackElement {
+    /// Result of a satisfied Miniscript fragment, or `vec![1]` in a witness
+    Satisfied,
+    /// Result of a dissatisfied Miniscript fragment, or `vec![]` in a witness
+    Dissatisfied,
+    /// Any other witness element
+    Push(Vec<u8>),
+}
+
+impl<'txin> From<Element<'txin>> for WitnessStackElement {
+    fn from(elem: Element<'txin>) -> WitnessStackElement {
+        match elem {
+            Element::Satisfied => WitnessStackElement::Satisfied,
+            Element::Dissatisfied => WitnessStackElement::Dissatisfied,
+            Element::Push(v) => WitnessStackElement::Push(v.to_vec()),
+        }
+    }
+}
+
+impl WitnessStackElement {
+    /// The element as it appears in a witness
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match *self {
+            WitnessStackElement::Satisfied => vec![1],
+            WitnessStackElement::Dissatisfied => vec![],
+            WitnessStackElement::Push(ref v) => v.clone(),
+        }
+    }
+}
+
+/// Owned snapshot of an interpreter stack, with the top element last
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Default)]
+pub struct PublicStack(Vec<WitnessStackElement>);
+
+impl PublicStack {
+    /// Create a stack from its elements, with the top element last
+    pub fn new(elems: Vec<WitnessStackElement>) -> PublicStack {
+        PublicStack(elems)
+    }
+
+    /// Create a stack from witness elements, translating them into stack
+    /// elements as the interpreter does
+    pub fn from_witness(witness: &[Vec<u8>]) -> PublicStack {
+        PublicStack(
+            witness
+                .iter()
+                .map(|v| WitnessStackElement::from(Element::from(v)))
+                .collect(),
+        )
+    }
+
+    /// The elements of the stack, with the top element last
+    pub fn elements(&self) -> &[WitnessStackElement] {
+        &self.0
+    }
+
+    /// The stack as witness elements, e.g. for building a spend
+    pub fn to_witness(&self) -> Vec<Vec<u8>> {
+        self.0.iter().map(WitnessStackElement::to_bytes).collect()
+    }
+}
+
 /// Stack Data structure representing the stack input to Miniscript. This Stack
 /// is created from the combination of ScriptSig and Witness stack.
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
-pub struct Stack<'txin>(Vec<Element<'txin>>);
+///
+/// The stack also records the last push popped from it, which is not taken
+/// into account when comparing stacks.
+#[derive(Clone, Debug)]
+pub struct Stack<'txin> {
+    elems: Vec<Element<'txin>>,
+    last_push: Option<&'txin [u8]>,
+}
+
+impl<'txin> PartialEq for Stack<'txin> {
+    fn eq(&self, other: &Self) -> bool {
+        self.elems == other.elems
+    }
+}
+
+impl<'txin> Eq for Stack<'txin> {}
+
+impl<'txin> PartialOrd for Stack<'txin> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'txin> Ord for Stack<'txin> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.elems.cmp(&other.elems)
+    }
+}
+
+impl<'txin> hash::Hash for Stack<'txin> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.elems.hash(state)
+    }
+}
 
 impl<'txin> From<Vec<Element<'txin>>> for Stack<'txin> {
     fn from(v: Vec<Element<'txin>>) -> Self {
-        Stack(v)
+        Stack {
+            elems: v,
+            last_push: None,
+        }
     }
 }
 
 impl<'txin> Default for Stack<'txin> {
     fn default() -> Self {
-        Stack(vec![])
+        Stack::from(vec![])
     }
 }
 
 impl<'txin> Stack<'txin> {
     /// Whether the stack is empty
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.elems.is_empty()
     }
 
     /// Number of elements on the stack
     pub fn len(&mut self) -> usize {
-        self.0.len()
+        self.elems.len()
+    }
+
+    /// The last witness push popped from the stack, if any
+    pub fn last_push(&self) -> Option<&'txin [u8]> {
+        self.last_push
     }
 
     /// Removes the top stack element, if the stack is nonempty
     pub fn pop(&mut self) -> Option<Element<'txin>> {
-        self.0.pop()
+        let elem = self.elems.pop();
+        if let Some(Element::Push(push)) = elem {
+            self.last_push = Some(push);
+        }
+        elem
     }
 
     /// Pushes an element onto the top of the stack
     pub fn push(&mut self, elem: Element<'txin>) -> () {
-        self.0.push(elem);
+        self.elems.push(elem);
     }
 
     /// Returns a new stack representing the top `k` elements of the stack,
     /// removing these elements from the original
     pub fn split_off(&mut self, k: usize) -> Vec<Element<'txin>> {
-        self.0.split_off(k)
+        self.elems.split_off(k)
+    }
+
+    /// The elements of the stack, with the top element last
+    pub fn as_slice(&self) -> &[Element<'txin>] {
+        &self.elems
     }
 
     /// Returns a reference to the top stack element, if the stack is nonempty
     pub fn last(&self) -> Option<&Element<'txin>> {
-        self.0.last()
+        self.elems.last()
     }
 
     /// Helper function to evaluate a Pk Node which takes the
@@ -143,9 +260,13 @@ impl<'txin> Stack<'txin> {
                 Element::Push(ref sigser) => {
                     let sig = verify_sersig(verify_sig, pk, sigser);
                     match sig {
-                        Ok(sig) => {
+                        Ok((sig, sighash_type)) => {
                             self.push(Element::Satisfied);
-                            Some(Ok(SatisfiedConstraint::PublicKey { key: pk, sig }))
+                            Some(Ok(SatisfiedConstraint::PublicKey {
+                                key: pk,
+                                sig,
+                                sighash_type,
+                            }))
                         }
                         Err(e) => return Some(Err(e)),
                     }
@@ -163,22 +284,30 @@ impl<'txin> Stack<'txin> {
     /// from the top of the stack and outputs Sat if the pubkey, sig is valid
     /// Sat: If the pubkey hash matches and signature witness is correct,
     /// Unsat: For an empty witness
-    /// Err: All of other witness result in errors.
+    /// Err: All of other witness result in errors, as do uncompressed keys
+    /// if `require_compressed` is set, as it should be for segwit scripts.
     /// `DUP HASH160 <keyhash> EQUALVERIY CHECKSIG`
     pub fn evaluate_pkh<'intp, F>(
         &mut self,
         verify_sig: F,
         pkh: &'intp hash160::Hash,
+        require_compressed: bool,
     ) -> Option<Result<SatisfiedConstraint<'intp, 'txin>, Error>>
     where
         F: FnOnce(&dogecoin::PublicKey, BitcoinSig) -> bool,
     {
         if let Some(Element::Push(pk)) = self.pop() {
+            if pk.len() != 33 && pk.len() != 65 {
+                return Some(Err(Error::InvalidPubkeyLength(pk.len())));
+            }
             let pk_hash = hash160::Hash::hash(pk);
             if pk_hash != *pkh {
                 return Some(Err(Error::PkHashVerifyFail(*pkh)));
             }
             match dogecoin::PublicKey::from_slice(pk) {
+                Ok(ref pk) if require_compressed && !pk.compressed => {
+                    Some(Err(Error::UncompressedPubkey))
+                }
                 Ok(pk) => {
                     if let Some(sigser) = self.pop() {
                         match sigser {
@@ -189,12 +318,13 @@ impl<'txin> Stack<'txin> {
                             Element::Push(sigser) => {
                                 let sig = verify_sersig(verify_sig, &pk, sigser);
                                 match sig {
-                                    Ok(sig) => {
+                                    Ok((sig, sighash_type)) => {
                                         self.push(Element::Satisfied);
                                         Some(Ok(SatisfiedConstraint::PublicKeyHash {
                                             keyhash: pkh,
                                             key: pk,
                                             sig,
+                                            sighash_type,
                                         }))
                                     }
                                     Err(e) => return Some(Err(e)),
@@ -228,6 +358,11 @@ impl<'txin> Stack<'txin> {
         n: &'intp u32,
         age: u32,
     ) -> Option<Result<SatisfiedConstraint<'intp, 'txin>, Error>> {
+        // Miniscript only allows operands in 1..2^31
+        const LOCKTIME_MAX_EXCLUSIVE: u32 = 1 << 31;
+        if *n == 0 || *n >= LOCKTIME_MAX_EXCLUSIVE {
+            return Some(Err(Error::InvalidLocktimeValue(*n)));
+        }
         if age >= *n {
             self.push(Element::Satisfied);
             Some(Ok(SatisfiedConstraint::AbsoluteTimeLock { time: n }))
@@ -247,6 +382,11 @@ impl<'txin> Stack<'txin> {
         n: &'intp u32,
         height: u32,
     ) -> Option<Result<SatisfiedConstraint<'intp, 'txin>, Error>> {
+        // With the disable flag set, CSV does not lock anything; other bits
+        // outside the type flag and 16-bit value are reserved by BIP68
+        if *n == 0 || *n & !(SEQUENCE_LOCKTIME_TYPE_FLAG | 0xffff) != 0 {
+            return Some(Err(Error::InvalidLocktimeValue(*n)));
+        }
         if height >= *n {
             self.push(Element::Satisfied);
             Some(Ok(SatisfiedConstraint::RelativeTimeLock { time: n }))
@@ -373,7 +513,13 @@ impl<'txin> Stack<'txin> {
             if let Element::Push(sigser) = witness_sig {
                 let sig = verify_sersig(verify_sig, pk, sigser);
                 match sig {
-                    Ok(sig) => return Some(Ok(SatisfiedConstraint::PublicKey { key: pk, sig })),
+                    Ok((sig, sighash_type)) => {
+                        return Some(Ok(SatisfiedConstraint::PublicKey {
+                            key: pk,
+                            sig,
+                            sighash_type,
+                        }))
+                    }
                     Err(..) => {
                         self.push(witness_sig);
                         return None;
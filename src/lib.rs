@@ -125,7 +125,7 @@ use dogecoin::hashes::{hash160, sha256, Hash};
 
 pub use descriptor::{Descriptor, DescriptorPublicKey, DescriptorTrait};
 pub use interpreter::Interpreter;
-pub use miniscript::context::{BareCtx, Legacy, ScriptContext, Segwitv0};
+pub use miniscript::context::{BareCtx, Legacy, ScriptContext, ScriptContextKind, Segwitv0};
 pub use miniscript::decode::Terminal;
 pub use miniscript::satisfy::{BitcoinSig, Preimage32, Satisfier};
 pub use miniscript::Miniscript;
@@ -491,8 +491,18 @@ pub enum Error {
     RelativeLocktimeNotMet(u32),
     /// Could not satisfy, absolute locktime not met
     AbsoluteLocktimeNotMet(u32),
+    /// Absolute locktimes mixing block heights and timestamps cannot be
+    /// satisfied by a single `nLockTime`
+    HeightTimeLockCombination,
+    /// Relative heightlocks and timelocks were combined, which cannot be
+    /// satisfied by a single `nSequence`
+    IncompatibleTimelockUnits,
+    /// A satisfier failed while looking up a signature
+    Satisfier(miniscript::satisfy::SatisfierError),
     /// General failure to satisfy
     CouldNotSatisfy,
+    /// General failure to dissatisfy
+    CouldNotDissatisfy,
     /// Typechecking failed
     TypeCheck(String),
     /// General error in creating descriptor
@@ -570,6 +580,7 @@ impl error::Error for Error {
     fn cause(&self) -> Option<&error::Error> {
         match *self {
             Error::BadPubkey(ref e) => Some(e),
+            Error::Satisfier(ref e) => Some(e),
             _ => None,
         }
     }
@@ -613,7 +624,15 @@ impl fmt::Display for Error {
                 "required absolute locktime CLTV of {} blocks, not met",
                 n
             ),
+            Error::HeightTimeLockCombination => {
+                f.write_str("cannot combine absolute heightlocks and timelocks")
+            }
+            Error::IncompatibleTimelockUnits => {
+                f.write_str("cannot combine relative heightlocks and timelocks")
+            }
+            Error::Satisfier(ref e) => write!(f, "satisfier error: {}", e),
             Error::CouldNotSatisfy => f.write_str("could not satisfy"),
+            Error::CouldNotDissatisfy => f.write_str("could not dissatisfy"),
             Error::BadPubkey(ref e) => fmt::Display::fmt(e, f),
             Error::TypeCheck(ref e) => write!(f, "typecheck: {}", e),
             Error::BadDescriptor(ref e) => write!(f, "Invalid descriptor: {}", e),
@@ -696,3 +715,16 @@ fn hex_script(s: &str) -> dogecoin::Script {
     let v: Vec<u8> = dogecoin::hashes::hex::FromHex::from_hex(s).unwrap();
     dogecoin::Script::from(v)
 }
+
+/// Helper function used by tests: the secret key `[1; 32]` and its compressed
+/// public key
+#[cfg(test)]
+fn test_key() -> (dogecoin::secp256k1::SecretKey, dogecoin::PublicKey) {
+    let secp = dogecoin::secp256k1::Secp256k1::signing_only();
+    let sk = dogecoin::secp256k1::SecretKey::from_slice(&[1; 32][..]).unwrap();
+    let pk = dogecoin::PublicKey {
+        key: dogecoin::secp256k1::PublicKey::from_secret_key(&secp, &sk),
+        compressed: true,
+    };
+    (sk, pk)
+}
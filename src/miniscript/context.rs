@@ -55,6 +55,8 @@ pub enum ScriptContextError {
     MaxScriptSigSizeExceeded,
     /// Impossible to satisfy the miniscript under the current context
     ImpossibleSatisfaction,
+    /// Scripts of the current context cannot be committed to as given
+    IncompatibleKind(ScriptContextKind),
 }
 
 impl fmt::Display for ScriptContextError {
@@ -99,10 +101,29 @@ impl fmt::Display for ScriptContextError {
                     "Impossible to satisfy Miniscript under the current context"
                 )
             }
+            ScriptContextError::IncompatibleKind(kind) => write!(
+                f,
+                "Miniscript of the current context cannot be spent as {:?}",
+                kind
+            ),
         }
     }
 }
 
+/// How a script is committed to by the output it controls, which decides
+/// where its satisfaction goes in a spending input
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum ScriptContextKind {
+    /// The script is the scriptPubKey itself
+    Bare,
+    /// Pay-to-script-hash, with the script as redeem script
+    Legacy,
+    /// Pay-to-witness-script-hash, with the script as witness script
+    Segwitv0,
+    /// Pay-to-witness-script-hash nested in pay-to-script-hash
+    ShWsh,
+}
+
 /// The ScriptContext for Miniscript. Additional type information associated with
 /// miniscript that is used for carrying out checks that dependent on the
 /// context under which the script is used.
@@ -134,6 +155,12 @@ pub trait ScriptContext:
         Ok(())
     }
 
+    /// Whether scripts of this context can be committed to as `kind`, e.g.
+    /// segwit scripts by p2wsh, nested or not, but not by p2sh alone
+    fn allows_kind(_kind: ScriptContextKind) -> bool {
+        true
+    }
+
     /// Depending on script context, the size of a satifaction witness may slightly differ.
     fn max_satisfaction_size<Pk: MiniscriptKey, Ctx: ScriptContext>(
         ms: &Miniscript<Pk, Ctx>,
@@ -317,6 +344,10 @@ impl ScriptContext for Legacy {
         // The scriptSig cost is the second element of the tuple
         ms.ext.max_sat_size.map(|x| x.1)
     }
+
+    fn allows_kind(kind: ScriptContextKind) -> bool {
+        kind == ScriptContextKind::Legacy
+    }
 }
 
 /// Segwitv0 ScriptContext
@@ -400,6 +431,10 @@ impl ScriptContext for Segwitv0 {
         // The witness stack cost is the first element of the tuple
         ms.ext.max_sat_size.map(|x| x.0)
     }
+
+    fn allows_kind(kind: ScriptContextKind) -> bool {
+        kind == ScriptContextKind::Segwitv0 || kind == ScriptContextKind::ShWsh
+    }
 }
 
 /// Bare ScriptContext
@@ -461,6 +496,10 @@ impl ScriptContext for BareCtx {
         // The witness stack cost is the first element of the tuple
         ms.ext.max_sat_size.map(|x| x.1)
     }
+
+    fn allows_kind(kind: ScriptContextKind) -> bool {
+        kind == ScriptContextKind::Bare
+    }
 }
 
 /// "No Checks" Context
@@ -119,10 +119,46 @@ impl Iterator for TokenIter {
 
 /// Tokenize a script
 pub fn lex(script: &script::Script) -> Result<Vec<Token>, Error> {
+    lex_inner(script, None)
+}
+
+/// Tokenize a script as `lex` does, except that the operands of `OP_CSV`
+/// and `OP_CLTV` may be non-minimally encoded numbers, as in some spends
+/// which predate the minimal encoding rules. Returns the tokens and the
+/// values of the non-minimal operands. Other non-minimal numbers are still
+/// rejected.
+pub fn lex_allowing_nonminimal_timelocks(
+    script: &script::Script,
+) -> Result<(Vec<Token>, Vec<u32>), Error> {
+    let mut nonminimal = vec![];
+    let tokens = lex_inner(script, Some(&mut nonminimal))?;
+    Ok((tokens, nonminimal))
+}
+
+fn lex_inner(
+    script: &script::Script,
+    mut nonminimal_timelocks: Option<&mut Vec<u32>>,
+) -> Result<Vec<Token>, Error> {
     let mut ret = Vec::with_capacity(script.len());
+    // A non-minimal number which is only allowed as a timelock operand, so
+    // must be followed by `OP_CSV` or `OP_CLTV`
+    let mut pending: Option<(&[u8], u32)> = None;
 
     for ins in script.instructions_minimal() {
-        match ins.map_err(Error::Script)? {
+        let ins = ins.map_err(Error::Script)?;
+        if let Some((bytes, v)) = pending.take() {
+            match ins {
+                script::Instruction::Op(op)
+                    if op == opcodes::all::OP_CSV || op == opcodes::all::OP_CLTV =>
+                {
+                    if let Some(ref mut nonminimal) = nonminimal_timelocks {
+                        nonminimal.push(v);
+                    }
+                }
+                _ => return Err(Error::InvalidPush(bytes.to_owned())),
+            }
+        }
+        match ins {
             script::Instruction::Op(opcodes::all::OP_BOOLAND) => {
                 ret.push(Token::BoolAnd);
             }
@@ -238,7 +274,11 @@ pub fn lex(script: &script::Script) -> Result<Vec<Token>, Error> {
                             Ok(v) if v >= 0 => {
                                 // check minimality of the number
                                 if &script::Builder::new().push_int(v).into_script()[1..] != bytes {
-                                    return Err(Error::InvalidPush(bytes.to_owned()));
+                                    if nonminimal_timelocks.is_none() || v > u32::max_value() as i64
+                                    {
+                                        return Err(Error::InvalidPush(bytes.to_owned()));
+                                    }
+                                    pending = Some((bytes, v as u32));
                                 }
                                 ret.push(Token::Num(v as u32));
                             }
@@ -302,5 +342,8 @@ pub fn lex(script: &script::Script) -> Result<Vec<Token>, Error> {
             script::Instruction::Op(op) => return Err(Error::InvalidOpcode(op)),
         };
     }
+    if let Some((bytes, _)) = pending {
+        return Err(Error::InvalidPush(bytes.to_owned()));
+    }
     Ok(ret)
 }
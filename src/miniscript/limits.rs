@@ -37,6 +37,9 @@ pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
 /// Maximum script element size allowed by consensus rules
 // https://github.com/bitcoin/bitcoin/blob/42b66a6b814bca130a9ccf0a3f747cf33d628232/src/script/script.h#L23
 pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+/// Maximum signature operations in a P2SH redeem script allowed by
+/// standardness rules, as `MAX_P2SH_SIGOPS` in Bitcoin Core's policy.h
+pub const MAX_P2SH_SIGOPS: usize = 15;
 /// Maximum script sig size allowed by standardness rules
 // https://github.com/bitcoin/bitcoin/blob/42b66a6b814bca130a9ccf0a3f747cf33d628232/src/policy/policy.cpp#L102
 pub const MAX_SCRIPTSIG_SIZE: usize = 1650;
@@ -30,7 +30,7 @@ use std::{fmt, str};
 use dogecoin;
 use dogecoin::blockdata::script;
 
-pub use self::context::{BareCtx, Legacy, Segwitv0};
+pub use self::context::{BareCtx, Legacy, ScriptContextKind, Segwitv0};
 
 pub mod analyzable;
 pub mod astelem;
@@ -45,12 +45,16 @@ pub mod types;
 use self::lex::{lex, TokenIter};
 use self::types::Property;
 pub use miniscript::context::ScriptContext;
+use miniscript::context::ScriptContextError;
 use miniscript::decode::Terminal;
 use miniscript::types::extra_props::ExtData;
 use miniscript::types::Type;
 
-use std::cmp;
+use std::{cmp, i64};
+use std::collections::HashMap;
 use std::sync::Arc;
+use interpreter::HashLockType;
+use util::witness_to_scriptsig;
 use MiniscriptKey;
 use {expression, Error, ForEach, ForEachKey, ToPublicKey, TranslatePk};
 
@@ -148,7 +152,25 @@ impl<Ctx: ScriptContext> Miniscript<dogecoin::PublicKey, Ctx> {
     pub fn parse_insane(
         script: &script::Script,
     ) -> Result<Miniscript<dogecoin::PublicKey, Ctx>, Error> {
-        let tokens = lex(script)?;
+        Self::parse_tokens(lex(script)?)
+    }
+
+    /// Attempt to parse an insane script, as [Miniscript::parse_insane] does,
+    /// except that the operands of `older` and `after` fragments may be
+    /// non-minimally encoded numbers. This is meant for analyzing historical
+    /// spends; such scripts are always non-standard. Returns the values of the
+    /// non-minimal operands as warnings alongside the Miniscript, which will
+    /// not re-encode to the original script if there are any.
+    pub fn parse_insane_allowing_nonminimal_timelocks(
+        script: &script::Script,
+    ) -> Result<(Miniscript<dogecoin::PublicKey, Ctx>, Vec<u32>), Error> {
+        let (tokens, nonminimal) = lex::lex_allowing_nonminimal_timelocks(script)?;
+        Ok((Self::parse_tokens(tokens)?, nonminimal))
+    }
+
+    fn parse_tokens(
+        tokens: Vec<lex::Token>,
+    ) -> Result<Miniscript<dogecoin::PublicKey, Ctx>, Error> {
         let mut iter = TokenIter::new(tokens);
 
         let top = decode::parse(&mut iter)?;
@@ -323,16 +345,143 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     /// Attempt to produce non-malleable satisfying witness for the
     /// witness script represented by the parse tree
+    ///
+    /// Fails with `Error::Satisfier` if the satisfier's `try_lookup_sig`
+    /// failed, and with `Error::IncompatibleTimelockUnits` if the only
+    /// satisfactions rely on `older` fragments of both units, blocks and
+    /// time, which no single sequence number can meet.
     pub fn satisfy<S: satisfy::Satisfier<Pk>>(&self, satisfier: S) -> Result<Vec<Vec<u8>>, Error>
     where
         Pk: ToPublicKey,
     {
-        match satisfy::Satisfaction::satisfy(&self.node, &satisfier, self.ty.mall.safe).stack {
+        self.satisfy_with_has_sig(satisfier).map(|(stack, _)| stack)
+    }
+
+    /// Attempt to produce non-malleable satisfying witness, as with
+    /// `satisfy`, also returning whether the witness contains a signature.
+    /// A witness without one, e.g. satisfying only hashlocks and timelocks,
+    /// can be replaced by third parties who learn the same data, so callers
+    /// may wish to refuse it.
+    pub fn satisfy_with_has_sig<S: satisfy::Satisfier<Pk>>(
+        &self,
+        satisfier: S,
+    ) -> Result<(Vec<Vec<u8>>, bool), Error>
+    where
+        Pk: ToPublicKey,
+    {
+        let satisfier = satisfy::SigErrors::new(satisfier);
+        let safe = self.ty.mall.safe;
+        let mut sat = satisfy::Satisfaction::satisfy(&self.node, &satisfier, safe);
+        let tl = self.ext.timelock_info;
+        if tl.csv_with_height && tl.csv_with_time {
+            if let satisfy::Witness::Stack(_) = sat.stack {
+                // The satisfier may have accepted `older` fragments of both
+                // units, which no sequence number can meet together
+                let by_height = satisfy::OlderUnit::new(&satisfier, false);
+                let by_time = satisfy::OlderUnit::new(&satisfier, true);
+                let by_height = satisfy::Satisfaction::satisfy(&self.node, &by_height, safe);
+                let by_time = satisfy::Satisfaction::satisfy(&self.node, &by_time, safe);
+                sat = if by_time.stack < by_height.stack {
+                    by_time
+                } else {
+                    by_height
+                };
+                match sat.stack {
+                    satisfy::Witness::Stack(_) => {}
+                    _ => {
+                        satisfier.check()?;
+                        return Err(Error::IncompatibleTimelockUnits);
+                    }
+                }
+            }
+        }
+        match sat.stack {
+            satisfy::Witness::Stack(stack) => {
+                Ctx::check_witness::<Pk, Ctx>(&stack)?;
+                Ok((stack, sat.has_sig))
+            }
+            satisfy::Witness::Unavailable | satisfy::Witness::Impossible => {
+                satisfier.check()?;
+                Err(Error::CouldNotSatisfy)
+            }
+        }
+    }
+
+    /// Attempt to produce non-malleable satisfying witness, as with
+    /// `satisfy`, and split it into the final scriptSig and witness of an
+    /// input spending the script under `kind`, as would be put in the
+    /// `final_script_sig` and `final_script_witness` fields of a PSBT
+    /// input. The redeem or witness script is included where `kind` needs
+    /// it. Fields which would be empty are `None`. Fails with
+    /// `ScriptContextError::IncompatibleKind` if scripts of the context
+    /// `Ctx` cannot be spent under `kind`, e.g. a segwit script as a bare
+    /// scriptPubKey.
+    pub fn finalize_witness<S: satisfy::Satisfier<Pk>>(
+        &self,
+        satisfier: S,
+        kind: ScriptContextKind,
+    ) -> Result<(Option<script::Script>, Option<Vec<Vec<u8>>>), Error>
+    where
+        Pk: ToPublicKey,
+    {
+        if !Ctx::allows_kind(kind) {
+            return Err(ScriptContextError::IncompatibleKind(kind).into());
+        }
+        let mut stack = self.satisfy(satisfier)?;
+        let (script_sig, witness) = match kind {
+            ScriptContextKind::Bare => (witness_to_scriptsig(&stack), vec![]),
+            ScriptContextKind::Legacy => {
+                stack.push(self.encode().into_bytes());
+                (witness_to_scriptsig(&stack), vec![])
+            }
+            ScriptContextKind::Segwitv0 => {
+                stack.push(self.encode().into_bytes());
+                (script::Script::new(), stack)
+            }
+            ScriptContextKind::ShWsh => {
+                let witness_script = self.encode();
+                let script_sig = script::Builder::new()
+                    .push_slice(&witness_script.to_v0_p2wsh()[..])
+                    .into_script();
+                stack.push(witness_script.into_bytes());
+                (script_sig, stack)
+            }
+        };
+        let script_sig = if script_sig.is_empty() {
+            None
+        } else {
+            Some(script_sig)
+        };
+        let witness = if witness.is_empty() {
+            None
+        } else {
+            Some(witness)
+        };
+        Ok((script_sig, witness))
+    }
+
+    /// Attempt to produce non-malleable satisfying witness, as with
+    /// `satisfy`, except that whenever two branches of a disjunction can be
+    /// satisfied at the same cost, one is picked at random. Randomizing the
+    /// spending path in this way can make a wallet harder to fingerprint;
+    /// the result is always a valid non-malleable witness.
+    #[cfg(feature = "rand")]
+    pub fn satisfy_randomized<S, R>(&self, satisfier: S, rng: &mut R) -> Result<Vec<Vec<u8>>, Error>
+    where
+        Pk: ToPublicKey,
+        S: satisfy::Satisfier<Pk>,
+        R: dogecoin::secp256k1::rand::RngCore,
+    {
+        let satisfier = satisfy::SigErrors::new(satisfier);
+        let safe = self.ty.mall.safe;
+        let sat = satisfy::Satisfaction::satisfy_randomized(&self.node, &satisfier, safe, rng);
+        match sat.stack {
             satisfy::Witness::Stack(stack) => {
                 Ctx::check_witness::<Pk, Ctx>(&stack)?;
                 Ok(stack)
             }
             satisfy::Witness::Unavailable | satisfy::Witness::Impossible => {
+                satisfier.check()?;
                 Err(Error::CouldNotSatisfy)
             }
         }
@@ -347,16 +496,567 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     where
         Pk: ToPublicKey,
     {
-        match satisfy::Satisfaction::satisfy_mall(&self.node, &satisfier, self.ty.mall.safe).stack {
+        let satisfier = satisfy::SigErrors::new(satisfier);
+        let sat = satisfy::Satisfaction::satisfy_mall(&self.node, &satisfier, self.ty.mall.safe);
+        match sat.stack {
+            satisfy::Witness::Stack(stack) => {
+                Ctx::check_witness::<Pk, Ctx>(&stack)?;
+                Ok(stack)
+            }
+            satisfy::Witness::Unavailable | satisfy::Witness::Impossible => {
+                satisfier.check()?;
+                Err(Error::CouldNotSatisfy)
+            }
+        }
+    }
+
+    /// Attempt to produce a witness which makes the script represented by the
+    /// parse tree evaluate to false, for testing that invalid spends are
+    /// rejected. Fails for scripts which cannot be dissatisfied, such as
+    /// those ending in a timelock or a `VERIFY`.
+    pub fn dissatisfy<S: satisfy::Satisfier<Pk>>(
+        &self,
+        satisfier: S,
+    ) -> Result<Vec<Vec<u8>>, Error>
+    where
+        Pk: ToPublicKey,
+    {
+        match satisfy::Satisfaction::dissatisfy(&self.node, &satisfier, self.ty.mall.safe).stack {
+            satisfy::Witness::Stack(stack) => {
+                Ctx::check_witness::<Pk, Ctx>(&stack)?;
+                Ok(stack)
+            }
+            satisfy::Witness::Unavailable | satisfy::Witness::Impossible => {
+                Err(Error::CouldNotDissatisfy)
+            }
+        }
+    }
+
+    /// Attempt to produce a non-malleable satisfying witness as `satisfy`
+    /// does, and report the decisions made on the way: which branch of each
+    /// or-like fragment was used and why, and which subs of each `thresh`
+    /// were satisfied. The witness is `None` if no satisfaction was found,
+    /// including when that is because the satisfier's `try_lookup_sig`
+    /// failed.
+    pub fn satisfy_logged<S: satisfy::Satisfier<Pk>>(
+        &self,
+        satisfier: S,
+    ) -> (Option<Vec<Vec<u8>>>, satisfy::SatisfyLog)
+    where
+        Pk: ToPublicKey,
+    {
+        let mut term_ids = HashMap::new();
+        let mut thresh_ids = HashMap::new();
+        for (i, ms) in self.iter().enumerate() {
+            term_ids.insert(&ms.node as *const Terminal<Pk, Ctx>, satisfy::FragmentId(i));
+            if let Terminal::Thresh(_, ref subs) = ms.node {
+                thresh_ids.insert(subs.as_ptr(), satisfy::FragmentId(i));
+            }
+        }
+        let satisfier = satisfy::SigErrors::new(satisfier);
+        let (sat, log) = satisfy::Satisfaction::satisfy_logged(
+            &self.node,
+            &satisfier,
+            self.ty.mall.safe,
+            |term| term_ids.get(&(term as *const _)).cloned(),
+            |subs| thresh_ids.get(&subs.as_ptr()).cloned(),
+        );
+        let witness = match sat.stack {
+            satisfy::Witness::Stack(stack) => {
+                Ctx::check_witness::<Pk, Ctx>(&stack).ok().map(|_| stack)
+            }
+            satisfy::Witness::Unavailable | satisfy::Witness::Impossible => None,
+        };
+        (witness, log)
+    }
+
+    /// Attempt to produce a satisfying witness in which the `thresh`
+    /// fragments are satisfied by exactly the subs containing the keys of
+    /// `quorum`, e.g. "Alice and Bob sign" for a board of signers, with their
+    /// other subs dissatisfied. Any `thresh` without a quorum key is
+    /// satisfied as in `satisfy`.
+    ///
+    /// Returns `None` if a quorum key appears in no `thresh`, if the quorum
+    /// does not pick out exactly `k` subs of every `thresh` it touches, if
+    /// the resulting witness cannot be produced, for instance because the
+    /// satisfier's `try_lookup_sig` failed.
+    pub fn satisfy_thresh_with_keys<S: satisfy::Satisfier<Pk>>(
+        &self,
+        satisfier: S,
+        quorum: &[Pk],
+    ) -> Option<Vec<Vec<u8>>>
+    where
+        Pk: ToPublicKey,
+    {
+        let mut choices = HashMap::new();
+        let mut covered = vec![false; quorum.len()];
+        for ms in self.iter() {
+            if let Terminal::Thresh(k, ref subs) = ms.node {
+                let mut chosen = vec![];
+                for (i, sub) in subs.iter().enumerate() {
+                    let mut in_quorum = false;
+                    for pk in sub.iter_pk() {
+                        if let Some(pos) = quorum.iter().position(|q| *q == pk) {
+                            covered[pos] = true;
+                            in_quorum = true;
+                        }
+                    }
+                    if in_quorum {
+                        chosen.push(i);
+                    }
+                }
+                if chosen.is_empty() {
+                    continue;
+                }
+                if chosen.len() != k {
+                    return None;
+                }
+                choices.insert(subs.as_ptr(), chosen);
+            }
+        }
+        if covered.iter().any(|c| !c) {
+            return None;
+        }
+        let satisfier = satisfy::SigErrors::new(satisfier);
+        let sat = satisfy::Satisfaction::satisfy_with_thresh_choice(
+            &self.node,
+            &satisfier,
+            self.ty.mall.safe,
+            &|subs: &[Arc<Miniscript<Pk, Ctx>>]| choices.get(&subs.as_ptr()).cloned(),
+        );
+        match sat.stack {
+            satisfy::Witness::Stack(stack) => {
+                Ctx::check_witness::<Pk, Ctx>(&stack).ok().map(|_| stack)
+            }
+            satisfy::Witness::Unavailable | satisfy::Witness::Impossible => None,
+        }
+    }
+
+    /// Attempt to produce a satisfying witness, letting the caller pick which
+    /// branch of `or_b`, `or_c`, `or_d`, `or_i` and `andor` fragments to
+    /// take. Fragments are identified by their position in `iter`; when the
+    /// selector returns `None` the branch is chosen as in `satisfy`.
+    ///
+    /// A chosen branch is taken even if it is more expensive or malleable
+    /// than the alternative, and an error is returned if it cannot be satisfied.
+    pub fn satisfy_branch<S: satisfy::Satisfier<Pk>>(
+        &self,
+        satisfier: S,
+        branch_selector: &Fn(&satisfy::FragmentId) -> Option<satisfy::BranchChoice>,
+    ) -> Result<Vec<Vec<u8>>, Error>
+    where
+        Pk: ToPublicKey,
+    {
+        let ids: HashMap<*const Terminal<Pk, Ctx>, satisfy::FragmentId> = self
+            .iter()
+            .enumerate()
+            .map(|(i, ms)| (&ms.node as *const _, satisfy::FragmentId(i)))
+            .collect();
+        let mut choose = |term: &Terminal<Pk, Ctx>| {
+            ids.get(&(term as *const _))
+                .and_then(|id| branch_selector(id))
+        };
+        let satisfier = satisfy::SigErrors::new(satisfier);
+        let sat = satisfy::Satisfaction::satisfy_with_choice(
+            &self.node,
+            &satisfier,
+            self.ty.mall.safe,
+            &mut choose,
+        );
+        match sat.stack {
             satisfy::Witness::Stack(stack) => {
                 Ctx::check_witness::<Pk, Ctx>(&stack)?;
                 Ok(stack)
             }
             satisfy::Witness::Unavailable | satisfy::Witness::Impossible => {
+                satisfier.check()?;
                 Err(Error::CouldNotSatisfy)
             }
         }
     }
+
+    /// Attempt to produce a witness for the branch containing `older(n)`,
+    /// such as the recovery path of a vault, even when a cheaper path is
+    /// also satisfiable. At every disjunction the branch containing the
+    /// timelock is taken; elsewhere branches are chosen as in `satisfy`.
+    ///
+    /// Returns the witness together with the `nSequence` the spending
+    /// input must carry, or an error if no `older(n)` fragment exists or
+    /// the chosen branch cannot be satisfied.
+    pub fn satisfy_path_by_timelock<S: satisfy::Satisfier<Pk>>(
+        &self,
+        satisfier: S,
+        n: u32,
+    ) -> Result<(Vec<Vec<u8>>, u32), Error>
+    where
+        Pk: ToPublicKey,
+    {
+        let choices = self.branches_toward(|node| match *node {
+            Terminal::Older(t) => t == n,
+            _ => false,
+        });
+        let choices = choices.ok_or(Error::CouldNotSatisfy)?;
+        let witness = self.satisfy_branch(satisfier, &|id| choices.get(id).cloned())?;
+        Ok((witness, n))
+    }
+
+    /// Attempt to produce a witness revealing the preimage of `hash`, e.g.
+    /// for a watchtower whose claim transaction must put it on-chain as proof
+    /// of a breach, even when a cheaper path, such as a signature, is also
+    /// satisfiable. At every disjunction the branch containing the hashlock
+    /// is taken; elsewhere branches are chosen as in `satisfy`.
+    ///
+    /// Returns `None` if no such hashlock exists, or if the satisfier cannot
+    /// produce a witness in which the preimage appears.
+    pub fn satisfy_revealing_preimage<S: satisfy::Satisfier<Pk>>(
+        &self,
+        satisfier: S,
+        hash: HashLockType,
+    ) -> Option<Vec<Vec<u8>>>
+    where
+        Pk: ToPublicKey,
+    {
+        let choices = self.branches_toward(|node| match (node, hash) {
+            (&Terminal::Sha256(ref h), HashLockType::Sha256(x)) => h == x,
+            (&Terminal::Hash256(ref h), HashLockType::Hash256(x)) => h == x,
+            (&Terminal::Ripemd160(ref h), HashLockType::Ripemd160(x)) => h == x,
+            (&Terminal::Hash160(ref h), HashLockType::Hash160(x)) => h == x,
+            _ => false,
+        })?;
+        let preimage = match hash {
+            HashLockType::Sha256(h) => satisfier.lookup_sha256(*h),
+            HashLockType::Hash256(h) => satisfier.lookup_hash256(*h),
+            HashLockType::Ripemd160(h) => satisfier.lookup_ripemd160(*h),
+            HashLockType::Hash160(h) => satisfier.lookup_hash160(*h),
+        }?;
+        let witness = self
+            .satisfy_branch(satisfier, &|id| choices.get(id).cloned())
+            .ok()?;
+        if witness.iter().any(|elem| elem[..] == preimage[..]) {
+            Some(witness)
+        } else {
+            None
+        }
+    }
+
+    /// For each disjunction with exactly one branch containing a fragment
+    /// matching `target`, that branch, keyed by the position of the
+    /// disjunction in `iter`. Returns `None` if no fragment matches.
+    fn branches_toward<F>(
+        &self,
+        target: F,
+    ) -> Option<HashMap<satisfy::FragmentId, satisfy::BranchChoice>>
+    where
+        F: Fn(&Terminal<Pk, Ctx>) -> bool,
+    {
+        let contains = |ms: &Miniscript<Pk, Ctx>| ms.iter().any(|sub| target(&sub.node));
+        if !contains(self) {
+            return None;
+        }
+        let choices = self
+            .iter()
+            .enumerate()
+            .filter_map(|(i, ms)| {
+                let (left, right) = match ms.node {
+                    Terminal::OrB(ref l, ref r)
+                    | Terminal::OrC(ref l, ref r)
+                    | Terminal::OrD(ref l, ref r)
+                    | Terminal::OrI(ref l, ref r) => (contains(l), contains(r)),
+                    Terminal::AndOr(ref a, ref b, ref c) => {
+                        (contains(a) || contains(b), contains(c))
+                    }
+                    _ => return None,
+                };
+                match (left, right) {
+                    (true, false) => Some((satisfy::FragmentId(i), satisfy::BranchChoice::Left)),
+                    (false, true) => Some((satisfy::FragmentId(i), satisfy::BranchChoice::Right)),
+                    _ => None,
+                }
+            })
+            .collect();
+        Some(choices)
+    }
+
+    /// Collect every signature the satisfier has for keys in this Miniscript,
+    /// as `(pubkey, signature)` pairs with the sighash byte appended to the
+    /// DER signature. This is the format of a PSBT input's `partial_sigs`
+    /// map; unlike `satisfy` it does not require a complete satisfaction.
+    pub fn collect_signatures<S: satisfy::Satisfier<Pk>>(
+        &self,
+        satisfier: S,
+    ) -> Vec<(dogecoin::PublicKey, Vec<u8>)>
+    where
+        Pk: ToPublicKey,
+    {
+        fn push_sig(
+            ret: &mut Vec<(dogecoin::PublicKey, Vec<u8>)>,
+            pk: dogecoin::PublicKey,
+            sig: satisfy::BitcoinSig,
+        ) {
+            if ret.iter().any(|&(ref k, _)| *k == pk) {
+                return;
+            }
+            let mut sigser = sig.0.serialize_der().to_vec();
+            sigser.push(sig.1.as_u32() as u8);
+            ret.push((pk, sigser));
+        }
+
+        let mut ret = vec![];
+        for ms in self.iter() {
+            match ms.node {
+                Terminal::PkK(ref pk) => {
+                    if let Some(sig) = satisfier.lookup_sig(pk) {
+                        push_sig(&mut ret, pk.to_public_key(), sig);
+                    }
+                }
+                Terminal::PkH(ref pkh) => {
+                    if let Some((pk, sig)) = satisfier.lookup_pkh_sig(pkh) {
+                        push_sig(&mut ret, pk, sig);
+                    }
+                }
+                Terminal::Multi(_, ref keys) => {
+                    for pk in keys {
+                        if let Some(sig) = satisfier.lookup_sig(pk) {
+                            push_sig(&mut ret, pk.to_public_key(), sig);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        ret
+    }
+
+    /// Hashlocks whose preimage is pushed more than once by some satisfaction
+    /// of this Miniscript, because the same hash is checked by several
+    /// fragments which can all be satisfied together. Such scripts bloat the
+    /// witness with copies of one preimage. Hashlocks committing to the same
+    /// secret under different hash types cannot be detected this way.
+    pub fn duplicate_preimage_pushes(&self) -> Vec<HashLockType> {
+        let max_pushes = self.max_preimage_pushes();
+        let mut ret = vec![];
+        for ms in self.iter() {
+            let hash = match ms.node {
+                Terminal::Sha256(ref h) => HashLockType::Sha256(h),
+                Terminal::Hash256(ref h) => HashLockType::Hash256(h),
+                Terminal::Ripemd160(ref h) => HashLockType::Ripemd160(h),
+                Terminal::Hash160(ref h) => HashLockType::Hash160(h),
+                _ => continue,
+            };
+            if max_pushes[&hash] > 1 && !ret.contains(&hash) {
+                ret.push(hash);
+            }
+        }
+        ret
+    }
+
+    /// For every hashlock, the most times its preimage is pushed by a single
+    /// satisfaction of this fragment
+    fn max_preimage_pushes(&self) -> HashMap<HashLockType, usize> {
+        fn sum<'a>(
+            mut a: HashMap<HashLockType<'a>, usize>,
+            b: HashMap<HashLockType<'a>, usize>,
+        ) -> HashMap<HashLockType<'a>, usize> {
+            for (hash, n) in b {
+                *a.entry(hash).or_insert(0) += n;
+            }
+            a
+        }
+
+        fn max<'a>(
+            mut a: HashMap<HashLockType<'a>, usize>,
+            b: HashMap<HashLockType<'a>, usize>,
+        ) -> HashMap<HashLockType<'a>, usize> {
+            for (hash, n) in b {
+                let entry = a.entry(hash).or_insert(0);
+                *entry = cmp::max(*entry, n);
+            }
+            a
+        }
+
+        let mut ret = HashMap::new();
+        match self.node {
+            Terminal::Sha256(ref h) => {
+                ret.insert(HashLockType::Sha256(h), 1);
+            }
+            Terminal::Hash256(ref h) => {
+                ret.insert(HashLockType::Hash256(h), 1);
+            }
+            Terminal::Ripemd160(ref h) => {
+                ret.insert(HashLockType::Ripemd160(h), 1);
+            }
+            Terminal::Hash160(ref h) => {
+                ret.insert(HashLockType::Hash160(h), 1);
+            }
+            Terminal::Alt(ref sub)
+            | Terminal::Swap(ref sub)
+            | Terminal::Check(ref sub)
+            | Terminal::DupIf(ref sub)
+            | Terminal::Verify(ref sub)
+            | Terminal::NonZero(ref sub)
+            | Terminal::ZeroNotEqual(ref sub) => return sub.max_preimage_pushes(),
+            Terminal::AndV(ref left, ref right) | Terminal::AndB(ref left, ref right) => {
+                return sum(left.max_preimage_pushes(), right.max_preimage_pushes());
+            }
+            Terminal::AndOr(ref a, ref b, ref c) => {
+                return max(
+                    sum(a.max_preimage_pushes(), b.max_preimage_pushes()),
+                    c.max_preimage_pushes(),
+                );
+            }
+            // Only one branch of a disjunction is ever satisfied
+            Terminal::OrB(ref left, ref right)
+            | Terminal::OrD(ref left, ref right)
+            | Terminal::OrC(ref left, ref right)
+            | Terminal::OrI(ref left, ref right) => {
+                return max(left.max_preimage_pushes(), right.max_preimage_pushes());
+            }
+            // Exactly k subs are satisfied, so each hash is pushed at most
+            // as often as by the k subs pushing it the most
+            Terminal::Thresh(k, ref subs) => {
+                let sub_pushes: Vec<_> = subs.iter().map(|s| s.max_preimage_pushes()).collect();
+                for pushes in &sub_pushes {
+                    for hash in pushes.keys() {
+                        if ret.contains_key(hash) {
+                            continue;
+                        }
+                        let mut counts: Vec<usize> = sub_pushes
+                            .iter()
+                            .map(|p| p.get(hash).map(|n| *n).unwrap_or(0))
+                            .collect();
+                        counts.sort_by(|a, b| b.cmp(a));
+                        ret.insert(*hash, counts.iter().take(k).sum());
+                    }
+                }
+            }
+            Terminal::True
+            | Terminal::False
+            | Terminal::PkK(..)
+            | Terminal::PkH(..)
+            | Terminal::After(..)
+            | Terminal::Older(..)
+            | Terminal::Multi(..) => {}
+        }
+        ret
+    }
+
+    /// The hashlocks whose preimages the cheapest satisfaction of this
+    /// Miniscript reveals, so a satisfier can fetch only those preimages
+    /// from an expensive store. Branches are chosen by their maximum
+    /// witness size alone, as if every key could sign and every preimage
+    /// were known, so a satisfier missing some condition of the chosen
+    /// branch may end up using a different one. Returns nothing if the
+    /// Miniscript cannot be satisfied at all.
+    pub fn required_preimages(&self) -> Vec<HashLockType> {
+        let mut ret = vec![];
+        if self.ext.max_sat_size.is_some() {
+            self.push_required_preimages(&mut ret);
+        }
+        ret
+    }
+
+    /// Push the hashlocks satisfied by the cheapest satisfaction of this
+    /// fragment onto `ret`
+    fn push_required_preimages<'a>(&'a self, ret: &mut Vec<HashLockType<'a>>) {
+        fn sat<Pk: MiniscriptKey, Ctx: ScriptContext>(ms: &Miniscript<Pk, Ctx>) -> Option<usize> {
+            ms.ext.max_sat_size.map(|s| s.0)
+        }
+        fn dissat<Pk: MiniscriptKey, Ctx: ScriptContext>(
+            ms: &Miniscript<Pk, Ctx>,
+        ) -> Option<usize> {
+            ms.ext.max_dissat_size.map(|s| s.0)
+        }
+        fn add(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+            Some(a? + b?)
+        }
+        // Whether the first option is no more expensive than the second,
+        // where an impossible option is the most expensive
+        fn cheaper(a: Option<usize>, b: Option<usize>) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => a <= b,
+                (_, None) => true,
+                (None, Some(_)) => false,
+            }
+        }
+
+        let hash = match self.node {
+            Terminal::Sha256(ref h) => HashLockType::Sha256(h),
+            Terminal::Hash256(ref h) => HashLockType::Hash256(h),
+            Terminal::Ripemd160(ref h) => HashLockType::Ripemd160(h),
+            Terminal::Hash160(ref h) => HashLockType::Hash160(h),
+            Terminal::Alt(ref sub)
+            | Terminal::Swap(ref sub)
+            | Terminal::Check(ref sub)
+            | Terminal::DupIf(ref sub)
+            | Terminal::Verify(ref sub)
+            | Terminal::NonZero(ref sub)
+            | Terminal::ZeroNotEqual(ref sub) => return sub.push_required_preimages(ret),
+            Terminal::AndV(ref left, ref right) | Terminal::AndB(ref left, ref right) => {
+                left.push_required_preimages(ret);
+                return right.push_required_preimages(ret);
+            }
+            // Dissatisfactions never reveal a preimage
+            Terminal::AndOr(ref a, ref b, ref c) => {
+                if cheaper(add(sat(a), sat(b)), add(dissat(a), sat(c))) {
+                    a.push_required_preimages(ret);
+                    return b.push_required_preimages(ret);
+                } else {
+                    return c.push_required_preimages(ret);
+                }
+            }
+            Terminal::OrB(ref left, ref right) => {
+                if cheaper(add(sat(left), dissat(right)), add(dissat(left), sat(right))) {
+                    return left.push_required_preimages(ret);
+                } else {
+                    return right.push_required_preimages(ret);
+                }
+            }
+            Terminal::OrD(ref left, ref right) | Terminal::OrC(ref left, ref right) => {
+                if cheaper(sat(left), add(dissat(left), sat(right))) {
+                    return left.push_required_preimages(ret);
+                } else {
+                    return right.push_required_preimages(ret);
+                }
+            }
+            Terminal::OrI(ref left, ref right) => {
+                if cheaper(sat(left), sat(right)) {
+                    return left.push_required_preimages(ret);
+                } else {
+                    return right.push_required_preimages(ret);
+                }
+            }
+            // Satisfy the k subs which cost the least over their
+            // dissatisfaction, as `satisfy` does
+            Terminal::Thresh(k, ref subs) => {
+                let mut costs: Vec<_> = subs
+                    .iter()
+                    .filter_map(|sub| {
+                        let weight = match (sat(sub), dissat(sub)) {
+                            (None, _) => return None,
+                            (Some(_), None) => i64::MIN,
+                            (Some(s), Some(d)) => s as i64 - d as i64,
+                        };
+                        Some((weight, sub))
+                    })
+                    .collect();
+                costs.sort_by_key(|&(weight, _)| weight);
+                for &(_, sub) in costs.iter().take(k) {
+                    sub.push_required_preimages(ret);
+                }
+                return;
+            }
+            Terminal::True
+            | Terminal::False
+            | Terminal::PkK(..)
+            | Terminal::PkH(..)
+            | Terminal::After(..)
+            | Terminal::Older(..)
+            | Terminal::Multi(..) => return,
+        };
+        if !ret.contains(&hash) {
+            ret.push(hash);
+        }
+    }
 }
 
 impl<Pk, Ctx> expression::FromTree for Arc<Miniscript<Pk, Ctx>>
@@ -421,13 +1121,16 @@ mod tests {
     use super::{Miniscript, ScriptContext};
     use hex_script;
     use miniscript::types::{self, ExtData, Property, Type};
+    use interpreter::HashLockType;
     use miniscript::Terminal;
     use policy::Liftable;
     use std::marker::PhantomData;
-    use {DummyKey, DummyKeyHash, MiniscriptKey, TranslatePk, TranslatePk1};
+    use test_key;
+    use {BitcoinSig, DummyKey, DummyKeyHash, MiniscriptKey, TranslatePk, TranslatePk1};
 
     use dogecoin::hashes::{hash160, sha256, Hash};
     use dogecoin::{self, secp256k1};
+    use std::collections::HashMap;
     use std::str;
     use std::str::FromStr;
     use std::sync::Arc;
@@ -455,6 +1158,29 @@ mod tests {
         ret
     }
 
+    /// A signature over a fixed message, valid for none of the test keys,
+    /// and its DER serialization with a SIGHASH_ALL byte appended
+    fn dummy_sig() -> (secp256k1::Signature, Vec<u8>) {
+        let secp = secp256k1::Secp256k1::signing_only();
+        let sig = secp.sign(
+            &secp256k1::Message::from_slice(&[1; 32][..]).unwrap(),
+            &test_key().0,
+        );
+        let mut sigser = sig.serialize_der().to_vec();
+        sigser.push(0x01);
+        (sig, sigser)
+    }
+
+    /// A satisfier with the signature `sig`, under SIGHASH_ALL, for each key
+    fn sig_map(
+        keys: &[dogecoin::PublicKey],
+        sig: secp256k1::Signature,
+    ) -> HashMap<dogecoin::PublicKey, BitcoinSig> {
+        keys.iter()
+            .map(|pk| (*pk, (sig, dogecoin::SigHashType::All)))
+            .collect()
+    }
+
     fn string_rtt<Pk, Ctx, Str1, Str2>(
         script: Miniscript<Pk, Ctx>,
         expected_debug: Str1,
@@ -907,4 +1633,1403 @@ mod tests {
             .to_string()
             .contains("unprintable character"));
     }
+
+    #[test]
+    fn collect_signatures() {
+        let keys = pubkeys(3);
+        let (sig, _) = dummy_sig();
+
+        let mut sigs = sig_map(&keys[..1], sig);
+        sigs.insert(keys[2], (sig, dogecoin::SigHashType::Single));
+
+        // Only one of the sigs is usable for a 2-of-2, but both are collected
+        let ms: Segwitv0Script = ms_str!(
+            "or_d(multi(2,{},{}),pk({}))",
+            keys[0],
+            keys[1],
+            keys[2]
+        );
+        assert!(ms.satisfy(&sigs).is_ok());
+        let collected = ms.collect_signatures(&sigs);
+        assert_eq!(collected.len(), 2);
+
+        let mut sig_all = sig.serialize_der().to_vec();
+        sig_all.push(0x01);
+        let mut sig_single = sig.serialize_der().to_vec();
+        sig_single.push(0x03);
+        assert_eq!(collected[0], (keys[0], sig_all));
+        assert_eq!(collected[1], (keys[2], sig_single));
+
+        let ms: Segwitv0Script = ms_str!("and_v(v:pk({}),pk({}))", keys[1], keys[1]);
+        assert!(ms.collect_signatures(&sigs).is_empty());
+    }
+
+    #[test]
+    fn preimage_fn_satisfier() {
+        use interpreter::HashLockType;
+        use miniscript::satisfy::PreimageFn;
+
+        let preimage = [0xab; 32];
+        let sha = sha256::Hash::hash(&preimage);
+        let h160 = hash160::Hash::hash(&preimage);
+        let ms: Segwitv0Script = ms_str!("and_v(v:sha256({}),hash160({}))", sha, h160);
+
+        let sat = PreimageFn::new(|h| match h {
+            HashLockType::Sha256(h) if *h == sha => Some(preimage),
+            HashLockType::Hash160(h) if *h == h160 => Some(preimage),
+            _ => None,
+        });
+        assert_eq!(
+            ms.satisfy(&sat).unwrap(),
+            vec![preimage.to_vec(), preimage.to_vec()]
+        );
+
+        let sat = PreimageFn::new(|h| match h {
+            HashLockType::Sha256(_) => Some(preimage),
+            _ => None,
+        });
+        assert!(ms.satisfy(&sat).is_err());
+    }
+
+    #[test]
+    fn shared_preimage_cache() {
+        use miniscript::satisfy::SharedPreimageCache;
+
+        let preimage = [0xab; 32];
+        let sha = sha256::Hash::hash(&preimage);
+        let h160 = hash160::Hash::hash(&preimage);
+        let ms: Segwitv0Script = ms_str!("and_v(v:sha256({}),hash160({}))", sha, h160);
+
+        let cache = SharedPreimageCache::new();
+        let clone = cache.clone();
+        assert!(ms.satisfy(&clone).is_err());
+        cache.insert(preimage);
+        assert_eq!(
+            ms.satisfy(&clone).unwrap(),
+            vec![preimage.to_vec(), preimage.to_vec()]
+        );
+    }
+
+    #[test]
+    fn andor_branch_selection() {
+        let keys = pubkeys(3);
+        let (a, b, c) = (keys[0], keys[1], keys[2]);
+        let (sig, sigser) = dummy_sig();
+
+        let ms: Segwitv0Script = ms_str!("andor(pk({}),pk({}),pk({}))", a, b, c);
+        let then_wit = vec![sigser.clone(), sigser.clone()];
+        let else_wit = vec![sigser.clone(), vec![]];
+        let cases: Vec<(Vec<dogecoin::PublicKey>, Option<Vec<Vec<u8>>>)> = vec![
+            (vec![], None),
+            (vec![a], None),
+            (vec![b], None),
+            (vec![a, b], Some(then_wit.clone())),
+            (vec![c], Some(else_wit.clone())),
+            (vec![a, c], Some(else_wit.clone())),
+            (vec![b, c], Some(else_wit.clone())),
+            // Both branches available, the else branch is cheaper
+            (vec![a, b, c], Some(else_wit.clone())),
+        ];
+        for (avail, expected) in cases {
+            let sigs = sig_map(&avail, sig);
+            assert_eq!(ms.satisfy(&sigs).ok(), expected, "keys {:?}", avail);
+            assert_eq!(ms.satisfy_malleable(&sigs).ok(), expected, "keys {:?}", avail);
+        }
+
+        // If the else branch needs no signature, a third party who knows the
+        // preimage could malleate the "then" satisfaction into it, so it is
+        // only produced when malleability is allowed
+        let hash = sha256::Hash::hash(&[0xab; 32]);
+        let ms: Segwitv0Script = ms_str!("andor(pk({}),pk({}),sha256({}))", a, b, hash);
+        let sigs = sig_map(&[a, b], sig);
+        assert!(ms.satisfy(&sigs).is_err());
+        assert_eq!(ms.satisfy_malleable(&sigs).unwrap(), then_wit);
+    }
+
+    #[test]
+    fn aggregate_locktimes() {
+        use miniscript::satisfy::aggregate_locktimes;
+
+        assert_eq!(aggregate_locktimes(&[]).unwrap(), 0);
+        assert_eq!(aggregate_locktimes(&[100, 0, 300, 200]).unwrap(), 300);
+        assert_eq!(
+            aggregate_locktimes(&[500_000_001, 600_000_000]).unwrap(),
+            600_000_000
+        );
+        assert!(aggregate_locktimes(&[100, 500_000_000]).is_err());
+    }
+
+    #[test]
+    fn aggregate_relative_locktimes() {
+        use miniscript::satisfy::aggregate_relative_locktimes;
+
+        assert_eq!(aggregate_relative_locktimes(&[]).unwrap(), None);
+        assert_eq!(aggregate_relative_locktimes(&[10, 30, 20]).unwrap(), Some(30));
+        assert_eq!(
+            aggregate_relative_locktimes(&[0x0040_0002, 0x0040_0001]).unwrap(),
+            Some(0x0040_0002)
+        );
+        assert!(aggregate_relative_locktimes(&[10, 0x0040_0001]).is_err());
+    }
+
+    #[test]
+    fn boxed_satisfier() {
+        use miniscript::satisfy::{After, Older};
+        use Satisfier;
+
+        let ms: Segwitv0Script = ms_str!("and_v(v:after(100),older(10))");
+        let sats: Vec<Box<Satisfier<dogecoin::PublicKey>>> =
+            vec![Box::new(After(100)), Box::new(Older(10))];
+        assert!(ms.satisfy((&sats[0], &sats[1])).is_ok());
+        assert!(ms.satisfy(&sats[0]).is_err());
+    }
+
+    #[test]
+    fn satisfy_branch() {
+        use miniscript::satisfy::{BranchChoice, FragmentId, Older};
+
+        let keys = pubkeys(2);
+        let (sig, sigser) = dummy_sig();
+        let sigs = sig_map(&keys[..2], sig);
+
+        // HTLC-like: either key 0, or key 1 after a timeout
+        let ms: Segwitv0Script = ms_str!(
+            "or_i(pk({}),and_v(v:pk({}),older(10)))",
+            keys[0],
+            keys[1]
+        );
+        let sat = (&sigs, Older(10));
+        // The timeout branch is cheaper, so is picked by default
+        assert_eq!(ms.satisfy(&sat).unwrap(), vec![sigser.clone(), vec![]]);
+        assert_eq!(
+            ms.satisfy_branch(&sat, &|_| None).unwrap(),
+            vec![sigser.clone(), vec![]]
+        );
+
+        let left = |id: &FragmentId| {
+            if *id == FragmentId(0) {
+                Some(BranchChoice::Left)
+            } else {
+                None
+            }
+        };
+        assert_eq!(
+            ms.satisfy_branch(&sat, &left).unwrap(),
+            vec![sigser.clone(), vec![1]]
+        );
+
+        // A chosen branch is never swapped for the other one
+        let sigs = sig_map(&keys[1..], sig);
+        let sat = (&sigs, Older(10));
+        assert!(ms.satisfy(&sat).is_ok());
+        assert!(ms.satisfy_branch(&sat, &left).is_err());
+    }
+
+    #[test]
+    fn preimage_whitelist() {
+        use miniscript::satisfy::{Preimage32, Satisfier};
+        use std::collections::HashSet;
+
+        struct HtlcSat {
+            preimages: HashMap<sha256::Hash, Preimage32>,
+            whitelist: Option<HashSet<Preimage32>>,
+        }
+
+        impl Satisfier<dogecoin::PublicKey> for HtlcSat {
+            fn lookup_sha256(&self, h: sha256::Hash) -> Option<Preimage32> {
+                self.preimages.get(&h).map(|x| *x)
+            }
+
+            fn preimage_whitelist(&self) -> Option<&HashSet<Preimage32>> {
+                self.whitelist.as_ref()
+            }
+        }
+
+        let (secret_a, secret_b) = ([0xaa; 32], [0xbb; 32]);
+        let (hash_a, hash_b) = (sha256::Hash::hash(&secret_a), sha256::Hash::hash(&secret_b));
+        let ms: Segwitv0Script = ms_str!("or_i(sha256({}),sha256({}))", hash_a, hash_b);
+
+        let mut preimages = HashMap::new();
+        preimages.insert(hash_a, secret_a);
+        preimages.insert(hash_b, secret_b);
+        let mut sat = HtlcSat {
+            preimages: preimages,
+            whitelist: None,
+        };
+        // Without a whitelist, both preimages are known; neither branch has a
+        // signature so only a malleable satisfaction is possible
+        let wit = ms.satisfy_malleable(&sat).unwrap();
+        assert!(wit[0] == secret_a.to_vec() || wit[0] == secret_b.to_vec());
+
+        let mut whitelist = HashSet::new();
+        whitelist.insert(secret_b);
+        sat.whitelist = Some(whitelist);
+        assert_eq!(
+            ms.satisfy_malleable(&sat).unwrap(),
+            vec![secret_b.to_vec(), vec![]]
+        );
+
+        sat.whitelist = Some(HashSet::new());
+        assert!(ms.satisfy_malleable(&sat).is_err());
+    }
+
+    #[test]
+    fn tuple_satisfier_precedence() {
+        use miniscript::satisfy::{BitcoinSig, Preimage32, Satisfier};
+        use dogecoin::hashes::{ripemd160, sha256d};
+        use std::collections::HashSet;
+
+        // Answers every query with data identifying which element it is
+        struct Tagged {
+            tag: u8,
+            key: dogecoin::PublicKey,
+            sig: BitcoinSig,
+            whitelist: HashSet<Preimage32>,
+        }
+
+        impl Tagged {
+            fn new(tag: u8) -> Tagged {
+                let secp = secp256k1::Secp256k1::new();
+                let sig = secp.sign(
+                    &secp256k1::Message::from_slice(&[tag; 32][..]).unwrap(),
+                    &test_key().0,
+                );
+                let mut whitelist = HashSet::new();
+                whitelist.insert([tag; 32]);
+                Tagged {
+                    tag: tag,
+                    key: pubkeys(tag as usize + 1)[tag as usize],
+                    sig: (sig, dogecoin::SigHashType::All),
+                    whitelist: whitelist,
+                }
+            }
+        }
+
+        impl Satisfier<dogecoin::PublicKey> for Tagged {
+            fn lookup_sig(&self, _: &dogecoin::PublicKey) -> Option<BitcoinSig> {
+                Some(self.sig)
+            }
+
+            fn lookup_pkh_pk(&self, _: &hash160::Hash) -> Option<dogecoin::PublicKey> {
+                Some(self.key)
+            }
+
+            fn lookup_pkh_sig(
+                &self,
+                _: &hash160::Hash,
+            ) -> Option<(dogecoin::PublicKey, BitcoinSig)> {
+                Some((self.key, self.sig))
+            }
+
+            fn lookup_sha256(&self, _: sha256::Hash) -> Option<Preimage32> {
+                Some([self.tag; 32])
+            }
+
+            fn lookup_hash256(&self, _: sha256d::Hash) -> Option<Preimage32> {
+                Some([self.tag; 32])
+            }
+
+            fn lookup_ripemd160(&self, _: ripemd160::Hash) -> Option<Preimage32> {
+                Some([self.tag; 32])
+            }
+
+            fn lookup_hash160(&self, _: hash160::Hash) -> Option<Preimage32> {
+                Some([self.tag; 32])
+            }
+
+            fn check_older(&self, n: u32) -> bool {
+                n == self.tag as u32
+            }
+
+            fn check_after(&self, n: u32) -> bool {
+                n == self.tag as u32
+            }
+
+            fn preimage_whitelist(&self) -> Option<&HashSet<Preimage32>> {
+                Some(&self.whitelist)
+            }
+        }
+
+        let (first, second) = (Tagged::new(1), Tagged::new(2));
+        let sat = ((), &first, &second);
+        let key = pubkeys(1)[0];
+        let keyhash = hash160::Hash::hash(&[0; 32]);
+
+        assert_eq!(sat.lookup_sig(&key), Some(first.sig));
+        assert_eq!(sat.lookup_pkh_pk(&keyhash), Some(first.key));
+        assert_eq!(sat.lookup_pkh_sig(&keyhash), Some((first.key, first.sig)));
+        assert_eq!(sat.lookup_sha256(sha256::Hash::hash(&[0; 32])), Some([1; 32]));
+        assert_eq!(sat.lookup_hash256(sha256d::Hash::hash(&[0; 32])), Some([1; 32]));
+        assert_eq!(
+            sat.lookup_ripemd160(ripemd160::Hash::hash(&[0; 32])),
+            Some([1; 32])
+        );
+        assert_eq!(sat.lookup_hash160(keyhash), Some([1; 32]));
+        assert_eq!(sat.preimage_whitelist(), Some(&first.whitelist));
+
+        // Reordering the elements flips the precedence
+        let sat = (&second, (), &first);
+        assert_eq!(sat.lookup_sig(&key), Some(second.sig));
+        assert_eq!(sat.lookup_pkh_pk(&keyhash), Some(second.key));
+        assert_eq!(sat.lookup_pkh_sig(&keyhash), Some((second.key, second.sig)));
+        assert_eq!(sat.lookup_sha256(sha256::Hash::hash(&[0; 32])), Some([2; 32]));
+        assert_eq!(sat.lookup_hash256(sha256d::Hash::hash(&[0; 32])), Some([2; 32]));
+        assert_eq!(
+            sat.lookup_ripemd160(ripemd160::Hash::hash(&[0; 32])),
+            Some([2; 32])
+        );
+        assert_eq!(sat.lookup_hash160(keyhash), Some([2; 32]));
+        assert_eq!(sat.preimage_whitelist(), Some(&second.whitelist));
+
+        // Timelocks are satisfied by whichever element accepts them
+        assert!(sat.check_older(1) && sat.check_older(2) && !sat.check_older(3));
+        assert!(sat.check_after(1) && sat.check_after(2) && !sat.check_after(3));
+    }
+
+    #[test]
+    fn ranged_signer() {
+        use dogecoin::util::bip32;
+        use miniscript::satisfy::{BitcoinSig, RangedSigner};
+
+        let secp = secp256k1::Secp256k1::new();
+        let xpriv = bip32::ExtendedPrivKey::new_master(dogecoin::Network::Bitcoin, &[7; 32])
+            .unwrap();
+        let xpub = bip32::ExtendedPubKey::from_private(&secp, &xpriv);
+        let path = bip32::DerivationPath::from_str("m/0/5").unwrap();
+        let child = xpub.derive_pub(&secp, &path).unwrap().public_key;
+        let (sig, sigser) = dummy_sig();
+
+        let ms: Segwitv0Script = ms_str!("pk({})", child);
+        let signer = RangedSigner::new(
+            &secp,
+            |pk: &dogecoin::PublicKey| {
+                if *pk == child {
+                    Some((xpub, path.clone()))
+                } else {
+                    None
+                }
+            },
+            |_: &bip32::ExtendedPubKey, p: &bip32::DerivationPath| {
+                assert_eq!(*p, path);
+                Some((sig, dogecoin::SigHashType::All))
+            },
+        );
+        assert_eq!(ms.satisfy(&signer).unwrap(), vec![sigser]);
+
+        // A mapping to the wrong index does not produce a signature
+        let wrong_path = bip32::DerivationPath::from_str("m/0/6").unwrap();
+        let signer = RangedSigner::new(
+            &secp,
+            |_: &dogecoin::PublicKey| Some((xpub, wrong_path.clone())),
+            |_: &bip32::ExtendedPubKey, _: &bip32::DerivationPath| -> Option<BitcoinSig> {
+                panic!("signed for a mismatched derivation")
+            },
+        );
+        assert!(ms.satisfy(&signer).is_err());
+    }
+
+    #[test]
+    fn blocking_signer() {
+        use miniscript::satisfy::BlockingSigner;
+
+        let secp = secp256k1::Secp256k1::new();
+        let (sk, pk) = test_key();
+        let other = pubkeys(1)[0];
+        let sighash = secp256k1::Message::from_slice(&[3; 32][..]).unwrap();
+        let mut sigser = secp.sign(&sighash, &sk).serialize_der().to_vec();
+        sigser.push(0x01);
+
+        // The callback signs whatever message it is given, for its own key
+        let signer = BlockingSigner::new(sighash, |key: &dogecoin::PublicKey, msg| {
+            if *key == pk {
+                Some((secp.sign(&msg, &sk), dogecoin::SigHashType::All))
+            } else {
+                None
+            }
+        });
+        let ms: Segwitv0Script = ms_str!("pk({})", pk);
+        assert_eq!(ms.satisfy(&signer).unwrap(), vec![sigser]);
+        let ms: Segwitv0Script = ms_str!("pk({})", other);
+        assert!(ms.satisfy(&signer).is_err());
+    }
+
+    #[test]
+    fn duplicate_preimage_pushes() {
+        let hash = sha256::Hash::hash(&[1; 32]);
+        let other = hash160::Hash::hash(&[1; 32]);
+
+        let ms: Segwitv0Script = ms_str!("and_v(v:sha256({}),sha256({}))", hash, hash);
+        assert_eq!(
+            ms.duplicate_preimage_pushes(),
+            vec![HashLockType::Sha256(&hash)]
+        );
+
+        // Distinct hashes, even of the same secret, are not reported
+        let ms: Segwitv0Script = ms_str!("and_v(v:sha256({}),hash160({}))", hash, other);
+        assert!(ms.duplicate_preimage_pushes().is_empty());
+
+        // Disjunctions only ever reveal one branch
+        let ms: Segwitv0Script = ms_str!("or_i(sha256({}),sha256({}))", hash, hash);
+        assert!(ms.duplicate_preimage_pushes().is_empty());
+
+        let ms: Segwitv0Script = ms_str!(
+            "thresh(1,sha256({}),a:sha256({}),a:hash160({}))",
+            hash,
+            hash,
+            other
+        );
+        assert!(ms.duplicate_preimage_pushes().is_empty());
+        let ms: Segwitv0Script = ms_str!(
+            "thresh(2,sha256({}),a:sha256({}),a:hash160({}))",
+            hash,
+            hash,
+            other
+        );
+        assert_eq!(
+            ms.duplicate_preimage_pushes(),
+            vec![HashLockType::Sha256(&hash)]
+        );
+    }
+
+    #[test]
+    fn sighash_byte_policy() {
+        use miniscript::satisfy::{BitcoinSig, SigHashBytePolicy};
+        use Satisfier;
+
+        struct PolicySat {
+            sigs: HashMap<dogecoin::PublicKey, BitcoinSig>,
+            policy: SigHashBytePolicy,
+        }
+
+        impl Satisfier<dogecoin::PublicKey> for PolicySat {
+            fn lookup_sig(&self, pk: &dogecoin::PublicKey) -> Option<BitcoinSig> {
+                self.sigs.get(pk).map(|x| *x)
+            }
+
+            fn sighash_byte_policy(&self) -> SigHashBytePolicy {
+                self.policy
+            }
+        }
+
+        let keys = pubkeys(2);
+        let (sig, _) = dummy_sig();
+        let mut sigs = HashMap::new();
+        sigs.insert(keys[0], (sig, dogecoin::SigHashType::Single));
+        sigs.insert(keys[1], (sig, dogecoin::SigHashType::All));
+
+        let ms: Segwitv0Script = ms_str!("or_d(pk({}),pk({}))", keys[0], keys[1]);
+        let mut sat = PolicySat {
+            sigs: sigs,
+            policy: SigHashBytePolicy::Append,
+        };
+        // The first key's SIGHASH_SINGLE signature is cheapest
+        let wit = ms.satisfy(&sat).unwrap();
+        assert_eq!(wit.len(), 1);
+        assert_eq!(*wit[0].last().unwrap(), 0x03);
+
+        sat.policy = SigHashBytePolicy::Require(dogecoin::SigHashType::All);
+        let wit = ms.satisfy(&sat).unwrap();
+        assert_eq!(wit.len(), 2);
+        assert_eq!(*wit[0].last().unwrap(), 0x01);
+        assert!(wit[1].is_empty());
+
+        sat.policy = SigHashBytePolicy::Require(dogecoin::SigHashType::None);
+        assert!(ms.satisfy(&sat).is_err());
+    }
+
+    #[test]
+    fn lookup_sig_for_type() {
+        use miniscript::satisfy::{BitcoinSig, SigHashBytePolicy};
+        use Satisfier;
+
+        struct MultiTypeSat {
+            sigs: Vec<BitcoinSig>,
+            policy: SigHashBytePolicy,
+        }
+
+        impl Satisfier<dogecoin::PublicKey> for MultiTypeSat {
+            fn lookup_sig(&self, _: &dogecoin::PublicKey) -> Option<BitcoinSig> {
+                self.sigs.first().cloned()
+            }
+
+            fn lookup_sig_for_type(
+                &self,
+                _: &dogecoin::PublicKey,
+                ty: dogecoin::SigHashType,
+            ) -> Option<secp256k1::Signature> {
+                self.sigs.iter().find(|sig| sig.1 == ty).map(|sig| sig.0)
+            }
+
+            fn sighash_byte_policy(&self) -> SigHashBytePolicy {
+                self.policy
+            }
+        }
+
+        let secp = secp256k1::Secp256k1::new();
+        let keys = pubkeys(1);
+        let (sk, _) = test_key();
+        let all = secp.sign(&secp256k1::Message::from_slice(&[1; 32][..]).unwrap(), &sk);
+        let single = secp.sign(&secp256k1::Message::from_slice(&[2; 32][..]).unwrap(), &sk);
+
+        let ms: Segwitv0Script = ms_str!("pk({})", keys[0]);
+        let mut sat = MultiTypeSat {
+            sigs: vec![
+                (all, dogecoin::SigHashType::All),
+                (single, dogecoin::SigHashType::Single),
+            ],
+            policy: SigHashBytePolicy::Append,
+        };
+        let mut sigser = all.serialize_der().to_vec();
+        sigser.push(0x01);
+        assert_eq!(ms.satisfy(&sat).unwrap(), vec![sigser]);
+
+        sat.policy = SigHashBytePolicy::Require(dogecoin::SigHashType::Single);
+        let mut sigser = single.serialize_der().to_vec();
+        sigser.push(0x03);
+        assert_eq!(ms.satisfy(&sat).unwrap(), vec![sigser]);
+
+        sat.policy = SigHashBytePolicy::Require(dogecoin::SigHashType::None);
+        assert!(ms.satisfy(&sat).is_err());
+    }
+
+    #[test]
+    fn satisfy_any() {
+        use miniscript::satisfy::{satisfy_any, After, Older};
+
+        let scripts: Vec<Segwitv0Script> = vec![
+            ms_str!("after(100)"),
+            ms_str!("older(10)"),
+            ms_str!("and_v(v:older(10),after(100))"),
+        ];
+        assert_eq!(satisfy_any(&scripts, Older(10)), Some((1, vec![])));
+        assert_eq!(satisfy_any(&scripts, (After(100), Older(10))), Some((0, vec![])));
+        assert_eq!(satisfy_any(&scripts[2..], (After(100), Older(10))), Some((0, vec![])));
+        assert_eq!(satisfy_any(&scripts, ()), None);
+    }
+
+    #[test]
+    fn satisfy_path_by_timelock() {
+        use miniscript::satisfy::Older;
+
+        let keys = pubkeys(2);
+        let (sig, sigser) = dummy_sig();
+        let sigs = sig_map(&keys[..2], sig);
+
+        // Hot key, or cold key after a delay
+        let ms: Segwitv0Script = ms_str!(
+            "or_d(pk({}),and_v(v:pk({}),older(1000)))",
+            keys[0],
+            keys[1]
+        );
+        let sat = (&sigs, Older(1000));
+        assert_eq!(ms.satisfy(&sat).unwrap(), vec![sigser.clone()]);
+        assert_eq!(
+            ms.satisfy_path_by_timelock(&sat, 1000).unwrap(),
+            (vec![sigser.clone(), vec![]], 1000)
+        );
+        assert!(ms.satisfy_path_by_timelock(&sat, 10).is_err());
+        assert!(ms.satisfy_path_by_timelock(&sigs, 1000).is_err());
+    }
+
+    #[test]
+    fn satisfy_revealing_preimage() {
+        use miniscript::satisfy::PreimageFn;
+
+        let keys = pubkeys(2);
+        let (sig, sigser) = dummy_sig();
+        let sigs = sig_map(&keys[..2], sig);
+        let preimage = [0x42; 32];
+        let hash = sha256::Hash::hash(&preimage);
+        let other = sha256::Hash::hash(&[0x43; 32]);
+        let preimages = PreimageFn::new(|h| match h {
+            HashLockType::Sha256(h) if *h == hash => Some(preimage),
+            _ => None,
+        });
+
+        // Revocation key, or the other key with the preimage
+        let ms: Segwitv0Script = ms_str!(
+            "or_d(pk({}),and_v(v:pk({}),sha256({})))",
+            keys[0],
+            keys[1],
+            hash
+        );
+        let sat = (&sigs, &preimages);
+        assert_eq!(ms.satisfy(&sat).unwrap(), vec![sigser.clone()]);
+        assert_eq!(
+            ms.satisfy_revealing_preimage(&sat, HashLockType::Sha256(&hash)),
+            Some(vec![preimage.to_vec(), sigser.clone(), vec![]])
+        );
+        assert_eq!(
+            ms.satisfy_revealing_preimage(&sat, HashLockType::Sha256(&other)),
+            None
+        );
+        assert_eq!(
+            ms.satisfy_revealing_preimage(&sigs, HashLockType::Sha256(&hash)),
+            None
+        );
+    }
+
+    #[test]
+    fn raw_sig_satisfier() {
+        use miniscript::satisfy::RawSigSatisfier;
+
+        let keys = pubkeys(3);
+        let (_, sigser) = dummy_sig();
+        let mut sigs = HashMap::new();
+        sigs.insert(keys[0], vec![0x30, 0x01, 0x01]);
+        sigs.insert(keys[1], vec![]);
+        sigs.insert(keys[2], sigser.clone());
+        let sat = RawSigSatisfier(sigs);
+
+        let ms: Segwitv0Script = ms_str!("pk({})", keys[2]);
+        assert_eq!(ms.satisfy(&sat).unwrap(), vec![sigser.clone()]);
+        // Unparseable signatures are treated as missing
+        let ms: Segwitv0Script = ms_str!("pk({})", keys[0]);
+        assert!(ms.satisfy(&sat).is_err());
+        let ms: Segwitv0Script = ms_str!("pk({})", keys[1]);
+        assert!(ms.satisfy(&sat).is_err());
+    }
+
+    #[test]
+    fn compressed_key_satisfier() {
+        use miniscript::satisfy::CompressedKeySatisfier;
+        use miniscript::Legacy;
+
+        let keys = pubkeys(2);
+        let (sig, sigser) = dummy_sig();
+        let mut sigs = HashMap::new();
+        sigs.insert(keys[0].key, (sig, dogecoin::SigHashType::All));
+        let sat = CompressedKeySatisfier(sigs);
+
+        let ms: Segwitv0Script = ms_str!("pk({})", keys[0]);
+        assert_eq!(ms.satisfy(&sat).unwrap(), vec![sigser]);
+        let ms: Segwitv0Script = ms_str!("pk({})", keys[1]);
+        assert!(ms.satisfy(&sat).is_err());
+        // The uncompressed form of a key is not looked up
+        let uncompressed = dogecoin::PublicKey {
+            key: keys[0].key,
+            compressed: false,
+        };
+        let ms: Miniscript<dogecoin::PublicKey, Legacy> = ms_str!("pk({})", uncompressed);
+        assert!(ms.satisfy(&sat).is_err());
+    }
+
+    #[test]
+    fn signer_store() {
+        use miniscript::satisfy::SignerStore;
+
+        let keys = pubkeys(2);
+        let (sig, sigser) = dummy_sig();
+        let sigs = sig_map(&keys[..1], sig);
+        let store = SignerStore(sigs);
+
+        let ms: Segwitv0Script = ms_str!("pk({})", keys[0]);
+        assert_eq!(ms.satisfy(&store).unwrap(), vec![sigser.clone()]);
+        let ms: Segwitv0Script = ms_str!("pkh({})", keys[0].to_pubkeyhash());
+        assert_eq!(ms.satisfy(&store).unwrap(), vec![sigser, keys[0].to_bytes()]);
+        let ms: Segwitv0Script = ms_str!("pkh({})", keys[1].to_pubkeyhash());
+        assert!(ms.satisfy(&store).is_err());
+    }
+
+    #[test]
+    fn witness_is_minimally_encoded() {
+        use miniscript::satisfy::Witness;
+        use Interpreter;
+
+        let keys = pubkeys(2);
+        let (sig, sigser) = dummy_sig();
+        let ms: Segwitv0Script = ms_str!("or_i(pk({}),pk({}))", keys[0], keys[1]);
+        for (i, branch) in vec![vec![1], vec![]].into_iter().enumerate() {
+            let sigs = sig_map(&keys[i..i + 1], sig);
+            let witness = ms.satisfy(&sigs).unwrap();
+            assert_eq!(witness, vec![sigser.clone(), branch]);
+            assert!(Witness::Stack(witness).is_minimally_encoded());
+        }
+
+        assert!(Witness::Stack(vec![vec![0x80, 0x80], vec![0; 32]]).is_minimally_encoded());
+        assert!(!Witness::Stack(vec![vec![0]]).is_minimally_encoded());
+        assert!(!Witness::Stack(vec![vec![1, 0]]).is_minimally_encoded());
+        assert!(!Witness::Stack(vec![vec![0x80]]).is_minimally_encoded());
+        assert!(Witness::Unavailable.is_minimally_encoded());
+
+        // `[2]` is a minimal number, so passes, though as the argument of
+        // `or_i`'s OP_IF it breaks MINIMALIF, which the interpreter checks
+        let witness = vec![sigser.clone(), vec![2]];
+        assert!(Witness::Stack(witness).is_minimally_encoded());
+        let script = ms.encode();
+        let spk = script.to_v0_p2wsh();
+        let empty = dogecoin::Script::new();
+        let verify = |branch: Vec<u8>| {
+            let witness = vec![sigser.clone(), branch, script.to_bytes()];
+            let mut interpreter = Interpreter::from_txdata(&spk, &empty, &witness, 0, 0).unwrap();
+            interpreter.verify_structure().is_ok()
+        };
+        assert!(verify(vec![1]));
+        assert!(!verify(vec![2]));
+    }
+
+    #[test]
+    fn finalize_witness() {
+        use miniscript::context::ScriptContextError;
+        use miniscript::satisfy::Older;
+        use miniscript::ScriptContextKind;
+        use {BareCtx, Error, Legacy};
+
+        let script_push = |script: &dogecoin::Script| {
+            dogecoin::blockdata::script::Builder::new()
+                .push_slice(&script[..])
+                .into_script()
+        };
+
+        let ms: Miniscript<dogecoin::PublicKey, BareCtx> = ms_str!("older(10)");
+        let finalized = ms.finalize_witness(Older(10), ScriptContextKind::Bare);
+        assert_eq!(finalized.unwrap(), (None, None));
+        let ms: Miniscript<dogecoin::PublicKey, Legacy> = ms_str!("older(10)");
+        let finalized = ms.finalize_witness(Older(10), ScriptContextKind::Legacy);
+        assert_eq!(finalized.unwrap(), (Some(script_push(&ms.encode())), None));
+
+        let ms: Segwitv0Script = ms_str!("older(10)");
+        let script = ms.encode();
+        let finalize = |kind| ms.finalize_witness(Older(10), kind);
+        assert_eq!(
+            finalize(ScriptContextKind::Segwitv0).unwrap(),
+            (None, Some(vec![script.to_bytes()]))
+        );
+        assert_eq!(
+            finalize(ScriptContextKind::ShWsh).unwrap(),
+            (
+                Some(script_push(&script.to_v0_p2wsh())),
+                Some(vec![script.to_bytes()])
+            )
+        );
+        let unsatisfied = ms.finalize_witness(Older(9), ScriptContextKind::Segwitv0);
+        assert!(unsatisfied.is_err());
+
+        // A segwit script can only be spent by p2wsh
+        match finalize(ScriptContextKind::Legacy) {
+            Err(Error::ContextError(ScriptContextError::IncompatibleKind(
+                ScriptContextKind::Legacy,
+            ))) => {}
+            x => panic!("expected incompatible kind, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn try_lookup_sig() {
+        use miniscript::satisfy::{Satisfier, SatisfierError};
+        use {BitcoinSig, Error};
+
+        struct Unreachable;
+        impl Satisfier<dogecoin::PublicKey> for Unreachable {
+            fn try_lookup_sig(
+                &self,
+                _: &dogecoin::PublicKey,
+            ) -> Result<Option<BitcoinSig>, SatisfierError> {
+                Err(SatisfierError("signer unreachable".to_owned()))
+            }
+        }
+
+        let keys = pubkeys(1);
+        let (sig, sigser) = dummy_sig();
+        let sigs = sig_map(&keys[..1], sig);
+
+        let ms: Segwitv0Script = ms_str!("pk({})", keys[0]);
+        match ms.satisfy(Unreachable) {
+            Err(Error::Satisfier(ref e)) if e.0 == "signer unreachable" => {}
+            x => panic!("unexpected result {:?}", x),
+        }
+        match ms.satisfy(()) {
+            Err(Error::CouldNotSatisfy) => {}
+            x => panic!("unexpected result {:?}", x),
+        }
+        // The error is not reported if another satisfier has the signature
+        assert_eq!(ms.satisfy((Unreachable, &sigs)).unwrap(), vec![sigser]);
+    }
+
+    #[test]
+    fn try_lookup_sig_entry_points() {
+        use miniscript::satisfy::{
+            BranchChoice, FragmentId, Older, PreimageFn, Satisfier, SatisfierError,
+            SigHashBytePolicy,
+        };
+        use {BitcoinSig, Error};
+
+        // A remote signer, only implementing `try_lookup_sig`, which is
+        // unreachable if it has no signature
+        struct Remote(Option<BitcoinSig>, SigHashBytePolicy);
+        impl Satisfier<dogecoin::PublicKey> for Remote {
+            fn try_lookup_sig(
+                &self,
+                _: &dogecoin::PublicKey,
+            ) -> Result<Option<BitcoinSig>, SatisfierError> {
+                match self.0 {
+                    Some(sig) => Ok(Some(sig)),
+                    None => Err(SatisfierError("signer unreachable".to_owned())),
+                }
+            }
+
+            fn sighash_byte_policy(&self) -> SigHashBytePolicy {
+                self.1
+            }
+        }
+        let is_unreachable = |res: Result<Vec<Vec<u8>>, Error>| match res {
+            Err(Error::Satisfier(ref e)) => e.0 == "signer unreachable",
+            _ => false,
+        };
+
+        let keys = pubkeys(2);
+        let (sig, sigser) = dummy_sig();
+        let signer = Remote(
+            Some((sig, dogecoin::SigHashType::All)),
+            SigHashBytePolicy::Append,
+        );
+        let unreachable = Remote(None, SigHashBytePolicy::Append);
+        let preimage = [0x42; 32];
+        let hash = sha256::Hash::hash(&preimage);
+        let preimages = PreimageFn::new(|h| match h {
+            HashLockType::Sha256(h) if *h == hash => Some(preimage),
+            _ => None,
+        });
+
+        let ms: Segwitv0Script = ms_str!("pk({})", keys[0]);
+        assert_eq!(ms.satisfy_logged(&signer).0, Some(vec![sigser.clone()]));
+        assert_eq!(ms.satisfy_logged(&unreachable).0, None);
+        // Requiring a sighash type looks signatures up by type
+        let all = SigHashBytePolicy::Require(dogecoin::SigHashType::All);
+        assert_eq!(
+            ms.satisfy(Remote(signer.0, all)).unwrap(),
+            vec![sigser.clone()]
+        );
+        assert!(is_unreachable(ms.satisfy(Remote(None, all))));
+
+        let ms: Segwitv0Script = ms_str!("thresh(1,pk({}),s:pk({}))", keys[0], keys[1]);
+        assert_eq!(
+            ms.satisfy_thresh_with_keys(&signer, &keys[..1]),
+            Some(vec![vec![], sigser.clone()])
+        );
+        assert_eq!(ms.satisfy_thresh_with_keys(&unreachable, &keys[..1]), None);
+
+        let ms: Segwitv0Script =
+            ms_str!("or_i(pk({}),and_v(v:pk({}),older(10)))", keys[0], keys[1]);
+        let left = |id: &FragmentId| {
+            if *id == FragmentId(0) {
+                Some(BranchChoice::Left)
+            } else {
+                None
+            }
+        };
+        assert_eq!(
+            ms.satisfy_branch(&signer, &left).unwrap(),
+            vec![sigser.clone(), vec![1]]
+        );
+        assert!(is_unreachable(ms.satisfy_branch(&unreachable, &left)));
+        assert_eq!(
+            ms.satisfy_path_by_timelock((&signer, Older(10)), 10)
+                .unwrap(),
+            (vec![sigser.clone(), vec![]], 10)
+        );
+        assert!(is_unreachable(
+            ms.satisfy_path_by_timelock((&unreachable, Older(10)), 10)
+                .map(|(witness, _)| witness)
+        ));
+
+        let ms: Segwitv0Script = ms_str!(
+            "or_i(pk({}),and_v(v:pk({}),sha256({})))",
+            keys[0],
+            keys[1],
+            hash
+        );
+        assert_eq!(
+            ms.satisfy_revealing_preimage((&signer, &preimages), HashLockType::Sha256(&hash)),
+            Some(vec![preimage.to_vec(), sigser.clone(), vec![]])
+        );
+        assert_eq!(
+            ms.satisfy_revealing_preimage((&unreachable, &preimages), HashLockType::Sha256(&hash)),
+            None
+        );
+    }
+
+    #[test]
+    fn try_lookup_sig_unneeded_key() {
+        use miniscript::satisfy::{Satisfier, SatisfierError};
+        use Error;
+
+        // A signer which is unreachable for one key and has signatures for
+        // the others
+        struct OneUnreachable(
+            dogecoin::PublicKey,
+            HashMap<dogecoin::PublicKey, BitcoinSig>,
+        );
+        impl Satisfier<dogecoin::PublicKey> for OneUnreachable {
+            fn try_lookup_sig(
+                &self,
+                pk: &dogecoin::PublicKey,
+            ) -> Result<Option<BitcoinSig>, SatisfierError> {
+                if *pk == self.0 {
+                    Err(SatisfierError("signer unreachable".to_owned()))
+                } else {
+                    Ok(self.1.get(pk).cloned())
+                }
+            }
+        }
+
+        let keys = pubkeys(3);
+        let (sig, sigser) = dummy_sig();
+        let sat = OneUnreachable(keys[0], sig_map(&keys[1..], sig));
+
+        // The error is ignored when the script is satisfiable without the key
+        let ms: Segwitv0Script = ms_str!("multi(2,{},{},{})", keys[0], keys[1], keys[2]);
+        let witness = vec![vec![], sigser.clone(), sigser.clone()];
+        assert_eq!(ms.satisfy(&sat).unwrap(), witness);
+        assert_eq!(ms.satisfy_malleable(&sat).unwrap(), witness);
+        assert_eq!(ms.satisfy_logged(&sat).0, Some(witness));
+        let ms: Segwitv0Script = ms_str!("or_d(pk({}),pk({}))", keys[0], keys[1]);
+        assert_eq!(ms.satisfy(&sat).unwrap(), vec![sigser.clone(), vec![]]);
+
+        // And reported when it is not
+        let ms: Segwitv0Script = ms_str!("and_v(v:pk({}),pk({}))", keys[1], keys[0]);
+        match ms.satisfy(&sat) {
+            Err(Error::Satisfier(ref e)) => assert_eq!(e.0, "signer unreachable"),
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn satisfaction_vsize() {
+        use miniscript::satisfy::{Satisfaction, Witness};
+
+        let sat = Satisfaction {
+            stack: Witness::Stack(vec![vec![0x5a; 72], vec![], vec![1]]),
+            has_sig: true,
+        };
+        // Empty scriptSig length byte, plus 1 count byte, 1+72, 1+0, 1+1 => 77 WU
+        assert_eq!(sat.vsize(true, None), Some(21));
+        // Length byte, then 1+72 push, OP_0, OP_1
+        assert_eq!(sat.vsize(false, None), Some(76));
+
+        // sh: 75 bytes of pushes and a 2+200 byte push of the redeem script,
+        // which needs a 3 byte length prefix
+        let redeem_script = dogecoin::Script::from(vec![0x51; 200]);
+        assert_eq!(sat.vsize(false, Some(&redeem_script)), Some(280));
+
+        // sh(wsh): the scriptSig is a 1+34 byte push of the witness program
+        let wsh = dogecoin::Script::new_v0_wsh(&redeem_script.wscript_hash());
+        assert_eq!(sat.vsize(true, Some(&wsh)), Some(56));
+
+        let sat = Satisfaction {
+            stack: Witness::Unavailable,
+            has_sig: false,
+        };
+        assert_eq!(sat.vsize(true, None), None);
+        assert_eq!(sat.vsize(false, None), None);
+    }
+
+    #[test]
+    fn satisfy_with_has_sig() {
+        use miniscript::satisfy::Older;
+
+        let keys = pubkeys(1);
+        let (sig, sigser) = dummy_sig();
+        let sigs = sig_map(&keys[..1], sig);
+
+        let ms: Segwitv0Script = ms_str!("and_v(v:pk({}),older(10))", keys[0]);
+        assert_eq!(
+            ms.satisfy_with_has_sig((&sigs, Older(10))).unwrap(),
+            (vec![sigser], true)
+        );
+        let ms: Segwitv0Script = ms_str!("older(10)");
+        assert_eq!(ms.satisfy_with_has_sig(Older(10)).unwrap(), (vec![], false));
+        assert!(ms.satisfy_with_has_sig(Older(9)).is_err());
+    }
+
+    #[test]
+    fn satisfy_incompatible_timelock_units() {
+        use miniscript::satisfy::Older;
+        use Error;
+
+        // Either unit is accepted on its own, but not both together
+        let sat = (Older(10), Older(0x00400001), Older(20));
+        let ms: Segwitv0Script = ms_str!("and_v(v:older(10),older(4194305))");
+        match ms.satisfy(&sat) {
+            Err(Error::IncompatibleTimelockUnits) => {}
+            x => panic!("unexpected result {:?}", x),
+        }
+        match ms.satisfy(Older(10)) {
+            Err(Error::CouldNotSatisfy) => {}
+            x => panic!("unexpected result {:?}", x),
+        }
+
+        // A branch using one unit is satisfied instead where there is one
+        let keys = pubkeys(2);
+        let (sig, sigser) = dummy_sig();
+        let sigs = sig_map(&keys[..2], sig);
+        let ms: Segwitv0Script = ms_str!(
+            "and_v(v:pk({}),or_i(and_v(v:older(10),older(4194305)),and_v(v:pk({}),older(20))))",
+            keys[0],
+            keys[1]
+        );
+        assert_eq!(
+            ms.satisfy((&sigs, &sat)).unwrap(),
+            vec![sigser.clone(), vec![], sigser]
+        );
+    }
+
+    #[test]
+    fn verifying_signer() {
+        use miniscript::satisfy::VerifyingSigner;
+
+        let secp = secp256k1::Secp256k1::new();
+        let (sk, pk) = test_key();
+        let msg = secp256k1::Message::from_slice(&[2; 32][..]).unwrap();
+        let good_sig = secp.sign(&msg, &sk);
+        // A valid signature, but over a different message
+        let other_sig = secp.sign(&secp256k1::Message::from_slice(&[3; 32][..]).unwrap(), &sk);
+
+        let mut keys = HashMap::new();
+        keys.insert(pk, sk);
+        let sighash = |ty| {
+            if ty == dogecoin::SigHashType::All {
+                Some(msg)
+            } else {
+                None
+            }
+        };
+        let ms: Segwitv0Script = ms_str!("pk({})", pk);
+
+        let mut sigs = HashMap::new();
+        sigs.insert(pk, (good_sig, dogecoin::SigHashType::All));
+        let sat = VerifyingSigner::new(&secp, keys.clone(), sighash, &sigs);
+        assert!(ms.satisfy(&sat).is_ok());
+
+        sigs.insert(pk, (other_sig, dogecoin::SigHashType::All));
+        let sat = VerifyingSigner::new(&secp, keys.clone(), sighash, &sigs);
+        assert!(ms.satisfy(&sat).is_err());
+
+        // No message to check against
+        sigs.insert(pk, (good_sig, dogecoin::SigHashType::None));
+        let sat = VerifyingSigner::new(&secp, keys, sighash, &sigs);
+        assert!(ms.satisfy(&sat).is_err());
+
+        // Keys without a secret key are not checked
+        sigs.insert(pk, (other_sig, dogecoin::SigHashType::All));
+        let sat = VerifyingSigner::new(&secp, HashMap::new(), sighash, &sigs);
+        assert!(ms.satisfy(&sat).is_ok());
+    }
+
+    #[test]
+    fn now_satisfier() {
+        use miniscript::satisfy::NowSatisfier;
+
+        let now = NowSatisfier {
+            current_height: 1000,
+            current_time: 600_000_000,
+            confirmed_height: 991,
+            confirmed_time: 600_000_000 - 1024,
+        };
+        let check = |s: &str| {
+            let ms: Segwitv0Script = ms_str!("{}", s);
+            ms.satisfy(&now).is_ok()
+        };
+        assert!(check("after(1000)"));
+        assert!(!check("after(1001)"));
+        assert!(check("after(599999999)"));
+        assert!(!check("after(600000000)"));
+        // The next block gives the coin its tenth confirmation
+        assert!(check("older(10)"));
+        assert!(!check("older(11)"));
+        // Two units of 512 seconds
+        assert!(check("older(4194306)"));
+        assert!(!check("older(4194307)"));
+    }
+
+    #[test]
+    fn future_context() {
+        use miniscript::satisfy::FutureContext;
+
+        let later = FutureContext {
+            at_height: 2000,
+            at_time: 600_000_000,
+        };
+        let check = |s: &str| {
+            let ms: Segwitv0Script = ms_str!("{}", s);
+            (ms.satisfy(&later).is_ok(), ms.satisfy(later.spending(1991, 0)).is_ok())
+        };
+        assert_eq!(check("after(2000)"), (true, true));
+        assert_eq!(check("after(2001)"), (false, false));
+        assert_eq!(check("after(599999999)"), (true, true));
+        assert_eq!(check("after(600000000)"), (false, false));
+        // Relative timelocks can only be checked knowing the confirmation
+        assert_eq!(check("older(10)"), (false, true));
+        assert_eq!(check("older(11)"), (false, false));
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn satisfy_randomized() {
+        use dogecoin::secp256k1::rand::{self, RngCore};
+
+        // Returns 0, 1, 0, 1, ...
+        struct Alternating(u32);
+        impl RngCore for Alternating {
+            fn next_u32(&mut self) -> u32 {
+                self.0 ^= 1;
+                self.0 ^ 1
+            }
+            fn next_u64(&mut self) -> u64 {
+                self.next_u32() as u64
+            }
+            fn fill_bytes(&mut self, dest: &mut [u8]) {
+                for byte in dest {
+                    *byte = self.next_u32() as u8;
+                }
+            }
+            fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+                self.fill_bytes(dest);
+                Ok(())
+            }
+        }
+
+        let keys = pubkeys(2);
+        let (sig, sigser) = dummy_sig();
+        let mut sigs = sig_map(&keys[..2], sig);
+
+        // Both branches cost the same
+        let ms: Segwitv0Script = ms_str!("or_b(pk({}),s:pk({}))", keys[0], keys[1]);
+        let mut rng = Alternating(0);
+        let first = ms.satisfy_randomized(&sigs, &mut rng).unwrap();
+        let second = ms.satisfy_randomized(&sigs, &mut rng).unwrap();
+        assert_eq!(first, ms.satisfy(&sigs).unwrap());
+        assert_ne!(first, second);
+        for wit in &[first, second] {
+            assert!(*wit == vec![vec![], sigser.clone()] || *wit == vec![sigser.clone(), vec![]]);
+        }
+
+        // The cheaper branch is always taken
+        let ms: Segwitv0Script = ms_str!("or_i(pk({}),pk({}))", keys[0], keys[1]);
+        for _ in 0..2 {
+            assert_eq!(
+                ms.satisfy_randomized(&sigs, &mut rng).unwrap(),
+                vec![sigser.clone(), vec![]]
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_multi_satisfaction() {
+        use miniscript::satisfy::{Satisfaction, Witness};
+
+        let keys = pubkeys(2);
+        let (sig, _) = dummy_sig();
+        let sigs = sig_map(&keys[..2], sig);
+
+        // Not a valid Miniscript, so only constructible as a bare term
+        let term: Terminal<dogecoin::PublicKey, Segwitv0> = Terminal::Multi(3, keys.clone());
+        let sat = Satisfaction::satisfy(&term, &sigs, true);
+        assert_eq!(sat.stack, Witness::Impossible);
+
+        let term: Terminal<dogecoin::PublicKey, Segwitv0> = Terminal::Multi(2, keys);
+        let sat = Satisfaction::satisfy(&term, &sigs, true);
+        match sat.stack {
+            Witness::Stack(stack) => assert_eq!(stack.len(), 3),
+            x => panic!("expected a satisfaction, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn preferred_keys() {
+        use miniscript::satisfy::PreferredKeys;
+
+        let secp = secp256k1::Secp256k1::new();
+        let msg = secp256k1::Message::from_slice(&[1; 32][..]).unwrap();
+        let mut keys = vec![];
+        let mut sigsers = vec![];
+        let mut sigs = HashMap::new();
+        for i in 1..3 {
+            let sk = secp256k1::SecretKey::from_slice(&[i; 32][..]).unwrap();
+            let pk = dogecoin::PublicKey {
+                key: secp256k1::PublicKey::from_secret_key(&secp, &sk),
+                compressed: true,
+            };
+            let sig = secp.sign(&msg, &sk);
+            let mut sigser = sig.serialize_der().to_vec();
+            sigser.push(0x01);
+            keys.push(pk);
+            sigsers.push(sigser);
+            sigs.insert(pk, (sig, dogecoin::SigHashType::All));
+        }
+
+        let multi: Segwitv0Script = ms_str!("multi(1,{},{})", keys[0], keys[1]);
+        let thresh: Segwitv0Script = ms_str!("thresh(1,pk({}),s:pk({}))", keys[0], keys[1]);
+        for i in 0..2 {
+            let sat = PreferredKeys {
+                inner: &sigs,
+                order: vec![keys[i]],
+            };
+            assert_eq!(multi.satisfy(&sat).unwrap(), vec![vec![], sigsers[i].clone()]);
+            let wit = thresh.satisfy(&sat).unwrap();
+            assert!(wit.contains(&sigsers[i]));
+            assert!(!wit.contains(&sigsers[1 - i]));
+        }
+    }
+
+    #[test]
+    fn satisfy_thresh_with_keys() {
+        let secp = secp256k1::Secp256k1::new();
+        let msg = secp256k1::Message::from_slice(&[1; 32][..]).unwrap();
+        let mut keys = vec![];
+        let mut sigsers = vec![];
+        let mut sigs = HashMap::new();
+        for i in 1..5 {
+            let sk = secp256k1::SecretKey::from_slice(&[i; 32][..]).unwrap();
+            let pk = dogecoin::PublicKey {
+                key: secp256k1::PublicKey::from_secret_key(&secp, &sk),
+                compressed: true,
+            };
+            let sig = secp.sign(&msg, &sk);
+            let mut sigser = sig.serialize_der().to_vec();
+            sigser.push(0x01);
+            keys.push(pk);
+            sigsers.push(sigser);
+            if i < 4 {
+                sigs.insert(pk, (sig, dogecoin::SigHashType::All));
+            }
+        }
+
+        let ms: Segwitv0Script = ms_str!(
+            "thresh(2,pk({}),s:pk({}),s:pk({}))",
+            keys[0],
+            keys[1],
+            keys[2]
+        );
+        assert_eq!(
+            ms.satisfy_thresh_with_keys(&sigs, &[keys[2], keys[0]]),
+            Some(vec![sigsers[2].clone(), vec![], sigsers[0].clone()])
+        );
+        assert_eq!(
+            ms.satisfy_thresh_with_keys(&sigs, &[keys[1], keys[2]]),
+            Some(vec![sigsers[2].clone(), sigsers[1].clone(), vec![]])
+        );
+        // Not a quorum of exactly two
+        assert_eq!(ms.satisfy_thresh_with_keys(&sigs, &[keys[0]]), None);
+        assert_eq!(ms.satisfy_thresh_with_keys(&sigs, &keys[..3]), None);
+        // A key which is not in the threshold
+        assert_eq!(ms.satisfy_thresh_with_keys(&sigs, &[keys[0], keys[3]]), None);
+
+        // The quorum also picks the subs of nested thresholds
+        let ms: Segwitv0Script = ms_str!(
+            "thresh(1,pk({}),a:thresh(1,pk({}),s:pk({})))",
+            keys[0],
+            keys[1],
+            keys[2]
+        );
+        assert_eq!(
+            ms.satisfy_thresh_with_keys(&sigs, &[keys[2]]),
+            Some(vec![sigsers[2].clone(), vec![], vec![]])
+        );
+        assert_eq!(
+            ms.satisfy_thresh_with_keys(&sigs, &[keys[1]]),
+            Some(vec![vec![], sigsers[1].clone(), vec![]])
+        );
+
+        // A quorum member who has not signed
+        let ms: Segwitv0Script = ms_str!(
+            "thresh(2,pk({}),s:pk({}),s:pk({}))",
+            keys[0],
+            keys[1],
+            keys[3]
+        );
+        assert_eq!(ms.satisfy_thresh_with_keys(&sigs, &[keys[0], keys[3]]), None);
+        assert!(ms.satisfy_thresh_with_keys(&sigs, &[keys[0], keys[1]]).is_some());
+    }
+
+    #[test]
+    fn required_preimages() {
+        let keys = pubkeys(2);
+        let hash = sha256::Hash::hash(&[1; 32]);
+        let other = hash160::Hash::hash(&[1; 32]);
+        let second = sha256::Hash::hash(&[2; 32]);
+
+        let ms: Segwitv0Script = ms_str!("and_v(v:sha256({}),sha256({}))", hash, second);
+        assert_eq!(
+            ms.required_preimages(),
+            vec![HashLockType::Sha256(&hash), HashLockType::Sha256(&second)]
+        );
+
+        // Only the cheaper branch is counted
+        let ms: Segwitv0Script = ms_str!(
+            "or_i(and_v(v:sha256({}),sha256({})),hash160({}))",
+            hash,
+            second,
+            other
+        );
+        assert_eq!(ms.required_preimages(), vec![HashLockType::Hash160(&other)]);
+        let ms: Segwitv0Script =
+            ms_str!("andor(pk({}),sha256({}),hash160({}))", keys[0], hash, other);
+        assert_eq!(ms.required_preimages(), vec![HashLockType::Hash160(&other)]);
+        let ms: Segwitv0Script = ms_str!(
+            "thresh(1,pk({}),a:sha256({}),s:pk({}))",
+            keys[0],
+            hash,
+            keys[1]
+        );
+        assert_eq!(ms.required_preimages(), vec![HashLockType::Sha256(&hash)]);
+
+        let ms: Segwitv0Script = ms_str!("and_v(v:sha256({}),0)", hash);
+        assert!(ms.required_preimages().is_empty());
+    }
+
+    #[test]
+    fn satisfy_logged() {
+        use miniscript::satisfy::{
+            BranchChoice, ChoiceReason, FragmentId, Older, SatisfyDecision, SatisfyLog,
+        };
+
+        let keys = pubkeys(2);
+        let (sig, _) = dummy_sig();
+        let both = sig_map(&keys[..2], sig);
+        let only_b = sig_map(&keys[1..], sig);
+        let branch = |choice, reason| SatisfyLog {
+            decisions: vec![SatisfyDecision::Branch {
+                fragment: FragmentId(0),
+                choice: choice,
+                reason: reason,
+            }],
+        };
+
+        let ms: Segwitv0Script = ms_str!("or_i(pk({}),pk({}))", keys[0], keys[1]);
+        let (witness, log) = ms.satisfy_logged(&both);
+        assert_eq!(witness, Some(ms.satisfy(&both).unwrap()));
+        // The right branch's `OP_IF` selector is the empty push
+        assert_eq!(log, branch(Some(BranchChoice::Right), ChoiceReason::Cheaper));
+        let only_a = sig_map(&keys[..1], sig);
+        let (witness, log) = ms.satisfy_logged(&only_a);
+        assert_eq!(witness, Some(ms.satisfy(&only_a).unwrap()));
+        assert_eq!(log, branch(Some(BranchChoice::Left), ChoiceReason::OnlyPossible));
+
+        // `or_b` considers its branches in the opposite order
+        let ms: Segwitv0Script = ms_str!("or_b(pk({}),s:pk({}))", keys[1], keys[0]);
+        let (_, log) = ms.satisfy_logged(&only_b);
+        assert_eq!(log, branch(Some(BranchChoice::Left), ChoiceReason::OnlyPossible));
+
+        let ms: Segwitv0Script = ms_str!("or_d(pk({}),older(10))", keys[0]);
+        let (_, log) = ms.satisfy_logged((&both, Older(10)));
+        assert_eq!(log, branch(Some(BranchChoice::Right), ChoiceReason::WithoutSignature));
+
+        let ms: Segwitv0Script = ms_str!("thresh(1,pk({}),s:pk({}))", keys[0], keys[1]);
+        let (witness, log) = ms.satisfy_logged(&only_b);
+        assert!(witness.is_some());
+        assert_eq!(
+            log.decisions,
+            vec![SatisfyDecision::Thresh {
+                fragment: FragmentId(0),
+                satisfied: vec![1],
+            }]
+        );
+
+        let ms: Segwitv0Script = ms_str!("or_i(older(10),pk({}))", keys[0]);
+        // An unmet timelock may be met later, so its branch is still taken
+        let (witness, log) = ms.satisfy_logged(());
+        assert_eq!(witness, None);
+        assert_eq!(log, branch(Some(BranchChoice::Left), ChoiceReason::OnlyPossible));
+    }
 }
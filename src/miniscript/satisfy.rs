@@ -18,18 +18,22 @@
 //! scriptpubkeys.
 //!
 
-use std::collections::HashMap;
-use std::sync::Arc;
-use std::{cmp, i64, mem};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+use std::{cmp, error, fmt, i64, mem, usize};
 
-use dogecoin::hashes::{hash160, ripemd160, sha256, sha256d};
+use dogecoin::hashes::{hash160, ripemd160, sha256, sha256d, Hash};
+use dogecoin::util::bip32;
 use dogecoin::{self, secp256k1};
-use {MiniscriptKey, ToPublicKey};
+use {Error, MiniscriptKey, ToPublicKey};
 
 use miniscript::limits::{
     HEIGHT_TIME_THRESHOLD, SEQUENCE_LOCKTIME_DISABLE_FLAG, SEQUENCE_LOCKTIME_TYPE_FLAG,
 };
-use util::witness_size;
+use interpreter::HashLockType;
+use util::{varint_len, witness_size, witness_to_scriptsig};
+use push_opcode_size;
 use Miniscript;
 use ScriptContext;
 use Terminal;
@@ -49,21 +53,73 @@ pub fn bitcoinsig_from_rawsig(rawsig: &[u8]) -> Result<BitcoinSig, ::interpreter
     let sig = secp256k1::Signature::from_der(sig)?;
     Ok((sig, flag))
 }
+/// An error met by a satisfier while looking up data, as opposed to the
+/// data being unavailable, e.g. a remote signer which could not be reached
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SatisfierError(pub String);
+
+impl fmt::Display for SatisfierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl error::Error for SatisfierError {}
+
 /// Trait describing a lookup table for signatures, hash preimages, etc.
 /// Every method has a default implementation that simply returns `None`
 /// on every query. Users are expected to override the methods that they
 /// have data for.
+///
+/// Tuples of satisfiers are themselves satisfiers. Their elements are
+/// queried in declaration order and the first one to return a value wins,
+/// so e.g. `(fresh_sigs, cached_sigs)` prefers a fresh signature over a
+/// cached one for the same key. Timelock checks pass if any element passes.
 pub trait Satisfier<Pk: MiniscriptKey + ToPublicKey> {
     /// Given a public key, look up a signature with that key
     fn lookup_sig(&self, _: &Pk) -> Option<BitcoinSig> {
         None
     }
 
+    /// Given a public key, look up a signature with that key, reporting a
+    /// failure to fetch it, e.g. from a remote signer, as an error distinct
+    /// from the signature being unavailable. The satisfaction methods of
+    /// `Miniscript` and descriptors use this in place of `lookup_sig`,
+    /// treating a failed lookup as a missing signature. If no satisfaction
+    /// is found they fail with `Error::Satisfier`, or return `None` if they
+    /// return an `Option`. By default it wraps `lookup_sig`.
+    fn try_lookup_sig(&self, pk: &Pk) -> Result<Option<BitcoinSig>, SatisfierError> {
+        Ok(self.lookup_sig(pk))
+    }
+
+    /// Given a public key, look up a signature with that key under the
+    /// sighash type `ty`, for satisfiers which hold signatures under several
+    /// types for one key. This is used instead of `lookup_sig` when the
+    /// `sighash_byte_policy` requires a type. By default it returns the
+    /// signature from `lookup_sig` if it has that type.
+    fn lookup_sig_for_type(
+        &self,
+        pk: &Pk,
+        ty: dogecoin::SigHashType,
+    ) -> Option<secp256k1::Signature> {
+        match self.lookup_sig(pk) {
+            Some((sig, sig_ty)) if sig_ty == ty => Some(sig),
+            _ => None,
+        }
+    }
+
     /// Given a `Pkh`, lookup corresponding `Pk`
     fn lookup_pkh_pk(&self, _: &Pk::Hash) -> Option<Pk> {
         None
     }
 
+    /// Given a keyhash, look up the corresponding public key, for satisfiers
+    /// which know keys only as `dogecoin::PublicKey`s rather than as `Pk`s.
+    /// This is used to dissatisfy a pkh when `lookup_pkh_pk` returns `None`.
+    fn lookup_raw_pkh_pk(&self, _: &hash160::Hash) -> Option<dogecoin::PublicKey> {
+        None
+    }
+
     /// Given a keyhash, look up the signature and the associated key
     /// Even if signatures for public key Hashes are not available, the users
     /// can use this map to provide pkh -> pk mapping which can be useful
@@ -101,6 +157,28 @@ pub trait Satisfier<Pk: MiniscriptKey + ToPublicKey> {
     fn check_after(&self, _: u32) -> bool {
         false
     }
+
+    /// If this returns a set, only the preimages in it may be revealed in a
+    /// satisfaction; any other preimage returned by the `lookup_*` methods
+    /// is treated as if it were unavailable. This prevents accidentally
+    /// revealing an unrelated secret which the satisfier happens to know.
+    fn preimage_whitelist(&self) -> Option<&HashSet<Preimage32>> {
+        None
+    }
+
+    /// How the sighash type of looked-up signatures is treated. Signatures
+    /// violating the policy are never used in a satisfaction.
+    fn sighash_byte_policy(&self) -> SigHashBytePolicy {
+        SigHashBytePolicy::Append
+    }
+
+    /// Where a key ranks when several signatures could be used, lower
+    /// being preferred. When a `multi` or `thresh` has more than enough
+    /// signatures available, those of the best ranked keys are used; keys
+    /// without a rank come last.
+    fn key_priority(&self, _: &Pk) -> Option<usize> {
+        None
+    }
 }
 
 // Allow use of `()` as a "no conditions available" satisfier
@@ -146,6 +224,148 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for After {
     }
 }
 
+/// Satisfier which checks timelocks as if the transaction were being
+/// broadcast now, to be mined in the block after `current_height`. The
+/// coin being spent was confirmed at `confirmed_height` and
+/// `confirmed_time`, from which relative timelocks are measured.
+///
+/// Times should be median-time-past values for exactness, though the
+/// current unix time is a reasonable approximation for testing.
+pub struct NowSatisfier {
+    /// Height of the current chain tip
+    pub current_height: u32,
+    /// Current unix time
+    pub current_time: u32,
+    /// Height of the block confirming the coin being spent
+    pub confirmed_height: u32,
+    /// Unix time of the block confirming the coin being spent
+    pub confirmed_time: u32,
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for NowSatisfier {
+    fn check_older(&self, n: u32) -> bool {
+        if n & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return true;
+        }
+
+        /* If nSequence encodes a relative lock-time, this mask is
+         * applied to extract that lock-time from the sequence field. */
+        const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000ffff;
+        /* Time-based relative lock-times are measured in units of 512
+         * seconds. */
+        const SEQUENCE_LOCKTIME_GRANULARITY: u32 = 9;
+
+        let value = n & SEQUENCE_LOCKTIME_MASK;
+        if n & SEQUENCE_LOCKTIME_TYPE_FLAG != 0 {
+            let elapsed = self.current_time.saturating_sub(self.confirmed_time);
+            elapsed >= value << SEQUENCE_LOCKTIME_GRANULARITY
+        } else {
+            let next_height = self.current_height.saturating_add(1);
+            next_height.saturating_sub(self.confirmed_height) >= value
+        }
+    }
+
+    fn check_after(&self, n: u32) -> bool {
+        if n < HEIGHT_TIME_THRESHOLD {
+            n <= self.current_height
+        } else {
+            n < self.current_time
+        }
+    }
+}
+
+/// Satisfier which checks absolute timelocks as if the transaction were
+/// being broadcast at a later time, with the chain tip at `at_height` and
+/// the time `at_time`. This lets a transaction be satisfied and stored now,
+/// e.g. a vault's recovery transaction, to be broadcast once its timelocks
+/// are met.
+///
+/// Relative timelocks also depend on when the spent coin was confirmed, so
+/// this satisfies no `older` fragment; see `FutureContext::spending`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FutureContext {
+    /// Height of the chain tip when the transaction will be broadcast
+    pub at_height: u32,
+    /// Unix time when the transaction will be broadcast
+    pub at_time: u32,
+}
+
+impl FutureContext {
+    /// A satisfier for spending, at this later time, a coin confirmed at
+    /// `confirmed_height` and `confirmed_time`, which checks both absolute
+    /// and relative timelocks
+    pub fn spending(&self, confirmed_height: u32, confirmed_time: u32) -> NowSatisfier {
+        NowSatisfier {
+            current_height: self.at_height,
+            current_time: self.at_time,
+            confirmed_height: confirmed_height,
+            confirmed_time: confirmed_time,
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for FutureContext {
+    fn check_after(&self, n: u32) -> bool {
+        Satisfier::<Pk>::check_after(&self.spending(0, 0), n)
+    }
+}
+
+/// Combine the `after` requirements of several inputs into the single
+/// `nLockTime` which satisfies all of them, i.e. the largest. Since an
+/// `nLockTime` is either a block height or a timestamp, the requirements
+/// must all use the same unit. Zero requirements are ignored, and an empty
+/// list yields a locktime of 0.
+pub fn aggregate_locktimes(reqs: &[u32]) -> Result<u32, Error> {
+    let mut ret = 0;
+    for &n in reqs {
+        if n == 0 {
+            continue;
+        }
+        if ret != 0 && (n < HEIGHT_TIME_THRESHOLD) != (ret < HEIGHT_TIME_THRESHOLD) {
+            return Err(Error::HeightTimeLockCombination);
+        }
+        ret = cmp::max(ret, n);
+    }
+    Ok(ret)
+}
+
+/// Combine several `older` requirements into the single `nSequence` which
+/// satisfies all of them, i.e. the largest. As with `aggregate_locktimes`,
+/// the requirements must all be block-based or all be time-based. An empty
+/// list yields `None`, as there is no requirement on the sequence.
+pub fn aggregate_relative_locktimes(reqs: &[u32]) -> Result<Option<u32>, Error> {
+    let mut ret: Option<u32> = None;
+    for &n in reqs {
+        if let Some(prev) = ret {
+            if n & SEQUENCE_LOCKTIME_TYPE_FLAG != prev & SEQUENCE_LOCKTIME_TYPE_FLAG {
+                return Err(Error::IncompatibleTimelockUnits);
+            }
+        }
+        ret = Some(cmp::max(ret.unwrap_or(0), n));
+    }
+    Ok(ret)
+}
+
+/// Try to satisfy each of several scripts in turn, returning the index and
+/// witness of the first one the satisfier can satisfy. This suits wallets
+/// holding several variants of a script, e.g. during a migration, which
+/// want to spend via whichever is currently satisfiable.
+pub fn satisfy_any<Pk, Ctx, S>(
+    scripts: &[Miniscript<Pk, Ctx>],
+    satisfier: S,
+) -> Option<(usize, Vec<Vec<u8>>)>
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    Ctx: ScriptContext,
+    S: Satisfier<Pk>,
+{
+    scripts
+        .iter()
+        .enumerate()
+        .filter_map(|(i, ms)| ms.satisfy(&satisfier).ok().map(|wit| (i, wit)))
+        .next()
+}
+
 impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for HashMap<Pk, BitcoinSig> {
     fn lookup_sig(&self, key: &Pk) -> Option<BitcoinSig> {
         self.get(key).map(|x| *x)
@@ -170,15 +390,663 @@ where
     }
 }
 
+/// Satisfier holding a signature for each key, which serves both `pk` and
+/// `pkh` fragments: keys for `pkh` are found by hashing each stored key, so
+/// no separate map from hashes to keys needs to be kept
+pub struct SignerStore<Pk: MiniscriptKey>(pub HashMap<Pk, BitcoinSig>);
+
+impl<Pk: MiniscriptKey + ToPublicKey> SignerStore<Pk> {
+    fn find_pkh(&self, pk_hash: &Pk::Hash) -> Option<(&Pk, &BitcoinSig)> {
+        self.0.iter().find(|&(pk, _)| pk.to_pubkeyhash() == *pk_hash)
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for SignerStore<Pk> {
+    fn lookup_sig(&self, key: &Pk) -> Option<BitcoinSig> {
+        self.0.get(key).cloned()
+    }
+
+    fn lookup_pkh_pk(&self, pk_hash: &Pk::Hash) -> Option<Pk> {
+        self.find_pkh(pk_hash).map(|(pk, _)| pk.clone())
+    }
+
+    fn lookup_pkh_sig(&self, pk_hash: &Pk::Hash) -> Option<(dogecoin::PublicKey, BitcoinSig)> {
+        self.find_pkh(pk_hash).map(|(pk, sig)| (pk.to_public_key(), *sig))
+    }
+}
+
+/// Satisfier holding signatures as raw DER-encoded bytes followed by the
+/// sighash byte, as produced by external signing tools. Signatures are
+/// parsed with `bitcoinsig_from_rawsig` when looked up, and any which fail
+/// to parse are treated as missing.
+pub struct RawSigSatisfier<Pk: MiniscriptKey>(pub HashMap<Pk, Vec<u8>>);
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for RawSigSatisfier<Pk> {
+    fn lookup_sig(&self, key: &Pk) -> Option<BitcoinSig> {
+        match self.0.get(key) {
+            Some(rawsig) if !rawsig.is_empty() => bitcoinsig_from_rawsig(rawsig).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Satisfier holding signatures keyed by the bare secp256k1 keys of their
+/// public keys, for wallets which only use compressed keys. Uncompressed
+/// keys are never found.
+pub struct CompressedKeySatisfier(pub HashMap<secp256k1::PublicKey, BitcoinSig>);
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for CompressedKeySatisfier {
+    fn lookup_sig(&self, key: &Pk) -> Option<BitcoinSig> {
+        let pk = key.to_public_key();
+        if pk.compressed {
+            self.0.get(&pk.key).cloned()
+        } else {
+            None
+        }
+    }
+}
+
+/// Satisfier which looks up hash preimages by calling `F` with the hash
+/// being satisfied, tagged by hash type. This allows preimages to be loaded
+/// lazily, e.g. from disk or a database, rather than all held in memory.
+pub struct PreimageFn<F>(RefCell<F>);
+
+impl<F> PreimageFn<F>
+where
+    F: FnMut(HashLockType) -> Option<Preimage32>,
+{
+    /// Create a new preimage satisfier from a lookup closure
+    pub fn new(lookup: F) -> Self {
+        PreimageFn(RefCell::new(lookup))
+    }
+
+    fn lookup(&self, h: HashLockType) -> Option<Preimage32> {
+        (&mut *self.0.borrow_mut())(h)
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey, F> Satisfier<Pk> for PreimageFn<F>
+where
+    F: FnMut(HashLockType) -> Option<Preimage32>,
+{
+    fn lookup_sha256(&self, h: sha256::Hash) -> Option<Preimage32> {
+        self.lookup(HashLockType::Sha256(&h))
+    }
+
+    fn lookup_hash256(&self, h: sha256d::Hash) -> Option<Preimage32> {
+        self.lookup(HashLockType::Hash256(&h))
+    }
+
+    fn lookup_ripemd160(&self, h: ripemd160::Hash) -> Option<Preimage32> {
+        self.lookup(HashLockType::Ripemd160(&h))
+    }
+
+    fn lookup_hash160(&self, h: hash160::Hash) -> Option<Preimage32> {
+        self.lookup(HashLockType::Hash160(&h))
+    }
+}
+
+/// Satisfier holding hash preimages which can be shared between the
+/// satisfiers of many inputs, e.g. when sweeping a batch of HTLCs locked to
+/// the same hashes. Clones share the same preimages, so each is resolved
+/// and inserted once, and is then found by any clone under each of the four
+/// hash types.
+#[derive(Clone, Debug, Default)]
+pub struct SharedPreimageCache(Arc<RwLock<PreimageMaps>>);
+
+#[derive(Debug, Default)]
+struct PreimageMaps {
+    sha256: HashMap<sha256::Hash, Preimage32>,
+    hash256: HashMap<sha256d::Hash, Preimage32>,
+    ripemd160: HashMap<ripemd160::Hash, Preimage32>,
+    hash160: HashMap<hash160::Hash, Preimage32>,
+}
+
+impl SharedPreimageCache {
+    /// Create a new, empty, preimage cache
+    pub fn new() -> Self {
+        SharedPreimageCache::default()
+    }
+
+    /// Add a preimage to the cache, and so to every clone of it
+    pub fn insert(&self, preimage: Preimage32) {
+        let mut maps = self.0.write().unwrap_or_else(|e| e.into_inner());
+        maps.sha256.insert(sha256::Hash::hash(&preimage), preimage);
+        maps.hash256.insert(sha256d::Hash::hash(&preimage), preimage);
+        maps.ripemd160.insert(ripemd160::Hash::hash(&preimage), preimage);
+        maps.hash160.insert(hash160::Hash::hash(&preimage), preimage);
+    }
+
+    fn maps(&self) -> RwLockReadGuard<PreimageMaps> {
+        self.0.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for SharedPreimageCache {
+    fn lookup_sha256(&self, h: sha256::Hash) -> Option<Preimage32> {
+        self.maps().sha256.get(&h).cloned()
+    }
+
+    fn lookup_hash256(&self, h: sha256d::Hash) -> Option<Preimage32> {
+        self.maps().hash256.get(&h).cloned()
+    }
+
+    fn lookup_ripemd160(&self, h: ripemd160::Hash) -> Option<Preimage32> {
+        self.maps().ripemd160.get(&h).cloned()
+    }
+
+    fn lookup_hash160(&self, h: hash160::Hash) -> Option<Preimage32> {
+        self.maps().hash160.get(&h).cloned()
+    }
+}
+
+/// Satisfier for keys derived from extended public keys, e.g. those of a
+/// ranged descriptor at some index. `M` maps a queried key to the xpub and
+/// path it was derived from, and `S` returns a signature for the key at
+/// that derivation. The derivation is checked before `S` is called, so a
+/// stale or wrong mapping yields no signature rather than a bad one.
+pub struct RangedSigner<'secp, C: 'secp + secp256k1::Verification, M, S> {
+    secp: &'secp secp256k1::Secp256k1<C>,
+    derivation: RefCell<M>,
+    sign: RefCell<S>,
+}
+
+impl<'secp, C: secp256k1::Verification, M, S> RangedSigner<'secp, C, M, S> {
+    /// Create a new ranged signer from a derivation lookup and a signing
+    /// closure
+    pub fn new(secp: &'secp secp256k1::Secp256k1<C>, derivation: M, sign: S) -> Self {
+        RangedSigner {
+            secp: secp,
+            derivation: RefCell::new(derivation),
+            sign: RefCell::new(sign),
+        }
+    }
+}
+
+impl<'secp, Pk, C, M, S> Satisfier<Pk> for RangedSigner<'secp, C, M, S>
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    C: secp256k1::Verification,
+    M: FnMut(&Pk) -> Option<(bip32::ExtendedPubKey, bip32::DerivationPath)>,
+    S: FnMut(&bip32::ExtendedPubKey, &bip32::DerivationPath) -> Option<BitcoinSig>,
+{
+    fn lookup_sig(&self, pk: &Pk) -> Option<BitcoinSig> {
+        let (xpub, path) = (&mut *self.derivation.borrow_mut())(pk)?;
+        let derived = xpub.derive_pub(self.secp, &path).ok()?;
+        if derived.public_key != pk.to_public_key() {
+            return None;
+        }
+        (&mut *self.sign.borrow_mut())(&xpub, &path)
+    }
+}
+
+/// Satisfier which obtains signatures from an external signer, such as a
+/// hardware wallet, by calling `F` with the key and the sighash message of
+/// the input being satisfied. The callback may block until the signer
+/// responds; returning `None` treats the signature as missing.
+pub struct BlockingSigner<F> {
+    sighash: secp256k1::Message,
+    sign: F,
+}
+
+impl<F> BlockingSigner<F> {
+    /// Create a new blocking signer for the input whose sighash message is
+    /// `sighash`, from a signing callback
+    pub fn new(sighash: secp256k1::Message, sign: F) -> Self {
+        BlockingSigner {
+            sighash: sighash,
+            sign: sign,
+        }
+    }
+}
+
+impl<Pk, F> Satisfier<Pk> for BlockingSigner<F>
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    F: Fn(&Pk, secp256k1::Message) -> Option<BitcoinSig>,
+{
+    fn lookup_sig(&self, pk: &Pk) -> Option<BitcoinSig> {
+        (self.sign)(pk, self.sighash)
+    }
+}
+
+/// Satisfier wrapping another, which checks that each signature it returns
+/// was produced with an RFC6979 deterministic nonce. Signatures by keys in
+/// `keys` are recomputed over the message returned by `sighash` for their
+/// sighash type, and any signature which differs is rejected, as it points
+/// to a faulty or compromised signer. Signatures by other keys are passed
+/// through unchecked.
+///
+/// Signers which grind for a low R value, or otherwise add entropy to their
+/// nonces, will have their signatures rejected.
+pub struct VerifyingSigner<'secp, C: 'secp + secp256k1::Signing, M, S> {
+    secp: &'secp secp256k1::Secp256k1<C>,
+    keys: HashMap<dogecoin::PublicKey, secp256k1::SecretKey>,
+    sighash: RefCell<M>,
+    inner: S,
+}
+
+impl<'secp, C: secp256k1::Signing, M, S> VerifyingSigner<'secp, C, M, S> {
+    /// Create a new verifying signer from the secret keys to check, a
+    /// lookup of the message signed for each sighash type, and the wrapped
+    /// satisfier
+    pub fn new(
+        secp: &'secp secp256k1::Secp256k1<C>,
+        keys: HashMap<dogecoin::PublicKey, secp256k1::SecretKey>,
+        sighash: M,
+        inner: S,
+    ) -> Self {
+        VerifyingSigner {
+            secp: secp,
+            keys: keys,
+            sighash: RefCell::new(sighash),
+            inner: inner,
+        }
+    }
+
+    fn check_sig(&self, pk: &dogecoin::PublicKey, sig: BitcoinSig) -> Option<BitcoinSig>
+    where
+        M: FnMut(dogecoin::SigHashType) -> Option<secp256k1::Message>,
+    {
+        match self.keys.get(pk) {
+            Some(sk) => {
+                let msg = (&mut *self.sighash.borrow_mut())(sig.1)?;
+                if self.secp.sign(&msg, sk) == sig.0 {
+                    Some(sig)
+                } else {
+                    None
+                }
+            }
+            None => Some(sig),
+        }
+    }
+}
+
+impl<'secp, Pk, C, M, S> Satisfier<Pk> for VerifyingSigner<'secp, C, M, S>
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    C: secp256k1::Signing,
+    M: FnMut(dogecoin::SigHashType) -> Option<secp256k1::Message>,
+    S: Satisfier<Pk>,
+{
+    fn lookup_sig(&self, pk: &Pk) -> Option<BitcoinSig> {
+        let sig = self.inner.lookup_sig(pk)?;
+        self.check_sig(&pk.to_public_key(), sig)
+    }
+
+    fn lookup_sig_for_type(
+        &self,
+        pk: &Pk,
+        ty: dogecoin::SigHashType,
+    ) -> Option<secp256k1::Signature> {
+        let sig = self.inner.lookup_sig_for_type(pk, ty)?;
+        self.check_sig(&pk.to_public_key(), (sig, ty)).map(|(sig, _)| sig)
+    }
+
+    fn try_lookup_sig(&self, pk: &Pk) -> Result<Option<BitcoinSig>, SatisfierError> {
+        let sig = self.inner.try_lookup_sig(pk)?;
+        Ok(sig.and_then(|sig| self.check_sig(&pk.to_public_key(), sig)))
+    }
+
+    fn lookup_pkh_pk(&self, pkh: &Pk::Hash) -> Option<Pk> {
+        self.inner.lookup_pkh_pk(pkh)
+    }
+
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<dogecoin::PublicKey> {
+        self.inner.lookup_raw_pkh_pk(pkh)
+    }
+
+    fn lookup_pkh_sig(&self, pkh: &Pk::Hash) -> Option<(dogecoin::PublicKey, BitcoinSig)> {
+        let (pk, sig) = self.inner.lookup_pkh_sig(pkh)?;
+        self.check_sig(&pk, sig).map(|sig| (pk, sig))
+    }
+
+    fn lookup_sha256(&self, h: sha256::Hash) -> Option<Preimage32> {
+        self.inner.lookup_sha256(h)
+    }
+
+    fn lookup_hash256(&self, h: sha256d::Hash) -> Option<Preimage32> {
+        self.inner.lookup_hash256(h)
+    }
+
+    fn lookup_ripemd160(&self, h: ripemd160::Hash) -> Option<Preimage32> {
+        self.inner.lookup_ripemd160(h)
+    }
+
+    fn lookup_hash160(&self, h: hash160::Hash) -> Option<Preimage32> {
+        self.inner.lookup_hash160(h)
+    }
+
+    fn check_older(&self, t: u32) -> bool {
+        self.inner.check_older(t)
+    }
+
+    fn check_after(&self, t: u32) -> bool {
+        self.inner.check_after(t)
+    }
+
+    fn preimage_whitelist(&self) -> Option<&HashSet<Preimage32>> {
+        self.inner.preimage_whitelist()
+    }
+
+    fn sighash_byte_policy(&self) -> SigHashBytePolicy {
+        self.inner.sighash_byte_policy()
+    }
+
+    fn key_priority(&self, pk: &Pk) -> Option<usize> {
+        self.inner.key_priority(pk)
+    }
+}
+
+/// Satisfier wrapping another, which biases satisfaction toward the keys
+/// in `order`, earlier keys first. When a `multi` or `thresh` has more
+/// signatures available than it needs, the signatures of listed keys are
+/// used in preference to those of unlisted ones, e.g. to keep a cold key's
+/// signature out of the witness when a hot key's will do.
+pub struct PreferredKeys<Pk: MiniscriptKey, S> {
+    /// The wrapped satisfier
+    pub inner: S,
+    /// The preferred keys, most preferred first
+    pub order: Vec<Pk>,
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for PreferredKeys<Pk, S> {
+    fn lookup_sig(&self, pk: &Pk) -> Option<BitcoinSig> {
+        self.inner.lookup_sig(pk)
+    }
+
+    fn lookup_sig_for_type(
+        &self,
+        pk: &Pk,
+        ty: dogecoin::SigHashType,
+    ) -> Option<secp256k1::Signature> {
+        self.inner.lookup_sig_for_type(pk, ty)
+    }
+
+    fn try_lookup_sig(&self, pk: &Pk) -> Result<Option<BitcoinSig>, SatisfierError> {
+        self.inner.try_lookup_sig(pk)
+    }
+
+    fn lookup_pkh_pk(&self, pkh: &Pk::Hash) -> Option<Pk> {
+        self.inner.lookup_pkh_pk(pkh)
+    }
+
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<dogecoin::PublicKey> {
+        self.inner.lookup_raw_pkh_pk(pkh)
+    }
+
+    fn lookup_pkh_sig(&self, pkh: &Pk::Hash) -> Option<(dogecoin::PublicKey, BitcoinSig)> {
+        self.inner.lookup_pkh_sig(pkh)
+    }
+
+    fn lookup_sha256(&self, h: sha256::Hash) -> Option<Preimage32> {
+        self.inner.lookup_sha256(h)
+    }
+
+    fn lookup_hash256(&self, h: sha256d::Hash) -> Option<Preimage32> {
+        self.inner.lookup_hash256(h)
+    }
+
+    fn lookup_ripemd160(&self, h: ripemd160::Hash) -> Option<Preimage32> {
+        self.inner.lookup_ripemd160(h)
+    }
+
+    fn lookup_hash160(&self, h: hash160::Hash) -> Option<Preimage32> {
+        self.inner.lookup_hash160(h)
+    }
+
+    fn check_older(&self, t: u32) -> bool {
+        self.inner.check_older(t)
+    }
+
+    fn check_after(&self, t: u32) -> bool {
+        self.inner.check_after(t)
+    }
+
+    fn preimage_whitelist(&self) -> Option<&HashSet<Preimage32>> {
+        self.inner.preimage_whitelist()
+    }
+
+    fn sighash_byte_policy(&self) -> SigHashBytePolicy {
+        self.inner.sighash_byte_policy()
+    }
+
+    fn key_priority(&self, pk: &Pk) -> Option<usize> {
+        self.order
+            .iter()
+            .position(|k| k == pk)
+            .or_else(|| self.inner.key_priority(pk))
+    }
+}
+
+/// Satisfier wrapping another, which only accepts `older` fragments of one
+/// unit: blocks, or units of 512 seconds if `time_based` is set. A
+/// transaction's sequence number can only meet relative timelocks of one
+/// unit, so satisfactions are retried under this when they might mix them.
+pub(crate) struct OlderUnit<S> {
+    inner: S,
+    time_based: bool,
+    max: u32,
+}
+
+impl<S> OlderUnit<S> {
+    pub(crate) fn new(inner: S, time_based: bool) -> Self {
+        OlderUnit {
+            inner: inner,
+            time_based: time_based,
+            max: 0xffff,
+        }
+    }
+
+    /// Only accept the `older` fragments which the sequence number
+    /// `sequence` meets, i.e. those of its unit up to its value
+    pub(crate) fn up_to(inner: S, sequence: u32) -> Self {
+        OlderUnit {
+            inner: inner,
+            time_based: sequence & SEQUENCE_LOCKTIME_TYPE_FLAG != 0,
+            max: sequence & 0xffff,
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for OlderUnit<S> {
+    fn lookup_sig(&self, pk: &Pk) -> Option<BitcoinSig> {
+        self.inner.lookup_sig(pk)
+    }
+
+    fn lookup_sig_for_type(
+        &self,
+        pk: &Pk,
+        ty: dogecoin::SigHashType,
+    ) -> Option<secp256k1::Signature> {
+        self.inner.lookup_sig_for_type(pk, ty)
+    }
+
+    fn try_lookup_sig(&self, pk: &Pk) -> Result<Option<BitcoinSig>, SatisfierError> {
+        self.inner.try_lookup_sig(pk)
+    }
+
+    fn lookup_pkh_pk(&self, pkh: &Pk::Hash) -> Option<Pk> {
+        self.inner.lookup_pkh_pk(pkh)
+    }
+
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<dogecoin::PublicKey> {
+        self.inner.lookup_raw_pkh_pk(pkh)
+    }
+
+    fn lookup_pkh_sig(&self, pkh: &Pk::Hash) -> Option<(dogecoin::PublicKey, BitcoinSig)> {
+        self.inner.lookup_pkh_sig(pkh)
+    }
+
+    fn lookup_sha256(&self, h: sha256::Hash) -> Option<Preimage32> {
+        self.inner.lookup_sha256(h)
+    }
+
+    fn lookup_hash256(&self, h: sha256d::Hash) -> Option<Preimage32> {
+        self.inner.lookup_hash256(h)
+    }
+
+    fn lookup_ripemd160(&self, h: ripemd160::Hash) -> Option<Preimage32> {
+        self.inner.lookup_ripemd160(h)
+    }
+
+    fn lookup_hash160(&self, h: hash160::Hash) -> Option<Preimage32> {
+        self.inner.lookup_hash160(h)
+    }
+
+    fn check_older(&self, t: u32) -> bool {
+        (t & SEQUENCE_LOCKTIME_TYPE_FLAG != 0) == self.time_based
+            && t & 0xffff <= self.max
+            && self.inner.check_older(t)
+    }
+
+    fn check_after(&self, t: u32) -> bool {
+        self.inner.check_after(t)
+    }
+
+    fn preimage_whitelist(&self) -> Option<&HashSet<Preimage32>> {
+        self.inner.preimage_whitelist()
+    }
+
+    fn sighash_byte_policy(&self) -> SigHashBytePolicy {
+        self.inner.sighash_byte_policy()
+    }
+
+    fn key_priority(&self, pk: &Pk) -> Option<usize> {
+        self.inner.key_priority(pk)
+    }
+}
+
+/// Satisfier wrapping another, which looks up signatures with
+/// `try_lookup_sig` and keeps the first error, so that satisfaction can
+/// treat the signature as unavailable and report the error afterwards
+pub(super) struct SigErrors<S> {
+    inner: S,
+    error: RefCell<Option<SatisfierError>>,
+}
+
+impl<S> SigErrors<S> {
+    pub(super) fn new(inner: S) -> Self {
+        SigErrors {
+            inner: inner,
+            error: RefCell::new(None),
+        }
+    }
+
+    /// Fail with the first error met while looking up a signature, if any,
+    /// for callers which found no satisfaction without that signature
+    pub(super) fn check(&self) -> Result<(), Error> {
+        match *self.error.borrow() {
+            Some(ref e) => Err(Error::Satisfier(e.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for SigErrors<S> {
+    fn lookup_sig(&self, pk: &Pk) -> Option<BitcoinSig> {
+        match self.inner.try_lookup_sig(pk) {
+            Ok(sig) => sig,
+            Err(e) => {
+                let mut error = self.error.borrow_mut();
+                if error.is_none() {
+                    *error = Some(e);
+                }
+                None
+            }
+        }
+    }
+
+    fn lookup_sig_for_type(
+        &self,
+        pk: &Pk,
+        ty: dogecoin::SigHashType,
+    ) -> Option<secp256k1::Signature> {
+        // Satisfiers which only implement `try_lookup_sig` find nothing by
+        // default here, so fall back to it, keeping its errors
+        match self.inner.lookup_sig_for_type(pk, ty) {
+            Some(sig) => Some(sig),
+            None => match self.lookup_sig(pk) {
+                Some((sig, sig_ty)) if sig_ty == ty => Some(sig),
+                _ => None,
+            },
+        }
+    }
+
+    fn lookup_pkh_pk(&self, pkh: &Pk::Hash) -> Option<Pk> {
+        self.inner.lookup_pkh_pk(pkh)
+    }
+
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<dogecoin::PublicKey> {
+        self.inner.lookup_raw_pkh_pk(pkh)
+    }
+
+    fn lookup_pkh_sig(&self, pkh: &Pk::Hash) -> Option<(dogecoin::PublicKey, BitcoinSig)> {
+        self.inner.lookup_pkh_sig(pkh)
+    }
+
+    fn lookup_sha256(&self, h: sha256::Hash) -> Option<Preimage32> {
+        self.inner.lookup_sha256(h)
+    }
+
+    fn lookup_hash256(&self, h: sha256d::Hash) -> Option<Preimage32> {
+        self.inner.lookup_hash256(h)
+    }
+
+    fn lookup_ripemd160(&self, h: ripemd160::Hash) -> Option<Preimage32> {
+        self.inner.lookup_ripemd160(h)
+    }
+
+    fn lookup_hash160(&self, h: hash160::Hash) -> Option<Preimage32> {
+        self.inner.lookup_hash160(h)
+    }
+
+    fn check_older(&self, t: u32) -> bool {
+        self.inner.check_older(t)
+    }
+
+    fn check_after(&self, t: u32) -> bool {
+        self.inner.check_after(t)
+    }
+
+    fn preimage_whitelist(&self) -> Option<&HashSet<Preimage32>> {
+        self.inner.preimage_whitelist()
+    }
+
+    fn sighash_byte_policy(&self) -> SigHashBytePolicy {
+        self.inner.sighash_byte_policy()
+    }
+
+    fn key_priority(&self, pk: &Pk) -> Option<usize> {
+        self.inner.key_priority(pk)
+    }
+}
+
 impl<'a, Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &'a S {
     fn lookup_sig(&self, p: &Pk) -> Option<BitcoinSig> {
         (**self).lookup_sig(p)
     }
 
+    fn lookup_sig_for_type(
+        &self,
+        p: &Pk,
+        ty: dogecoin::SigHashType,
+    ) -> Option<secp256k1::Signature> {
+        (**self).lookup_sig_for_type(p, ty)
+    }
+
+    fn try_lookup_sig(&self, p: &Pk) -> Result<Option<BitcoinSig>, SatisfierError> {
+        (**self).try_lookup_sig(p)
+    }
+
     fn lookup_pkh_pk(&self, pkh: &Pk::Hash) -> Option<Pk> {
         (**self).lookup_pkh_pk(pkh)
     }
 
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<dogecoin::PublicKey> {
+        (**self).lookup_raw_pkh_pk(pkh)
+    }
+
     fn lookup_pkh_sig(&self, pkh: &Pk::Hash) -> Option<(dogecoin::PublicKey, BitcoinSig)> {
         (**self).lookup_pkh_sig(pkh)
     }
@@ -199,24 +1067,119 @@ impl<'a, Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &'
         (**self).lookup_hash160(h)
     }
 
-    fn check_older(&self, t: u32) -> bool {
-        (**self).check_older(t)
+    fn check_older(&self, t: u32) -> bool {
+        (**self).check_older(t)
+    }
+
+    fn check_after(&self, t: u32) -> bool {
+        (**self).check_after(t)
+    }
+
+    fn preimage_whitelist(&self) -> Option<&HashSet<Preimage32>> {
+        (**self).preimage_whitelist()
+    }
+
+    fn sighash_byte_policy(&self) -> SigHashBytePolicy {
+        (**self).sighash_byte_policy()
+    }
+
+    fn key_priority(&self, pk: &Pk) -> Option<usize> {
+        (**self).key_priority(pk)
+    }
+}
+
+impl<'a, Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &'a mut S {
+    fn lookup_sig(&self, p: &Pk) -> Option<BitcoinSig> {
+        (**self).lookup_sig(p)
+    }
+
+    fn lookup_sig_for_type(
+        &self,
+        p: &Pk,
+        ty: dogecoin::SigHashType,
+    ) -> Option<secp256k1::Signature> {
+        (**self).lookup_sig_for_type(p, ty)
+    }
+
+    fn try_lookup_sig(&self, p: &Pk) -> Result<Option<BitcoinSig>, SatisfierError> {
+        (**self).try_lookup_sig(p)
+    }
+
+    fn lookup_pkh_pk(&self, pkh: &Pk::Hash) -> Option<Pk> {
+        (**self).lookup_pkh_pk(pkh)
+    }
+
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<dogecoin::PublicKey> {
+        (**self).lookup_raw_pkh_pk(pkh)
+    }
+
+    fn lookup_pkh_sig(&self, pkh: &Pk::Hash) -> Option<(dogecoin::PublicKey, BitcoinSig)> {
+        (**self).lookup_pkh_sig(pkh)
+    }
+
+    fn lookup_sha256(&self, h: sha256::Hash) -> Option<Preimage32> {
+        (**self).lookup_sha256(h)
+    }
+
+    fn lookup_hash256(&self, h: sha256d::Hash) -> Option<Preimage32> {
+        (**self).lookup_hash256(h)
+    }
+
+    fn lookup_ripemd160(&self, h: ripemd160::Hash) -> Option<Preimage32> {
+        (**self).lookup_ripemd160(h)
+    }
+
+    fn lookup_hash160(&self, h: hash160::Hash) -> Option<Preimage32> {
+        (**self).lookup_hash160(h)
+    }
+
+    fn check_older(&self, t: u32) -> bool {
+        (**self).check_older(t)
+    }
+
+    fn check_after(&self, t: u32) -> bool {
+        (**self).check_after(t)
+    }
+
+    fn preimage_whitelist(&self) -> Option<&HashSet<Preimage32>> {
+        (**self).preimage_whitelist()
+    }
+
+    fn sighash_byte_policy(&self) -> SigHashBytePolicy {
+        (**self).sighash_byte_policy()
     }
 
-    fn check_after(&self, t: u32) -> bool {
-        (**self).check_after(t)
+    fn key_priority(&self, pk: &Pk) -> Option<usize> {
+        (**self).key_priority(pk)
     }
 }
 
-impl<'a, Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &'a mut S {
+// Allows heterogeneous satisfiers to be stored as `Box<Satisfier<Pk>>`
+impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk> + ?Sized> Satisfier<Pk> for Box<S> {
     fn lookup_sig(&self, p: &Pk) -> Option<BitcoinSig> {
         (**self).lookup_sig(p)
     }
 
+    fn lookup_sig_for_type(
+        &self,
+        p: &Pk,
+        ty: dogecoin::SigHashType,
+    ) -> Option<secp256k1::Signature> {
+        (**self).lookup_sig_for_type(p, ty)
+    }
+
+    fn try_lookup_sig(&self, p: &Pk) -> Result<Option<BitcoinSig>, SatisfierError> {
+        (**self).try_lookup_sig(p)
+    }
+
     fn lookup_pkh_pk(&self, pkh: &Pk::Hash) -> Option<Pk> {
         (**self).lookup_pkh_pk(pkh)
     }
 
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<dogecoin::PublicKey> {
+        (**self).lookup_raw_pkh_pk(pkh)
+    }
+
     fn lookup_pkh_sig(&self, pkh: &Pk::Hash) -> Option<(dogecoin::PublicKey, BitcoinSig)> {
         (**self).lookup_pkh_sig(pkh)
     }
@@ -244,6 +1207,18 @@ impl<'a, Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &'
     fn check_after(&self, t: u32) -> bool {
         (**self).check_after(t)
     }
+
+    fn preimage_whitelist(&self) -> Option<&HashSet<Preimage32>> {
+        (**self).preimage_whitelist()
+    }
+
+    fn sighash_byte_policy(&self) -> SigHashBytePolicy {
+        (**self).sighash_byte_policy()
+    }
+
+    fn key_priority(&self, pk: &Pk) -> Option<usize> {
+        (**self).key_priority(pk)
+    }
 }
 
 macro_rules! impl_tuple_satisfier {
@@ -264,6 +1239,37 @@ macro_rules! impl_tuple_satisfier {
                 None
             }
 
+            fn lookup_sig_for_type(
+                &self,
+                key: &Pk,
+                ty: dogecoin::SigHashType,
+            ) -> Option<secp256k1::Signature> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_sig_for_type(key, ty) {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
+            // An error is only reported if no element has the signature
+            fn try_lookup_sig(&self, key: &Pk) -> Result<Option<BitcoinSig>, SatisfierError> {
+                let &($(ref $ty,)*) = self;
+                let mut err = None;
+                $(
+                    match $ty.try_lookup_sig(key) {
+                        Ok(Some(result)) => return Ok(Some(result)),
+                        Ok(None) => {}
+                        Err(e) => err = err.or(Some(e)),
+                    }
+                )*
+                match err {
+                    Some(e) => Err(e),
+                    None => Ok(None),
+                }
+            }
+
             fn lookup_pkh_sig(
                 &self,
                 key_hash: &Pk::Hash,
@@ -290,6 +1296,19 @@ macro_rules! impl_tuple_satisfier {
                 None
             }
 
+            fn lookup_raw_pkh_pk(
+                &self,
+                key_hash: &hash160::Hash,
+            ) -> Option<dogecoin::PublicKey> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_raw_pkh_pk(key_hash) {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
             fn lookup_sha256(&self, h: sha256::Hash) -> Option<Preimage32> {
                 let &($(ref $ty,)*) = self;
                 $(
@@ -349,6 +1368,36 @@ macro_rules! impl_tuple_satisfier {
                 )*
                 false
             }
+
+            fn preimage_whitelist(&self) -> Option<&HashSet<Preimage32>> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.preimage_whitelist() {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
+            fn sighash_byte_policy(&self) -> SigHashBytePolicy {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let SigHashBytePolicy::Require(ty) = $ty.sighash_byte_policy() {
+                        return SigHashBytePolicy::Require(ty);
+                    }
+                )*
+                SigHashBytePolicy::Append
+            }
+
+            fn key_priority(&self, key: &Pk) -> Option<usize> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.key_priority(key) {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
         }
     }
 }
@@ -362,6 +1411,85 @@ impl_tuple_satisfier!(A, B, C, D, E, F);
 impl_tuple_satisfier!(A, B, C, D, E, F, G);
 impl_tuple_satisfier!(A, B, C, D, E, F, G, H);
 
+/// Identifies a fragment of a Miniscript by its position in the pre-order
+/// traversal given by `Miniscript::iter`, where the root is fragment 0
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct FragmentId(pub usize);
+
+/// Branch of an `or_b`, `or_c`, `or_d`, `or_i` or `andor` fragment to use
+/// when satisfying it. For `andor(X,Y,Z)` the left branch is `X` and `Y`,
+/// and the right branch is `Z`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BranchChoice {
+    /// Satisfy the left (first) branch
+    Left,
+    /// Satisfy the right (second) branch
+    Right,
+}
+
+/// Why the satisfier preferred one branch of a fragment to the other, in
+/// the order of precedence the satisfier applies
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ChoiceReason {
+    /// Neither branch can be satisfied by anyone
+    NeitherPossible,
+    /// The other branch cannot be satisfied by anyone
+    OnlyPossible,
+    /// Neither branch needs a signature, so a third party could swap one for
+    /// the other, and neither is used
+    NeitherSafe,
+    /// Only this branch needs no signature. The other, signed, branch could
+    /// be malleated into this one by a third party
+    WithoutSignature,
+    /// Both branches need a signature, and this one has the smaller
+    /// witness, or the only witness available
+    Cheaper,
+}
+
+/// A decision made while satisfying a Miniscript
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SatisfyDecision {
+    /// The branch of an `or_b`, `or_c`, `or_d`, `or_i` or `andor` which was
+    /// used, or `None` if neither could be. For `or_i` this is also made
+    /// when dissatisfying the fragment.
+    Branch {
+        /// The fragment
+        fragment: FragmentId,
+        /// The branch used
+        choice: Option<BranchChoice>,
+        /// Why the branch was used
+        reason: ChoiceReason,
+    },
+    /// The subs of a `thresh` which were satisfied, by their index in the
+    /// fragment; the others were dissatisfied. Subs which need no signature
+    /// are satisfied first, then the cheapest.
+    Thresh {
+        /// The fragment
+        fragment: FragmentId,
+        /// The satisfied subs
+        satisfied: Vec<usize>,
+    },
+}
+
+/// The decisions made by `Miniscript::satisfy_logged`, in the order they
+/// were made. Every fragment is satisfied bottom-up, so this includes
+/// decisions within branches which were not used in the end. Decisions of
+/// `thresh` fragments within another `thresh` are not recorded.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SatisfyLog {
+    /// The decisions
+    pub decisions: Vec<SatisfyDecision>,
+}
+
+/// Treatment of the sighash type of signatures returned by a `Satisfier`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SigHashBytePolicy {
+    /// Append the signature's own sighash type, whatever it is
+    Append,
+    /// Only use signatures with this sighash type
+    Require(dogecoin::SigHashType),
+}
+
 /// A witness, if available, for a Miniscript fragment
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Witness {
@@ -402,7 +1530,12 @@ impl Ord for Witness {
 impl Witness {
     /// Turn a signature into (part of) a satisfaction
     fn signature<Pk: ToPublicKey, S: Satisfier<Pk>>(sat: S, pk: &Pk) -> Self {
-        match sat.lookup_sig(pk) {
+        let sig = match sat.sighash_byte_policy() {
+            SigHashBytePolicy::Append => sat.lookup_sig(pk),
+            SigHashBytePolicy::Require(ty) => sat.lookup_sig_for_type(pk, ty).map(|sig| (sig, ty)),
+        };
+        match sig {
+            Some((_, hashtype)) if !Self::sighash_allowed(&sat, hashtype) => Witness::Impossible,
             Some((sig, hashtype)) => {
                 let mut ret = sig.serialize_der().to_vec();
                 ret.push(hashtype.as_u32() as u8);
@@ -415,8 +1548,12 @@ impl Witness {
 
     /// Turn a public key related to a pkh into (part of) a satisfaction
     fn pkh_public_key<Pk: ToPublicKey, S: Satisfier<Pk>>(sat: S, pkh: &Pk::Hash) -> Self {
-        match sat.lookup_pkh_pk(pkh) {
-            Some(pk) => Witness::Stack(vec![pk.to_public_key().to_bytes()]),
+        let pk = sat
+            .lookup_pkh_pk(pkh)
+            .map(|pk| pk.to_public_key())
+            .or_else(|| sat.lookup_raw_pkh_pk(&Pk::hash_to_hash160(pkh)));
+        match pk {
+            Some(pk) => Witness::Stack(vec![pk.to_bytes()]),
             // public key hashes are assumed to be unavailable
             // instead of impossible since it is the same as pub-key hashes
             None => Witness::Unavailable,
@@ -426,6 +1563,9 @@ impl Witness {
     /// Turn a key/signature pair related to a pkh into (part of) a satisfaction
     fn pkh_signature<Pk: ToPublicKey, S: Satisfier<Pk>>(sat: S, pkh: &Pk::Hash) -> Self {
         match sat.lookup_pkh_sig(pkh) {
+            Some((_, (_, hashtype))) if !Self::sighash_allowed(&sat, hashtype) => {
+                Witness::Impossible
+            }
             Some((pk, (sig, hashtype))) => {
                 let mut ret = sig.serialize_der().to_vec();
                 ret.push(hashtype.as_u32() as u8);
@@ -438,41 +1578,59 @@ impl Witness {
     /// Turn a hash preimage into (part of) a satisfaction
     fn ripemd160_preimage<Pk: ToPublicKey, S: Satisfier<Pk>>(sat: S, h: ripemd160::Hash) -> Self {
         match sat.lookup_ripemd160(h) {
-            Some(pre) => Witness::Stack(vec![pre.to_vec()]),
+            Some(pre) if Self::preimage_allowed(&sat, &pre) => Witness::Stack(vec![pre.to_vec()]),
             // Note hash preimages are unavailable instead of impossible
-            None => Witness::Unavailable,
+            _ => Witness::Unavailable,
         }
     }
 
     /// Turn a hash preimage into (part of) a satisfaction
     fn hash160_preimage<Pk: ToPublicKey, S: Satisfier<Pk>>(sat: S, h: hash160::Hash) -> Self {
         match sat.lookup_hash160(h) {
-            Some(pre) => Witness::Stack(vec![pre.to_vec()]),
+            Some(pre) if Self::preimage_allowed(&sat, &pre) => Witness::Stack(vec![pre.to_vec()]),
             // Note hash preimages are unavailable instead of impossible
-            None => Witness::Unavailable,
+            _ => Witness::Unavailable,
         }
     }
 
     /// Turn a hash preimage into (part of) a satisfaction
     fn sha256_preimage<Pk: ToPublicKey, S: Satisfier<Pk>>(sat: S, h: sha256::Hash) -> Self {
         match sat.lookup_sha256(h) {
-            Some(pre) => Witness::Stack(vec![pre.to_vec()]),
+            Some(pre) if Self::preimage_allowed(&sat, &pre) => Witness::Stack(vec![pre.to_vec()]),
             // Note hash preimages are unavailable instead of impossible
-            None => Witness::Unavailable,
+            _ => Witness::Unavailable,
         }
     }
 
     /// Turn a hash preimage into (part of) a satisfaction
     fn hash256_preimage<Pk: ToPublicKey, S: Satisfier<Pk>>(sat: S, h: sha256d::Hash) -> Self {
         match sat.lookup_hash256(h) {
-            Some(pre) => Witness::Stack(vec![pre.to_vec()]),
+            Some(pre) if Self::preimage_allowed(&sat, &pre) => Witness::Stack(vec![pre.to_vec()]),
             // Note hash preimages are unavailable instead of impossible
-            None => Witness::Unavailable,
+            _ => Witness::Unavailable,
         }
     }
 }
 
 impl Witness {
+    /// Whether the satisfier's sighash policy allows using a signature
+    fn sighash_allowed<Pk: ToPublicKey, S: Satisfier<Pk>>(
+        sat: &S,
+        hashtype: dogecoin::SigHashType,
+    ) -> bool {
+        match sat.sighash_byte_policy() {
+            SigHashBytePolicy::Append => true,
+            SigHashBytePolicy::Require(required) => hashtype == required,
+        }
+    }
+
+    /// Whether the satisfier's whitelist, if any, allows revealing a preimage
+    fn preimage_allowed<Pk: ToPublicKey, S: Satisfier<Pk>>(sat: &S, pre: &Preimage32) -> bool {
+        sat.preimage_whitelist()
+            .map(|whitelist| whitelist.contains(pre))
+            .unwrap_or(true)
+    }
+
     /// Produce something like a 32-byte 0 push
     fn hash_dissatisfaction() -> Self {
         Witness::Stack(vec![vec![0; 32]])
@@ -504,6 +1662,31 @@ impl Witness {
             }
         }
     }
+
+    /// Whether every element short enough to be read as a script number,
+    /// e.g. the `1` selecting a branch of `or_i`, is minimally encoded, as
+    /// standardness requires. Witnesses produced by satisfaction always
+    /// are; this is a safeguard for hand-assembled ones. A witness which is
+    /// not available has no elements, so is trivially minimal.
+    ///
+    /// This cannot check the MINIMALIF rule, which requires the argument of
+    /// an `OP_IF` in a segwit script to be empty or `[1]`: the witness alone
+    /// does not say which of its elements the script passes to `OP_IF`, and
+    /// e.g. `[2]` is a minimal number. The `Interpreter` rejects such
+    /// arguments when evaluating the spend.
+    pub fn is_minimally_encoded(&self) -> bool {
+        let stack = match *self {
+            Witness::Stack(ref stack) => stack,
+            Witness::Unavailable | Witness::Impossible => return true,
+        };
+        stack.iter().filter(|elem| elem.len() <= 4).all(|elem| match elem.split_last() {
+            // A final byte of 0 or 0x80 is only needed for its sign bit
+            Some((last, rest)) if last & 0x7f == 0 => {
+                rest.last().map(|prev| prev & 0x80 != 0).unwrap_or(false)
+            }
+            _ => true,
+        })
+    }
 }
 
 /// A (dis)satisfaction of a Miniscript fragment
@@ -517,6 +1700,22 @@ pub struct Satisfaction {
 }
 
 impl Satisfaction {
+    // the best rank the satisfier gives any key of a signed thresh sub
+    fn key_rank<Pk, Ctx, Sat>(sub: &Miniscript<Pk, Ctx>, sat: &Satisfaction, stfr: &Sat) -> usize
+    where
+        Pk: MiniscriptKey + ToPublicKey,
+        Ctx: ScriptContext,
+        Sat: Satisfier<Pk>,
+    {
+        if !sat.has_sig {
+            return usize::MAX;
+        }
+        sub.iter_pk()
+            .filter_map(|pk| stfr.key_priority(&pk))
+            .min()
+            .unwrap_or(usize::MAX)
+    }
+
     // produce a non-malleable satisafaction for thesh frag
     fn thresh<Pk, Ctx, Sat, F>(
         k: usize,
@@ -529,23 +1728,64 @@ impl Satisfaction {
         Pk: MiniscriptKey + ToPublicKey,
         Ctx: ScriptContext,
         Sat: Satisfier<Pk>,
-        F: FnMut(Satisfaction, Satisfaction) -> Satisfaction,
+        F: FnMut(&Terminal<Pk, Ctx>, Satisfaction, Satisfaction) -> Satisfaction,
+    {
+        Self::thresh_choosing(k, subs, stfr, root_has_sig, min_fn).0
+    }
+
+    // produce a non-malleable satisafaction for thesh frag, along with the
+    // indices of the subs which were satisfied
+    fn thresh_choosing<Pk, Ctx, Sat, F>(
+        k: usize,
+        subs: &[Arc<Miniscript<Pk, Ctx>>],
+        stfr: &Sat,
+        root_has_sig: bool,
+        min_fn: &mut F,
+    ) -> (Self, Vec<usize>)
+    where
+        Pk: MiniscriptKey + ToPublicKey,
+        Ctx: ScriptContext,
+        Sat: Satisfier<Pk>,
+        F: FnMut(&Terminal<Pk, Ctx>, Satisfaction, Satisfaction) -> Satisfaction,
+    {
+        Self::thresh_choosing_with(k, subs, stfr, root_has_sig, min_fn, &mut Self::thresh)
+    }
+
+    // as `thresh_choosing`, with `thresh_fn` satisfying any `thresh` nested
+    // in the subs
+    fn thresh_choosing_with<Pk, Ctx, Sat, F, G>(
+        k: usize,
+        subs: &[Arc<Miniscript<Pk, Ctx>>],
+        stfr: &Sat,
+        root_has_sig: bool,
+        min_fn: &mut F,
+        thresh_fn: &mut G,
+    ) -> (Self, Vec<usize>)
+    where
+        Pk: MiniscriptKey + ToPublicKey,
+        Ctx: ScriptContext,
+        Sat: Satisfier<Pk>,
+        F: FnMut(&Terminal<Pk, Ctx>, Satisfaction, Satisfaction) -> Satisfaction,
+        G: FnMut(usize, &[Arc<Miniscript<Pk, Ctx>>], &Sat, bool, &mut F) -> Satisfaction,
     {
         let mut sats = subs
             .iter()
-            .map(|s| Self::satisfy_helper(&s.node, stfr, root_has_sig, min_fn, &mut Self::thresh))
+            .map(|s| Self::satisfy_helper(&s.node, stfr, root_has_sig, min_fn, thresh_fn))
             .collect::<Vec<_>>();
         // Start with the to-return stack set to all dissatisfactions
         let mut ret_stack = subs
             .iter()
-            .map(|s| {
-                Self::dissatisfy_helper(&s.node, stfr, root_has_sig, min_fn, &mut Self::thresh)
-            })
+            .map(|s| Self::dissatisfy_helper(&s.node, stfr, root_has_sig, min_fn, thresh_fn))
             .collect::<Vec<_>>();
 
         // Sort everything by (sat cost - dissat cost), except that
         // satisfactions without signatures beat satisfactions with
         // signatures
+        let ranks = subs
+            .iter()
+            .zip(&sats)
+            .map(|(sub, sat)| Self::key_rank(sub, sat, stfr))
+            .collect::<Vec<_>>();
         let mut sat_indices = (0..subs.len()).collect::<Vec<_>>();
         sat_indices.sort_by_key(|&i| {
             let stack_weight = match (&sats[i].stack, &ret_stack[i].stack) {
@@ -561,13 +1801,16 @@ impl Satisfaction {
             // First consider the candidates that are not impossible to satisfy
             // by any party. Among those first consider the ones that have no sig
             // because third party can malleate them if they are not chosen.
-            // Lastly, choose by weight.
-            (is_impossible, sats[i].has_sig, stack_weight)
+            // Then prefer the satisfier's preferred keys, and lastly choose by
+            // weight.
+            (is_impossible, sats[i].has_sig, ranks[i], stack_weight)
         });
 
         for i in 0..k {
             mem::swap(&mut ret_stack[sat_indices[i]], &mut sats[sat_indices[i]]);
         }
+        let mut chosen = sat_indices[..k].to_vec();
+        chosen.sort();
 
         // We preferably take satisfactions that are not impossible
         // If we cannot find `k` satisfactions that are not impossible
@@ -575,7 +1818,7 @@ impl Satisfaction {
         // For example, the fragment thresh(2, hash, 0, 0, 0)
         // is has an impossible witness
         assert!(k > 0);
-        if sats[sat_indices[k - 1]].stack == Witness::Impossible {
+        let sat = if sats[sat_indices[k - 1]].stack == Witness::Impossible {
             Satisfaction {
                 stack: Witness::Impossible,
                 // If the witness is impossible, we don't care about the
@@ -615,7 +1858,8 @@ impl Satisfaction {
                     Witness::combine(next.stack, acc)
                 }),
             }
-        }
+        };
+        (sat, chosen)
     }
 
     // produce a possily malleable satisafaction for thesh frag
@@ -630,7 +1874,7 @@ impl Satisfaction {
         Pk: MiniscriptKey + ToPublicKey,
         Ctx: ScriptContext,
         Sat: Satisfier<Pk>,
-        F: FnMut(Satisfaction, Satisfaction) -> Satisfaction,
+        F: FnMut(&Terminal<Pk, Ctx>, Satisfaction, Satisfaction) -> Satisfaction,
     {
         let mut sats = subs
             .iter()
@@ -649,6 +1893,11 @@ impl Satisfaction {
         // Sort everything by (sat cost - dissat cost), except that
         // satisfactions without signatures beat satisfactions with
         // signatures
+        let ranks = subs
+            .iter()
+            .zip(&sats)
+            .map(|(sub, sat)| Self::key_rank(sub, sat, stfr))
+            .collect::<Vec<_>>();
         let mut sat_indices = (0..subs.len()).collect::<Vec<_>>();
         sat_indices.sort_by_key(|&i| {
             let stack_weight = match (&sats[i].stack, &ret_stack[i].stack) {
@@ -659,8 +1908,9 @@ impl Satisfaction {
                     witness_size(s) as i64 - witness_size(d) as i64
                 }
             };
-            // For malleable satifactions, directly choose smallest weights
-            stack_weight
+            // For malleable satifactions, directly choose smallest weights,
+            // after the satisfier's preferred keys
+            (ranks[i], stack_weight)
         });
 
         // swap the satisfactions
@@ -678,6 +1928,15 @@ impl Satisfaction {
         }
     }
 
+    // Choose between two non-malleable alternatives. In order of precedence:
+    // an impossible option always loses; if exactly one option needs a
+    // signature the sig-less one wins, even if *we* cannot produce it (a
+    // third party may be able to, so the signed option is malleable); if
+    // neither needs a signature neither is safe; and otherwise the cheaper
+    // one wins, where an available witness is cheaper than an unavailable one.
+    //
+    // For example in `andor(pk(A),pk(B),pk(C))` both branches need a
+    // signature, so we pick whichever branch we have all signatures for.
     fn minimum(sat1: Self, sat2: Self) -> Self {
         // If there is only one available satisfaction, we must choose that
         // regardless of has_sig marker.
@@ -715,6 +1974,25 @@ impl Satisfaction {
         }
     }
 
+    // The option `minimum` takes, as `Some(false)` for the first and
+    // `Some(true)` for the second, and why
+    fn minimum_reason(sat1: &Self, sat2: &Self) -> (Option<bool>, ChoiceReason) {
+        match (&sat1.stack, &sat2.stack) {
+            (&Witness::Impossible, &Witness::Impossible) => {
+                return (None, ChoiceReason::NeitherPossible)
+            }
+            (&Witness::Impossible, _) => return (Some(true), ChoiceReason::OnlyPossible),
+            (_, &Witness::Impossible) => return (Some(false), ChoiceReason::OnlyPossible),
+            _ => {}
+        }
+        match (sat1.has_sig, sat2.has_sig) {
+            (false, false) => (None, ChoiceReason::NeitherSafe),
+            (false, true) => (Some(false), ChoiceReason::WithoutSignature),
+            (true, false) => (Some(true), ChoiceReason::WithoutSignature),
+            (true, true) => (Some(sat2.stack < sat1.stack), ChoiceReason::Cheaper),
+        }
+    }
+
     // calculate the minimum witness allowing witness malleability
     fn minimum_mall(sat1: Self, sat2: Self) -> Self {
         match (&sat1.stack, &sat2.stack) {
@@ -744,7 +2022,7 @@ impl Satisfaction {
         Pk: MiniscriptKey + ToPublicKey,
         Ctx: ScriptContext,
         Sat: Satisfier<Pk>,
-        F: FnMut(Satisfaction, Satisfaction) -> Satisfaction,
+        F: FnMut(&Terminal<Pk, Ctx>, Satisfaction, Satisfaction) -> Satisfaction,
         G: FnMut(usize, &[Arc<Miniscript<Pk, Ctx>>], &Sat, bool, &mut F) -> Satisfaction,
     {
         match *term {
@@ -842,6 +2120,7 @@ impl Satisfaction {
                 let c_sat = Self::satisfy_helper(&c.node, stfr, root_has_sig, min_fn, thresh_fn);
 
                 min_fn(
+                    term,
                     Satisfaction {
                         stack: Witness::combine(b_sat.stack, a_sat.stack),
                         has_sig: a_sat.has_sig || b_sat.has_sig,
@@ -864,6 +2143,7 @@ impl Satisfaction {
                 assert!(!r_nsat.has_sig);
 
                 min_fn(
+                    term,
                     Satisfaction {
                         stack: Witness::combine(r_sat.stack, l_nsat.stack),
                         has_sig: r_sat.has_sig,
@@ -883,6 +2163,7 @@ impl Satisfaction {
                 assert!(!l_nsat.has_sig);
 
                 min_fn(
+                    term,
                     l_sat,
                     Satisfaction {
                         stack: Witness::combine(r_sat.stack, l_nsat.stack),
@@ -894,6 +2175,7 @@ impl Satisfaction {
                 let l_sat = Self::satisfy_helper(&l.node, stfr, root_has_sig, min_fn, thresh_fn);
                 let r_sat = Self::satisfy_helper(&r.node, stfr, root_has_sig, min_fn, thresh_fn);
                 min_fn(
+                    term,
                     Satisfaction {
                         stack: Witness::combine(l_sat.stack, Witness::push_1()),
                         has_sig: l_sat.has_sig,
@@ -905,14 +2187,22 @@ impl Satisfaction {
                 )
             }
             Terminal::Thresh(k, ref subs) => thresh_fn(k, subs, stfr, root_has_sig, min_fn),
+            // Type-checked Miniscripts have k <= n, but hand-built terms
+            // need not
+            Terminal::Multi(k, ref keys) if k > keys.len() => Satisfaction {
+                stack: Witness::Impossible,
+                has_sig: false,
+            },
             Terminal::Multi(k, ref keys) => {
                 // Collect all available signatures
                 let mut sig_count = 0;
                 let mut sigs = Vec::with_capacity(k);
+                let mut ranks = Vec::with_capacity(k);
                 for pk in keys {
                     match Witness::signature(stfr, pk) {
                         Witness::Stack(sig) => {
                             sigs.push(sig);
+                            ranks.push(stfr.key_priority(pk).unwrap_or(usize::MAX));
                             sig_count += 1;
                         }
                         Witness::Impossible => {}
@@ -928,12 +2218,14 @@ impl Satisfaction {
                         has_sig: false,
                     }
                 } else {
-                    // Throw away the most expensive ones
+                    // Throw away the least preferred ones, and among those
+                    // the most expensive
                     for _ in 0..sig_count - k {
                         let max_idx = sigs
                             .iter()
                             .enumerate()
-                            .max_by_key(|&(_, ref v)| v.len())
+                            .filter(|&(_, ref v)| !v.is_empty())
+                            .max_by_key(|&(i, ref v)| (ranks[i], v.len()))
                             .unwrap()
                             .0;
                         sigs[max_idx] = vec![];
@@ -962,7 +2254,7 @@ impl Satisfaction {
         Pk: MiniscriptKey + ToPublicKey,
         Ctx: ScriptContext,
         Sat: Satisfier<Pk>,
-        F: FnMut(Satisfaction, Satisfaction) -> Satisfaction,
+        F: FnMut(&Terminal<Pk, Ctx>, Satisfaction, Satisfaction) -> Satisfaction,
         G: FnMut(usize, &[Arc<Miniscript<Pk, Ctx>>], &Sat, bool, &mut F) -> Satisfaction,
     {
         match *term {
@@ -1048,7 +2340,7 @@ impl Satisfaction {
                     has_sig: rnsat.has_sig,
                 };
 
-                min_fn(dissat_1, dissat_2)
+                min_fn(term, dissat_1, dissat_2)
             }
             Terminal::Thresh(_, ref subs) => Satisfaction {
                 stack: subs.iter().fold(Witness::empty(), |acc, sub| {
@@ -1066,6 +2358,26 @@ impl Satisfaction {
         }
     }
 
+    /// Produce a dissatisfaction, i.e. a witness which makes the fragment
+    /// evaluate to false without aborting the script
+    pub(super) fn dissatisfy<
+        Pk: MiniscriptKey + ToPublicKey,
+        Ctx: ScriptContext,
+        Sat: Satisfier<Pk>,
+    >(
+        term: &Terminal<Pk, Ctx>,
+        stfr: &Sat,
+        root_has_sig: bool,
+    ) -> Self {
+        Self::dissatisfy_helper(
+            term,
+            stfr,
+            root_has_sig,
+            &mut |_: &Terminal<Pk, Ctx>, sat1, sat2| Satisfaction::minimum(sat1, sat2),
+            &mut Satisfaction::thresh,
+        )
+    }
+
     /// Produce a satisfaction non-malleable satisfaction
     pub(super) fn satisfy<
         Pk: MiniscriptKey + ToPublicKey,
@@ -1080,11 +2392,231 @@ impl Satisfaction {
             term,
             stfr,
             root_has_sig,
-            &mut Satisfaction::minimum,
+            &mut |_: &Terminal<Pk, Ctx>, sat1, sat2| Satisfaction::minimum(sat1, sat2),
+            &mut Satisfaction::thresh,
+        )
+    }
+
+    /// Produce a non-malleable satisfaction, choosing at random between the
+    /// branches of or-like fragments whose satisfactions are equally cheap
+    #[cfg(feature = "rand")]
+    pub(super) fn satisfy_randomized<Pk, Ctx, Sat, R>(
+        term: &Terminal<Pk, Ctx>,
+        stfr: &Sat,
+        root_has_sig: bool,
+        rng: &mut R,
+    ) -> Self
+    where
+        Pk: MiniscriptKey + ToPublicKey,
+        Ctx: ScriptContext,
+        Sat: Satisfier<Pk>,
+        R: secp256k1::rand::RngCore,
+    {
+        Self::satisfy_helper(
+            term,
+            stfr,
+            root_has_sig,
+            &mut |_: &Terminal<Pk, Ctx>, sat1: Satisfaction, sat2: Satisfaction| {
+                // Only ties between signed satisfactions are broken, since
+                // `minimum` would otherwise pick one for non-malleability
+                let tie = match (&sat1.stack, &sat2.stack) {
+                    (&Witness::Stack(ref s1), &Witness::Stack(ref s2)) => {
+                        sat1.has_sig && sat2.has_sig && witness_size(s1) == witness_size(s2)
+                    }
+                    _ => false,
+                };
+                if tie && rng.next_u32() & 1 == 1 {
+                    sat2
+                } else {
+                    Satisfaction::minimum(sat1, sat2)
+                }
+            },
+            &mut Satisfaction::thresh,
+        )
+    }
+
+    /// Produce a non-malleable satisfaction, except that or-like fragments
+    /// for which `choose` returns a branch always take that branch, whether
+    /// or not doing so is optimal or non-malleable
+    pub(super) fn satisfy_with_choice<Pk, Ctx, Sat, F>(
+        term: &Terminal<Pk, Ctx>,
+        stfr: &Sat,
+        root_has_sig: bool,
+        choose: &mut F,
+    ) -> Self
+    where
+        Pk: MiniscriptKey + ToPublicKey,
+        Ctx: ScriptContext,
+        Sat: Satisfier<Pk>,
+        F: FnMut(&Terminal<Pk, Ctx>) -> Option<BranchChoice>,
+    {
+        Self::satisfy_helper(
+            term,
+            stfr,
+            root_has_sig,
+            &mut |term: &Terminal<Pk, Ctx>, sat1, sat2| {
+                // `or_b` passes its right-branch satisfaction first
+                let swap = match *term {
+                    Terminal::OrB(..) => true,
+                    _ => false,
+                };
+                match (choose(term), swap) {
+                    (Some(BranchChoice::Left), false) | (Some(BranchChoice::Right), true) => sat1,
+                    (Some(BranchChoice::Left), true) | (Some(BranchChoice::Right), false) => sat2,
+                    (None, _) => Satisfaction::minimum(sat1, sat2),
+                }
+            },
             &mut Satisfaction::thresh,
         )
     }
 
+    /// Produce a non-malleable satisfaction, recording each decision made
+    /// along the way. `term_id` identifies or-like fragments and
+    /// `thresh_id` identifies `thresh` fragments by their subs.
+    pub(super) fn satisfy_logged<Pk, Ctx, Sat, I, J>(
+        term: &Terminal<Pk, Ctx>,
+        stfr: &Sat,
+        root_has_sig: bool,
+        term_id: I,
+        thresh_id: J,
+    ) -> (Self, SatisfyLog)
+    where
+        Pk: MiniscriptKey + ToPublicKey,
+        Ctx: ScriptContext,
+        Sat: Satisfier<Pk>,
+        I: Fn(&Terminal<Pk, Ctx>) -> Option<FragmentId>,
+        J: Fn(&[Arc<Miniscript<Pk, Ctx>>]) -> Option<FragmentId>,
+    {
+        // Both closures below record into the log
+        let log = RefCell::new(SatisfyLog::default());
+        let sat = Self::satisfy_helper(
+            term,
+            stfr,
+            root_has_sig,
+            &mut |term: &Terminal<Pk, Ctx>, sat1, sat2| {
+                if let Some(fragment) = term_id(term) {
+                    // `or_b` passes its right-branch satisfaction first
+                    let swap = match *term {
+                        Terminal::OrB(..) => true,
+                        _ => false,
+                    };
+                    let (second, reason) = Satisfaction::minimum_reason(&sat1, &sat2);
+                    let choice = second.map(|second| {
+                        if second != swap {
+                            BranchChoice::Right
+                        } else {
+                            BranchChoice::Left
+                        }
+                    });
+                    log.borrow_mut().decisions.push(SatisfyDecision::Branch {
+                        fragment: fragment,
+                        choice: choice,
+                        reason: reason,
+                    });
+                }
+                Satisfaction::minimum(sat1, sat2)
+            },
+            &mut |k, subs, stfr, root_has_sig, min_fn| {
+                let (sat, satisfied) =
+                    Satisfaction::thresh_choosing(k, subs, stfr, root_has_sig, min_fn);
+                if let Some(fragment) = thresh_id(subs) {
+                    log.borrow_mut().decisions.push(SatisfyDecision::Thresh {
+                        fragment: fragment,
+                        satisfied: satisfied,
+                    });
+                }
+                sat
+            },
+        );
+        (sat, log.into_inner())
+    }
+
+    /// Produce a non-malleable satisfaction, except that `thresh` fragments
+    /// for which `choose` returns a set of sub indices satisfy exactly those
+    /// subs and dissatisfy the rest, whether or not doing so is optimal or
+    /// non-malleable. This applies to nested `thresh` fragments too.
+    pub(super) fn satisfy_with_thresh_choice<Pk, Ctx, Sat, F>(
+        term: &Terminal<Pk, Ctx>,
+        stfr: &Sat,
+        root_has_sig: bool,
+        choose: &F,
+    ) -> Self
+    where
+        Pk: MiniscriptKey + ToPublicKey,
+        Ctx: ScriptContext,
+        Sat: Satisfier<Pk>,
+        F: Fn(&[Arc<Miniscript<Pk, Ctx>>]) -> Option<Vec<usize>>,
+    {
+        Self::satisfy_helper(
+            term,
+            stfr,
+            root_has_sig,
+            &mut |_: &Terminal<Pk, Ctx>, sat1, sat2| Satisfaction::minimum(sat1, sat2),
+            &mut |k, subs, stfr, root_has_sig, min_fn| {
+                Self::thresh_with_choice(k, subs, stfr, root_has_sig, min_fn, choose)
+            },
+        )
+    }
+
+    // satisfy a `thresh` fragment for `satisfy_with_thresh_choice`, passing
+    // `choose` down to the `thresh` fragments nested in its subs
+    fn thresh_with_choice<Pk, Ctx, Sat, F, C>(
+        k: usize,
+        subs: &[Arc<Miniscript<Pk, Ctx>>],
+        stfr: &Sat,
+        root_has_sig: bool,
+        min_fn: &mut F,
+        choose: &C,
+    ) -> Self
+    where
+        Pk: MiniscriptKey + ToPublicKey,
+        Ctx: ScriptContext,
+        Sat: Satisfier<Pk>,
+        F: FnMut(&Terminal<Pk, Ctx>, Satisfaction, Satisfaction) -> Satisfaction,
+        C: Fn(&[Arc<Miniscript<Pk, Ctx>>]) -> Option<Vec<usize>>,
+    {
+        let mut thresh_fn =
+            |k, subs: &[Arc<Miniscript<Pk, Ctx>>], stfr: &Sat, root_has_sig, min_fn: &mut F| {
+                Self::thresh_with_choice(k, subs, stfr, root_has_sig, min_fn, choose)
+            };
+        match choose(subs) {
+            Some(chosen) => {
+                let ret_stack = subs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, s)| {
+                        if chosen.contains(&i) {
+                            Self::satisfy_helper(
+                                &s.node,
+                                stfr,
+                                root_has_sig,
+                                min_fn,
+                                &mut thresh_fn,
+                            )
+                        } else {
+                            Self::dissatisfy_helper(
+                                &s.node,
+                                stfr,
+                                root_has_sig,
+                                min_fn,
+                                &mut thresh_fn,
+                            )
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                Satisfaction {
+                    has_sig: ret_stack.iter().any(|sat| sat.has_sig),
+                    stack: ret_stack.into_iter().fold(Witness::empty(), |acc, next| {
+                        Witness::combine(next.stack, acc)
+                    }),
+                }
+            }
+            None => {
+                Self::thresh_choosing_with(k, subs, stfr, root_has_sig, min_fn, &mut thresh_fn).0
+            }
+        }
+    }
+
     /// Produce a satisfaction(possibly malleable)
     pub(super) fn satisfy_mall<
         Pk: MiniscriptKey + ToPublicKey,
@@ -1099,8 +2631,42 @@ impl Satisfaction {
             term,
             stfr,
             root_has_sig,
-            &mut Satisfaction::minimum_mall,
+            &mut |_: &Terminal<Pk, Ctx>, sat1, sat2| Satisfaction::minimum_mall(sat1, sat2),
             &mut Satisfaction::thresh_mall,
         )
     }
+
+    /// The contribution of this satisfaction to the virtual size of a
+    /// transaction input, in vbytes, or `None` if it is not available. When
+    /// `is_segwit` the stack is serialized as a witness, whose bytes count a
+    /// quarter each (rounded up); otherwise it is serialized as pushes in the
+    /// scriptSig, whose bytes count in full. The length prefix of the
+    /// scriptSig is always counted.
+    ///
+    /// For `sh` outputs `redeem_script` is the script pushed last in the
+    /// scriptSig, which for `sh(wsh)` and `sh(wpkh)` is the witness program.
+    /// Callers spending `wsh` outputs must still add the witness script.
+    pub fn vsize(
+        &self,
+        is_segwit: bool,
+        redeem_script: Option<&dogecoin::Script>,
+    ) -> Option<usize> {
+        let stack = match self.stack {
+            Witness::Stack(ref stack) => stack,
+            Witness::Unavailable | Witness::Impossible => return None,
+        };
+        let redeem_push =
+            redeem_script.map_or(0, |script| push_opcode_size(script.len()) + script.len());
+        if is_segwit {
+            let weight = varint_len(stack.len())
+                + stack
+                    .iter()
+                    .map(|elem| varint_len(elem.len()) + elem.len())
+                    .sum::<usize>();
+            Some(varint_len(redeem_push) + redeem_push + (weight + 3) / 4)
+        } else {
+            let script_sig_len = witness_to_scriptsig(stack).len() + redeem_push;
+            Some(varint_len(script_sig_len) + script_sig_len)
+        }
+    }
 }
@@ -22,9 +22,12 @@
 use super::{sanity_check, Psbt};
 use super::{Error, InputError, PsbtInputSatisfier};
 use dogecoin::secp256k1::{self, Secp256k1};
+use dogecoin::util::psbt;
 use dogecoin::{self, PublicKey, Script};
-use descriptor::DescriptorTrait;
+use descriptor::{DescriptorTrait, DescriptorType};
 use interpreter;
+use std::cmp;
+use util::witness_to_scriptsig;
 use Descriptor;
 use Miniscript;
 use {BareCtx, Legacy, Segwitv0};
@@ -44,7 +47,7 @@ fn get_scriptpubkey(psbt: &Psbt, index: usize) -> Result<&Script, InputError> {
 }
 
 // Get the amount being spent for the psbt input
-fn get_amt(psbt: &Psbt, index: usize) -> Result<u64, InputError> {
+pub(super) fn get_amt(psbt: &Psbt, index: usize) -> Result<u64, InputError> {
     let amt;
     let inp = &psbt.inputs[index];
     if let Some(ref witness_utxo) = inp.witness_utxo {
@@ -206,35 +209,157 @@ pub fn interpreter_check<C: secp256k1::Verification>(
     psbt: &Psbt,
     secp: &Secp256k1<C>,
 ) -> Result<(), Error> {
-    for (index, input) in psbt.inputs.iter().enumerate() {
-        let spk = get_scriptpubkey(psbt, index).map_err(|e| Error::InputError(e, index))?;
-        let empty_script_sig = Script::new();
-        let empty_witness = Vec::new();
-        let script_sig = input.final_script_sig.as_ref().unwrap_or(&empty_script_sig);
-        let witness = input
-            .final_script_witness
-            .as_ref()
-            .unwrap_or(&empty_witness);
-
-        // Now look at all the satisfied constraints. If everything is filled in
-        // corrected, there should be no errors
-
-        let cltv = psbt.global.unsigned_tx.lock_time;
-        let csv = psbt.global.unsigned_tx.input[index].sequence;
-        let amt = get_amt(psbt, index).map_err(|e| Error::InputError(e, index))?;
-
-        let mut interpreter =
-            interpreter::Interpreter::from_txdata(spk, &script_sig, &witness, cltv, csv)
-                .map_err(|e| Error::InputError(InputError::Interpreter(e), index))?;
-
-        let vfyfn = interpreter.sighash_verify(&secp, &psbt.global.unsigned_tx, index, amt);
-        if let Some(error) = interpreter.iter(vfyfn).filter_map(Result::err).next() {
-            return Err(Error::InputError(InputError::Interpreter(error), index));
+    for index in 0..psbt.inputs.len() {
+        interpreter_check_input(psbt, index, secp).map_err(|e| Error::InputError(e, index))?;
+    }
+    Ok(())
+}
+
+// Interprets the final scriptSig and witness of the input at `index`,
+// returning the keys whose signatures it checked
+fn interpreter_check_input<C: secp256k1::Verification>(
+    psbt: &Psbt,
+    index: usize,
+    secp: &Secp256k1<C>,
+) -> Result<Vec<PublicKey>, InputError> {
+    let input = &psbt.inputs[index];
+    let spk = get_scriptpubkey(psbt, index)?;
+    let empty_script_sig = Script::new();
+    let empty_witness = Vec::new();
+    let script_sig = input.final_script_sig.as_ref().unwrap_or(&empty_script_sig);
+    let witness = input
+        .final_script_witness
+        .as_ref()
+        .unwrap_or(&empty_witness);
+
+    // Now look at all the satisfied constraints. If everything is filled in
+    // corrected, there should be no errors
+
+    let cltv = psbt.global.unsigned_tx.lock_time;
+    let csv = psbt.global.unsigned_tx.input[index].sequence;
+    let amt = get_amt(psbt, index)?;
+
+    let mut interpreter =
+        interpreter::Interpreter::from_txdata(spk, &script_sig, &witness, cltv, csv)
+            .map_err(InputError::Interpreter)?;
+
+    let vfyfn = interpreter.sighash_verify(&secp, &psbt.global.unsigned_tx, index, amt);
+    // Only report keys whose signatures the spend relied on, not ones
+    // inside a dissatisfied branch
+    let constraints = interpreter
+        .iter_contributing(vfyfn)
+        .map_err(InputError::Interpreter)?;
+    let mut keys = vec![];
+    for constraint in constraints {
+        match constraint {
+            interpreter::SatisfiedConstraint::PublicKey { key, .. } => keys.push(*key),
+            interpreter::SatisfiedConstraint::PublicKeyHash { key, .. } => keys.push(key),
+            _ => {}
+        }
+    }
+    Ok(keys)
+}
+
+// Check well-formedness of the signatures of the input at `n`
+fn check_partial_sigs(psbt: &Psbt, n: usize) -> Result<(), InputError> {
+    let input = &psbt.inputs[n];
+    let target = input.sighash_type.unwrap_or(dogecoin::SigHashType::All);
+    for (key, rawsig) in &input.partial_sigs {
+        if rawsig.is_empty() {
+            return Err(InputError::InvalidSignature {
+                pubkey: *key,
+                sig: rawsig.clone(),
+            });
+        }
+        let (flag, sig) = rawsig.split_last().unwrap();
+        let flag = dogecoin::SigHashType::from_u32_standard(*flag as u32).map_err(|_| {
+            InputError::Interpreter(interpreter::Error::NonStandardSigHash(
+                [sig, &[*flag]].concat().to_vec(),
+            ))
+        })?;
+        if target != flag {
+            return Err(InputError::WrongSigHashFlag {
+                required: target,
+                got: flag,
+                pubkey: *key,
+            });
+        }
+        match secp256k1::Signature::from_der(sig) {
+            Err(..) => {
+                return Err(InputError::InvalidSignature {
+                    pubkey: *key,
+                    sig: Vec::from(sig),
+                });
+            }
+            Ok(_sig) => {
+                // Interpreter will check all the sigs later.
+            }
         }
     }
     Ok(())
 }
 
+// Satisfy the input at `index` and fill in its final scriptSig and witness,
+// allowing a malleable satisfaction if `allow_mall` is set
+fn finalize_fields(psbt: &mut Psbt, index: usize, allow_mall: bool) -> Result<(), InputError> {
+    // Get a descriptor for this input
+    let desc = get_descriptor(&psbt, index)?;
+
+    //generate the satisfaction witness and scriptsig
+    let satisfier = PsbtInputSatisfier::new(&psbt, index);
+    let (witness, script_sig) = if allow_mall {
+        desc.get_satisfaction_mall(satisfier)
+    } else {
+        desc.get_satisfaction(satisfier)
+    }
+    .map_err(InputError::MiniscriptError)?;
+
+    set_final_fields(&mut psbt.inputs[index], witness, script_sig);
+    Ok(())
+}
+
+// Finalize the input at `index` and check the result with the interpreter,
+// leaving the input unchanged on failure. Returns the keys whose signatures
+// were used.
+fn finalize_one<C: secp256k1::Verification>(
+    psbt: &mut Psbt,
+    index: usize,
+    secp: &Secp256k1<C>,
+    allow_mall: bool,
+) -> Result<Vec<PublicKey>, InputError> {
+    check_partial_sigs(psbt, index)?;
+
+    let original = psbt.inputs[index].clone();
+    let res = finalize_fields(psbt, index, allow_mall);
+    let res = res.and_then(|()| interpreter_check_input(psbt, index, secp));
+    if res.is_err() {
+        psbt.inputs[index] = original;
+    }
+    res
+}
+
+// Finalize every input, collecting the failures, or the keys whose
+// signatures were used for each input
+fn finalize_all<C: secp256k1::Verification>(
+    psbt: &mut Psbt,
+    secp: &Secp256k1<C>,
+    allow_mall: bool,
+) -> Result<Vec<Vec<PublicKey>>, Vec<(usize, InputError)>> {
+    let mut selection = vec![];
+    let mut errors = vec![];
+    for index in 0..psbt.inputs.len() {
+        match finalize_one(psbt, index, secp, allow_mall) {
+            Ok(keys) => selection.push(keys),
+            Err(e) => errors.push((index, e)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(selection)
+    } else {
+        Err(errors)
+    }
+}
+
 /// Finalize the psbt. This function takes in a mutable reference to psbt
 /// and populates the final_witness and final_scriptsig
 /// of the psbt assuming all of the inputs are miniscript as per BIP174.
@@ -242,109 +367,182 @@ pub fn interpreter_check<C: secp256k1::Verification>(
 /// For satisfaction of individual inputs, use the satisfy API.
 /// This function also performs a sanity interpreter check on the
 /// finalized psbt which involves checking the signatures/ preimages/timelocks.
+///
+/// Every input is attempted, even after one fails: the inputs which pass
+/// are finalized and the failing ones left unchanged, so the psbt may be
+/// partly finalized when an error is returned. Only the error of the first
+/// input which cannot be finalized is returned; use `finalize_report` to get
+/// all of them.
 pub fn finalize<C: secp256k1::Verification>(
     psbt: &mut Psbt,
     secp: &Secp256k1<C>,
 ) -> Result<(), super::Error> {
+    finalize_helper(psbt, secp, false).map(|_| ())
+}
+
+/// Finalize the psbt like `finalize`, and return for each input the keys
+/// whose partial signatures were used in its final scriptSig and witness,
+/// in the order the script checks them. Signatures which were not needed,
+/// e.g. beyond the threshold of a multisig, are left out of the
+/// satisfaction, and cleared from the psbt along with the rest.
+pub fn finalize_with_selection<C: secp256k1::Verification>(
+    psbt: &mut Psbt,
+    secp: &Secp256k1<C>,
+) -> Result<Vec<Vec<PublicKey>>, super::Error> {
+    finalize_helper(psbt, secp, false)
+}
+
+/// Finalize the psbt like `finalize`, but with malleable satisfactions.
+///
+/// Where a script can be satisfied in several ways, the cheapest witness is
+/// used even if it includes an element which a third party could replace,
+/// e.g. a dissatisfaction of a `thresh` subexpression. The signatures stay
+/// valid, so such a replacement cannot redirect funds, but it changes the
+/// wtxid and possibly the fee rate of the transaction as relayed. Use this
+/// only when that is acceptable and a smaller witness is worth it.
+pub fn finalize_mall<C: secp256k1::Verification>(
+    psbt: &mut Psbt,
+    secp: &Secp256k1<C>,
+) -> Result<(), super::Error> {
+    finalize_helper(psbt, secp, true).map(|_| ())
+}
+
+fn finalize_helper<C: secp256k1::Verification>(
+    psbt: &mut Psbt,
+    secp: &Secp256k1<C>,
+    allow_mall: bool,
+) -> Result<Vec<Vec<PublicKey>>, super::Error> {
     sanity_check(psbt)?;
+    finalize_all(psbt, secp, allow_mall).map_err(|errors| {
+        let (index, e) = errors.into_iter().next().expect("at least one error");
+        Error::InputError(e, index)
+    })
+}
 
-    // Check well-formedness of input data
-    for (n, input) in psbt.inputs.iter().enumerate() {
-        let target = input.sighash_type.unwrap_or(dogecoin::SigHashType::All);
-        for (key, rawsig) in &input.partial_sigs {
-            if rawsig.is_empty() {
-                return Err(Error::InputError(
-                    InputError::InvalidSignature {
-                        pubkey: *key,
-                        sig: rawsig.clone(),
-                    },
-                    n,
-                ));
-            }
-            let (flag, sig) = rawsig.split_last().unwrap();
-            let flag = dogecoin::SigHashType::from_u32_standard(*flag as u32).map_err(|_| {
-                super::Error::InputError(
-                    InputError::Interpreter(interpreter::Error::NonStandardSigHash(
-                        [sig, &[*flag]].concat().to_vec(),
-                    )),
-                    n,
-                )
-            })?;
-            if target != flag {
-                return Err(Error::InputError(
-                    InputError::WrongSigHashFlag {
-                        required: target,
-                        got: flag,
-                        pubkey: *key,
-                    },
-                    n,
-                ));
-            }
-            match secp256k1::Signature::from_der(sig) {
-                Err(..) => {
-                    return Err(Error::InputError(
-                        InputError::InvalidSignature {
-                            pubkey: *key,
-                            sig: Vec::from(sig),
-                        },
-                        n,
-                    ));
-                }
-                Ok(_sig) => {
-                    // Interpreter will check all the sigs later.
-                }
-            }
-        }
+/// Finalize the psbt like `finalize`, but return the errors of all inputs
+/// which could not be finalized along with their indices. Those inputs are
+/// left unchanged, while the others are finalized.
+///
+/// If the psbt does not have one input for each input of its unsigned
+/// transaction, no input is finalized, and each index which only one of
+/// them has an input at is reported with `InputError::WrongInputCount`.
+pub fn finalize_report<C: secp256k1::Verification>(
+    psbt: &mut Psbt,
+    secp: &Secp256k1<C>,
+) -> Result<(), Vec<(usize, InputError)>> {
+    let in_tx = psbt.global.unsigned_tx.input.len();
+    let in_map = psbt.inputs.len();
+    if in_tx != in_map {
+        let unmatched = cmp::min(in_tx, in_map)..cmp::max(in_tx, in_map);
+        return Err(unmatched
+            .map(|index| (index, InputError::WrongInputCount { in_tx, in_map }))
+            .collect());
     }
+    finalize_all(psbt, secp, false).map(|_| ())
+}
 
-    // Actually construct the witnesses
-    for index in 0..psbt.inputs.len() {
-        // Get a descriptor for this input
-        let desc = get_descriptor(&psbt, index).map_err(|e| Error::InputError(e, index))?;
-
-        //generate the satisfaction witness and scriptsig
-        let (witness, script_sig) = desc
-            .get_satisfaction(PsbtInputSatisfier::new(&psbt, index))
-            .map_err(|e| Error::InputError(InputError::MiniscriptError(e), index))?;
-
-        let input = &mut psbt.inputs[index];
-        //Fill in the satisfactions
-        input.final_script_sig = if script_sig.is_empty() {
-            None
-        } else {
-            Some(script_sig)
-        };
-        input.final_script_witness = if witness.is_empty() {
-            None
-        } else {
-            Some(witness)
-        };
-        //reset everything
-        input.redeem_script = None;
-        input.partial_sigs.clear();
-        input.sighash_type = None;
-        input.redeem_script = None;
-        input.bip32_derivation.clear();
-        input.witness_script = None;
+/// Finalize only the input at `index`, as `finalize` does for every input,
+/// e.g. once enough signatures for it have been collected. The other inputs
+/// are not touched, and the input is left unchanged if it cannot be
+/// finalized or its finalized spend fails the interpreter check. Fails with
+/// `Error::InputIdxOutofBounds` if the psbt has no input at `index`.
+pub fn finalize_input<C: secp256k1::Verification>(
+    psbt: &mut Psbt,
+    index: usize,
+    secp: &Secp256k1<C>,
+) -> Result<(), super::Error> {
+    sanity_check(psbt)?;
+    if index >= psbt.inputs.len() {
+        return Err(Error::InputIdxOutofBounds {
+            psbt_inp: psbt.inputs.len(),
+            index: index,
+        });
     }
-    // Double check everything with the interpreter
-    // This only checks whether the script will be executed
-    // correctly by the bitcoin interpreter under the current
-    // psbt context.
-    interpreter_check(&psbt, secp)?;
+    finalize_one(psbt, index, secp, false)
+        .map(|_| ())
+        .map_err(|e| Error::InputError(e, index))
+}
+
+/// Install a satisfaction of the input's descriptor which was produced
+/// separately, e.g. by `Miniscript::satisfy` on the descriptor's script,
+/// into the final scriptSig and witness of the input at `index`. `stack`
+/// holds only the satisfying elements; the descriptor adds the pushes of
+/// its redeem or witness script. Like `finalize`, this clears the fields
+/// which are no longer needed once an input is final.
+///
+/// Unlike `finalize` the result is not checked with the interpreter, which
+/// `interpreter_check` can do once every input is final. Fails with
+/// `Error::InputIdxOutofBounds` if the psbt has no input at `index`.
+pub fn install_satisfaction(
+    psbt: &mut Psbt,
+    index: usize,
+    desc: &Descriptor<PublicKey>,
+    mut stack: Vec<Vec<u8>>,
+) -> Result<(), super::Error> {
+    if index >= psbt.inputs.len() {
+        return Err(Error::InputIdxOutofBounds {
+            psbt_inp: psbt.inputs.len(),
+            index: index,
+        });
+    }
+    let (witness, script_sig) = match desc.desc_type() {
+        DescriptorType::Bare | DescriptorType::Pkh => (vec![], witness_to_scriptsig(&stack)),
+        DescriptorType::Sh | DescriptorType::ShSortedMulti => {
+            stack.push(desc.explicit_script().into_bytes());
+            (vec![], witness_to_scriptsig(&stack))
+        }
+        DescriptorType::Wpkh | DescriptorType::ShWpkh => (stack, desc.unsigned_script_sig()),
+        DescriptorType::Wsh
+        | DescriptorType::WshSortedMulti
+        | DescriptorType::ShWsh
+        | DescriptorType::ShWshSortedMulti => {
+            stack.push(desc.explicit_script().into_bytes());
+            (stack, desc.unsigned_script_sig())
+        }
+    };
+    set_final_fields(&mut psbt.inputs[index], witness, script_sig);
     Ok(())
 }
 
+// Fill in the final scriptSig and witness of an input, clearing the fields
+// used to produce them
+fn set_final_fields(input: &mut psbt::Input, witness: Vec<Vec<u8>>, script_sig: Script) {
+    //Fill in the satisfactions
+    input.final_script_sig = if script_sig.is_empty() {
+        None
+    } else {
+        Some(script_sig)
+    };
+    input.final_script_witness = if witness.is_empty() {
+        None
+    } else {
+        Some(witness)
+    };
+    //reset everything
+    input.redeem_script = None;
+    input.partial_sigs.clear();
+    input.sighash_type = None;
+    input.redeem_script = None;
+    input.bip32_derivation.clear();
+    input.witness_script = None;
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use dogecoin::consensus::encode::deserialize;
     use dogecoin::hashes::hex::FromHex;
+    use test_key;
+
+    /// The psbt of the BIP174 test vector for the input finalizer
+    fn bip174_psbt() -> Psbt {
+        deserialize(&Vec::<u8>::from_hex("70736274ff01009a020000000258e87a21b56daf0c23be8e7070456c336f7cbaa5c8757924f545887bb2abdd750000000000ffffffff838d0427d0ec650a68aa46bb0b098aea4422c071b2ca78352a077959d07cea1d0100000000ffffffff0270aaf00800000000160014d85c2b71d0060b09c9886aeb815e50991dda124d00e1f5050000000016001400aea9a2e5f0f876a588df5546e8742d1d87008f00000000000100bb0200000001aad73931018bd25f84ae400b68848be09db706eac2ac18298babee71ab656f8b0000000048473044022058f6fc7c6a33e1b31548d481c826c015bd30135aad42cd67790dab66d2ad243b02204a1ced2604c6735b6393e5b41691dd78b00f0c5942fb9f751856faa938157dba01feffffff0280f0fa020000000017a9140fb9463421696b82c833af241c78c17ddbde493487d0f20a270100000017a91429ca74f8a08f81999428185c97b5d852e4063f6187650000002202029583bf39ae0a609747ad199addd634fa6108559d6c5cd39b4c2183f1ab96e07f473044022074018ad4180097b873323c0015720b3684cc8123891048e7dbcd9b55ad679c99022073d369b740e3eb53dcefa33823c8070514ca55a7dd9544f157c167913261118c01220202dab61ff49a14db6a7d02b0cd1fbb78fc4b18312b5b4e54dae4dba2fbfef536d7483045022100f61038b308dc1da865a34852746f015772934208c6d24454393cd99bdf2217770220056e675a675a6d0a02b85b14e5e29074d8a25a9b5760bea2816f661910a006ea01010304010000000104475221029583bf39ae0a609747ad199addd634fa6108559d6c5cd39b4c2183f1ab96e07f2102dab61ff49a14db6a7d02b0cd1fbb78fc4b18312b5b4e54dae4dba2fbfef536d752ae2206029583bf39ae0a609747ad199addd634fa6108559d6c5cd39b4c2183f1ab96e07f10d90c6a4f000000800000008000000080220602dab61ff49a14db6a7d02b0cd1fbb78fc4b18312b5b4e54dae4dba2fbfef536d710d90c6a4f0000008000000080010000800001012000c2eb0b0000000017a914b7f5faf40e3d40a5a459b1db3535f2b72fa921e887220203089dc10c7ac6db54f91329af617333db388cead0c231f723379d1b99030b02dc473044022062eb7a556107a7c73f45ac4ab5a1dddf6f7075fb1275969a7f383efff784bcb202200c05dbb7470dbf2f08557dd356c7325c1ed30913e996cd3840945db12228da5f012202023add904f3d6dcf59ddb906b0dee23529b7ffb9ed50e5e86151926860221f0e73473044022065f45ba5998b59a27ffe1a7bed016af1f1f90d54b3aa8f7450aa5f56a25103bd02207f724703ad1edb96680b284b56d4ffcb88f7fb759eabbe08aa30f29b851383d2010103040100000001042200208c2353173743b595dfb4a07b72ba8e42e3797da74e87fe7d9d7497e3b2028903010547522103089dc10c7ac6db54f91329af617333db388cead0c231f723379d1b99030b02dc21023add904f3d6dcf59ddb906b0dee23529b7ffb9ed50e5e86151926860221f0e7352ae2206023add904f3d6dcf59ddb906b0dee23529b7ffb9ed50e5e86151926860221f0e7310d90c6a4f000000800000008003000080220603089dc10c7ac6db54f91329af617333db388cead0c231f723379d1b99030b02dc10d90c6a4f00000080000000800200008000220203a9a4c37f5996d3aa25dbac6b570af0650394492942460b354753ed9eeca5877110d90c6a4f000000800000008004000080002202027f6399757d2eff55a136ad02c684b1838b6556e5f1b6b34282a94b6b5005109610d90c6a4f00000080000000800500008000").unwrap()).unwrap()
+    }
 
     #[test]
     fn tests_from_bip174() {
-        let mut psbt: dogecoin::util::psbt::PartiallySignedTransaction = deserialize(&Vec::<u8>::from_hex("70736274ff01009a020000000258e87a21b56daf0c23be8e7070456c336f7cbaa5c8757924f545887bb2abdd750000000000ffffffff838d0427d0ec650a68aa46bb0b098aea4422c071b2ca78352a077959d07cea1d0100000000ffffffff0270aaf00800000000160014d85c2b71d0060b09c9886aeb815e50991dda124d00e1f5050000000016001400aea9a2e5f0f876a588df5546e8742d1d87008f00000000000100bb0200000001aad73931018bd25f84ae400b68848be09db706eac2ac18298babee71ab656f8b0000000048473044022058f6fc7c6a33e1b31548d481c826c015bd30135aad42cd67790dab66d2ad243b02204a1ced2604c6735b6393e5b41691dd78b00f0c5942fb9f751856faa938157dba01feffffff0280f0fa020000000017a9140fb9463421696b82c833af241c78c17ddbde493487d0f20a270100000017a91429ca74f8a08f81999428185c97b5d852e4063f6187650000002202029583bf39ae0a609747ad199addd634fa6108559d6c5cd39b4c2183f1ab96e07f473044022074018ad4180097b873323c0015720b3684cc8123891048e7dbcd9b55ad679c99022073d369b740e3eb53dcefa33823c8070514ca55a7dd9544f157c167913261118c01220202dab61ff49a14db6a7d02b0cd1fbb78fc4b18312b5b4e54dae4dba2fbfef536d7483045022100f61038b308dc1da865a34852746f015772934208c6d24454393cd99bdf2217770220056e675a675a6d0a02b85b14e5e29074d8a25a9b5760bea2816f661910a006ea01010304010000000104475221029583bf39ae0a609747ad199addd634fa6108559d6c5cd39b4c2183f1ab96e07f2102dab61ff49a14db6a7d02b0cd1fbb78fc4b18312b5b4e54dae4dba2fbfef536d752ae2206029583bf39ae0a609747ad199addd634fa6108559d6c5cd39b4c2183f1ab96e07f10d90c6a4f000000800000008000000080220602dab61ff49a14db6a7d02b0cd1fbb78fc4b18312b5b4e54dae4dba2fbfef536d710d90c6a4f0000008000000080010000800001012000c2eb0b0000000017a914b7f5faf40e3d40a5a459b1db3535f2b72fa921e887220203089dc10c7ac6db54f91329af617333db388cead0c231f723379d1b99030b02dc473044022062eb7a556107a7c73f45ac4ab5a1dddf6f7075fb1275969a7f383efff784bcb202200c05dbb7470dbf2f08557dd356c7325c1ed30913e996cd3840945db12228da5f012202023add904f3d6dcf59ddb906b0dee23529b7ffb9ed50e5e86151926860221f0e73473044022065f45ba5998b59a27ffe1a7bed016af1f1f90d54b3aa8f7450aa5f56a25103bd02207f724703ad1edb96680b284b56d4ffcb88f7fb759eabbe08aa30f29b851383d2010103040100000001042200208c2353173743b595dfb4a07b72ba8e42e3797da74e87fe7d9d7497e3b2028903010547522103089dc10c7ac6db54f91329af617333db388cead0c231f723379d1b99030b02dc21023add904f3d6dcf59ddb906b0dee23529b7ffb9ed50e5e86151926860221f0e7352ae2206023add904f3d6dcf59ddb906b0dee23529b7ffb9ed50e5e86151926860221f0e7310d90c6a4f000000800000008003000080220603089dc10c7ac6db54f91329af617333db388cead0c231f723379d1b99030b02dc10d90c6a4f00000080000000800200008000220203a9a4c37f5996d3aa25dbac6b570af0650394492942460b354753ed9eeca5877110d90c6a4f000000800000008004000080002202027f6399757d2eff55a136ad02c684b1838b6556e5f1b6b34282a94b6b5005109610d90c6a4f00000080000000800500008000").unwrap()).unwrap();
+        let mut psbt = bip174_psbt();
 
         let secp = Secp256k1::verification_only();
         finalize(&mut psbt, &secp).unwrap();
@@ -352,4 +550,247 @@ mod tests {
         let expected: dogecoin::util::psbt::PartiallySignedTransaction = deserialize(&Vec::<u8>::from_hex("70736274ff01009a020000000258e87a21b56daf0c23be8e7070456c336f7cbaa5c8757924f545887bb2abdd750000000000ffffffff838d0427d0ec650a68aa46bb0b098aea4422c071b2ca78352a077959d07cea1d0100000000ffffffff0270aaf00800000000160014d85c2b71d0060b09c9886aeb815e50991dda124d00e1f5050000000016001400aea9a2e5f0f876a588df5546e8742d1d87008f00000000000100bb0200000001aad73931018bd25f84ae400b68848be09db706eac2ac18298babee71ab656f8b0000000048473044022058f6fc7c6a33e1b31548d481c826c015bd30135aad42cd67790dab66d2ad243b02204a1ced2604c6735b6393e5b41691dd78b00f0c5942fb9f751856faa938157dba01feffffff0280f0fa020000000017a9140fb9463421696b82c833af241c78c17ddbde493487d0f20a270100000017a91429ca74f8a08f81999428185c97b5d852e4063f6187650000000107da00473044022074018ad4180097b873323c0015720b3684cc8123891048e7dbcd9b55ad679c99022073d369b740e3eb53dcefa33823c8070514ca55a7dd9544f157c167913261118c01483045022100f61038b308dc1da865a34852746f015772934208c6d24454393cd99bdf2217770220056e675a675a6d0a02b85b14e5e29074d8a25a9b5760bea2816f661910a006ea01475221029583bf39ae0a609747ad199addd634fa6108559d6c5cd39b4c2183f1ab96e07f2102dab61ff49a14db6a7d02b0cd1fbb78fc4b18312b5b4e54dae4dba2fbfef536d752ae0001012000c2eb0b0000000017a914b7f5faf40e3d40a5a459b1db3535f2b72fa921e8870107232200208c2353173743b595dfb4a07b72ba8e42e3797da74e87fe7d9d7497e3b20289030108da0400473044022062eb7a556107a7c73f45ac4ab5a1dddf6f7075fb1275969a7f383efff784bcb202200c05dbb7470dbf2f08557dd356c7325c1ed30913e996cd3840945db12228da5f01473044022065f45ba5998b59a27ffe1a7bed016af1f1f90d54b3aa8f7450aa5f56a25103bd02207f724703ad1edb96680b284b56d4ffcb88f7fb759eabbe08aa30f29b851383d20147522103089dc10c7ac6db54f91329af617333db388cead0c231f723379d1b99030b02dc21023add904f3d6dcf59ddb906b0dee23529b7ffb9ed50e5e86151926860221f0e7352ae00220203a9a4c37f5996d3aa25dbac6b570af0650394492942460b354753ed9eeca5877110d90c6a4f000000800000008004000080002202027f6399757d2eff55a136ad02c684b1838b6556e5f1b6b34282a94b6b5005109610d90c6a4f00000080000000800500008000").unwrap()).unwrap();
         assert_eq!(psbt, expected);
     }
+
+    #[test]
+    fn install_satisfaction() {
+        let mut psbt = bip174_psbt();
+        let mut expected = psbt.clone();
+        let secp = Secp256k1::verification_only();
+        finalize(&mut expected, &secp).unwrap();
+
+        // Satisfy each input's script separately, then install the stacks
+        let desc = get_descriptor(&psbt, 0).unwrap();
+        let ms = Miniscript::<PublicKey, Legacy>::parse(&desc.explicit_script()).unwrap();
+        let stack = ms.satisfy(PsbtInputSatisfier::new(&psbt, 0)).unwrap();
+        super::install_satisfaction(&mut psbt, 0, &desc, stack).unwrap();
+
+        let desc = get_descriptor(&psbt, 1).unwrap();
+        let ms = Miniscript::<PublicKey, Segwitv0>::parse(&desc.explicit_script()).unwrap();
+        let stack = ms.satisfy(PsbtInputSatisfier::new(&psbt, 1)).unwrap();
+        super::install_satisfaction(&mut psbt, 1, &desc, stack.clone()).unwrap();
+
+        assert_eq!(psbt, expected);
+        interpreter_check(&psbt, &secp).unwrap();
+
+        match super::install_satisfaction(&mut psbt, 2, &desc, stack) {
+            Err(Error::InputIdxOutofBounds {
+                psbt_inp: 2,
+                index: 2,
+            }) => {}
+            x => panic!("expected index error, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn finalize_report() {
+        let mut psbt = bip174_psbt();
+        let mut expected = psbt.clone();
+        let secp = Secp256k1::verification_only();
+        finalize(&mut expected, &secp).unwrap();
+
+        let mut broken = psbt.clone();
+        broken.inputs[0].partial_sigs.clear();
+        broken.inputs[1].witness_script = None;
+        let before = broken.clone();
+        match super::finalize_report(&mut broken, &secp) {
+            Err(ref errors) if errors.len() == 2 => {
+                match errors[0] {
+                    (0, InputError::MiniscriptError(_)) => {}
+                    ref x => panic!("unexpected error {:?}", x),
+                }
+                match errors[1] {
+                    (1, InputError::MissingWitnessScript) => {}
+                    ref x => panic!("unexpected error {:?}", x),
+                }
+            }
+            x => panic!("expected two errors, got {:?}", x),
+        }
+        assert_eq!(broken, before);
+
+        // A psbt which does not match its transaction is an error too
+        broken.inputs.pop();
+        match super::finalize_report(&mut broken, &secp) {
+            Err(ref errors) if errors.len() == 1 => match errors[0] {
+                (
+                    1,
+                    InputError::WrongInputCount {
+                        in_tx: 2,
+                        in_map: 1,
+                    },
+                ) => {}
+                ref x => panic!("unexpected error {:?}", x),
+            },
+            x => panic!("expected input count error, got {:?}", x),
+        }
+
+        // `finalize` reports the first failure only, but the other inputs
+        // are still finalized
+        psbt.inputs[0].partial_sigs.clear();
+        let original = psbt.inputs[0].clone();
+        match finalize(&mut psbt, &secp) {
+            Err(Error::InputError(InputError::MiniscriptError(_), 0)) => {}
+            x => panic!("expected satisfaction error, got {:?}", x),
+        }
+        assert_eq!(psbt.inputs[0], original);
+        assert_eq!(psbt.inputs[1], expected.inputs[1]);
+    }
+
+    #[test]
+    fn finalize_input() {
+        let mut psbt = bip174_psbt();
+        let mut expected = psbt.clone();
+        let secp = Secp256k1::verification_only();
+        finalize(&mut expected, &secp).unwrap();
+
+        // Without its signatures the second input cannot be finalized, and
+        // is left as it was
+        let mut unsigned = psbt.clone();
+        unsigned.inputs[1].partial_sigs.clear();
+        let before = unsigned.clone();
+        match super::finalize_input(&mut unsigned, 1, &secp) {
+            Err(Error::InputError(InputError::MiniscriptError(_), 1)) => {}
+            x => panic!("expected satisfaction error, got {:?}", x),
+        }
+        assert_eq!(unsigned, before);
+
+        let original = psbt.inputs[1].clone();
+        super::finalize_input(&mut psbt, 0, &secp).unwrap();
+        assert_eq!(psbt.inputs[0], expected.inputs[0]);
+        assert_eq!(psbt.inputs[1], original);
+        super::finalize_input(&mut psbt, 1, &secp).unwrap();
+        assert_eq!(psbt, expected);
+
+        match super::finalize_input(&mut psbt, 2, &secp) {
+            Err(Error::InputIdxOutofBounds {
+                psbt_inp: 2,
+                index: 2,
+            }) => {}
+            x => panic!("expected index error, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn finalize_mall() {
+        use dogecoin::hashes::{sha256, Hash};
+        use dogecoin::util::bip143;
+
+        let secp = Secp256k1::new();
+        let (sk, pk) = test_key();
+        let preimages = (1..4).map(|i| vec![i; 32]).collect::<Vec<_>>();
+        let hashes = preimages
+            .iter()
+            .map(|p| sha256::Hash::hash(p))
+            .collect::<Vec<_>>();
+        // Without a signature the hashlocks could be replaced by their
+        // dissatisfaction, so the non-malleable satisfaction must use them
+        // although the signature is smaller
+        let ms = Miniscript::<PublicKey, Segwitv0>::from_str_insane(&format!(
+            "thresh(1,pk({}),aj:and_v(v:sha256({}),and_v(v:sha256({}),sha256({}))))",
+            pk, hashes[0], hashes[1], hashes[2],
+        ))
+        .unwrap();
+        let witness_script = ms.encode();
+
+        let tx = dogecoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![dogecoin::TxIn::default()],
+            output: vec![],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(dogecoin::TxOut {
+            value: 100_000,
+            script_pubkey: witness_script.to_v0_p2wsh(),
+        });
+        psbt.inputs[0].witness_script = Some(witness_script.clone());
+        for preimage in &preimages {
+            psbt.inputs[0]
+                .sha256_preimages
+                .insert(sha256::Hash::hash(preimage), preimage.clone());
+        }
+        let sighash = bip143::SigHashCache::new(&psbt.global.unsigned_tx).signature_hash(
+            0,
+            &witness_script,
+            100_000,
+            dogecoin::SigHashType::All,
+        );
+        let msg = secp256k1::Message::from_slice(&sighash[..]).unwrap();
+        let mut sig = secp.sign(&msg, &sk).serialize_der().to_vec();
+        sig.push(dogecoin::SigHashType::All as u8);
+        psbt.inputs[0].partial_sigs.insert(pk, sig);
+
+        let mut mall = psbt.clone();
+        finalize(&mut psbt, &secp).unwrap();
+        super::finalize_mall(&mut mall, &secp).unwrap();
+
+        let size = |psbt: &Psbt| {
+            let witness = psbt.inputs[0].final_script_witness.as_ref().unwrap();
+            witness.iter().map(Vec::len).sum::<usize>()
+        };
+        assert!(size(&mall) < size(&psbt));
+        let witness = psbt.inputs[0].final_script_witness.as_ref().unwrap();
+        assert!(witness.contains(&preimages[0]));
+        let witness = mall.inputs[0].final_script_witness.as_ref().unwrap();
+        assert!(!witness.contains(&preimages[0]));
+    }
+
+    #[test]
+    fn finalize_with_selection() {
+        use dogecoin::util::bip143;
+        use std::str::FromStr;
+
+        let secp = Secp256k1::new();
+        let mut sks = vec![];
+        let mut keys = vec![];
+        for i in 1..4 {
+            let sk = secp256k1::SecretKey::from_slice(&[i; 32]).unwrap();
+            keys.push(PublicKey {
+                key: secp256k1::PublicKey::from_secret_key(&secp, &sk),
+                compressed: true,
+            });
+            sks.push(sk);
+        }
+        let ms = Miniscript::<PublicKey, Segwitv0>::from_str(&format!(
+            "multi(2,{},{},{})",
+            keys[0], keys[1], keys[2]
+        ))
+        .unwrap();
+        let witness_script = ms.encode();
+
+        let tx = dogecoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![dogecoin::TxIn::default()],
+            output: vec![],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(dogecoin::TxOut {
+            value: 100_000,
+            script_pubkey: witness_script.to_v0_p2wsh(),
+        });
+        psbt.inputs[0].witness_script = Some(witness_script.clone());
+        let sighash = bip143::SigHashCache::new(&psbt.global.unsigned_tx).signature_hash(
+            0,
+            &witness_script,
+            100_000,
+            dogecoin::SigHashType::All,
+        );
+        let msg = secp256k1::Message::from_slice(&sighash[..]).unwrap();
+        let mut sigs = vec![];
+        for (sk, pk) in sks.iter().zip(&keys) {
+            let mut sig = secp.sign(&msg, sk).serialize_der().to_vec();
+            sig.push(dogecoin::SigHashType::All as u8);
+            psbt.inputs[0].partial_sigs.insert(*pk, sig.clone());
+            sigs.push(sig);
+        }
+
+        // Only two of the three signatures are needed
+        let selection = super::finalize_with_selection(&mut psbt, &secp).unwrap();
+        assert_eq!(selection.len(), 1);
+        assert_eq!(selection[0].len(), 2);
+        let witness = psbt.inputs[0].final_script_witness.as_ref().unwrap();
+        for (pk, sig) in keys.iter().zip(&sigs) {
+            assert_eq!(selection[0].contains(pk), witness.contains(sig));
+        }
+    }
 }
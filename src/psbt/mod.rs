@@ -14,15 +14,17 @@
 
 //! # Partially-Signed Bitcoin Transactions
 //!
-//! This module implements the Finalizer and Extractor roles defined in
-//! BIP 174, PSBT, described at
+//! This module implements the Combiner, Finalizer and Extractor roles
+//! defined in BIP 174, PSBT, described at
 //! `https://github.com/bitcoin/bips/blob/master/bip-0174.mediawiki`
 //!
 
 use std::{error, fmt};
 
 use dogecoin;
-use dogecoin::hashes::{hash160, ripemd160, sha256, sha256d};
+use dogecoin::blockdata::script::Instruction;
+use dogecoin::hashes::hex::ToHex;
+use dogecoin::hashes::{hash160, ripemd160, sha256, sha256d, Hash};
 use dogecoin::secp256k1::{self, Secp256k1};
 use dogecoin::util::psbt::PartiallySignedTransaction as Psbt;
 use dogecoin::Script;
@@ -35,7 +37,10 @@ use {BitcoinSig, Preimage32};
 use {MiniscriptKey, ToPublicKey};
 
 mod finalizer;
-pub use self::finalizer::{finalize, interpreter_check};
+pub use self::finalizer::{
+    finalize, finalize_input, finalize_mall, finalize_report, finalize_with_selection,
+    install_satisfaction, interpreter_check,
+};
 
 /// Error type for Pbst Input
 #[derive(Debug)]
@@ -67,6 +72,13 @@ pub enum InputError {
         /// The (incorrect) signature
         sig: Vec<u8>,
     },
+    /// Hash preimage was not 32 bytes long
+    InvalidPreimageLength {
+        /// The hash whose preimage was given
+        hash: Vec<u8>,
+        /// The length of the given preimage
+        len: usize,
+    },
     /// Pass through the underlying errors in miniscript
     MiniscriptError(super::Error),
     /// Missing redeem script for p2sh
@@ -92,6 +104,14 @@ pub enum InputError {
         /// the corresponding publickey
         pubkey: dogecoin::PublicKey,
     },
+    /// The psbt and its unsigned transaction have different numbers of
+    /// inputs, so the input is missing from one of them
+    WrongInputCount {
+        /// Input count in tx
+        in_tx: usize,
+        /// Input count in psbt
+        in_map: usize,
+    },
 }
 
 /// Error type for entire Psbt
@@ -106,6 +126,31 @@ pub enum Error {
         /// Input count in psbt
         in_map: usize,
     },
+    /// The index is not that of an input of the psbt
+    InputIdxOutofBounds {
+        /// Input count in psbt
+        psbt_inp: usize,
+        /// The index given
+        index: usize,
+    },
+    /// The outputs of the transaction are worth more than its inputs
+    NegativeFee {
+        /// Total value of the inputs
+        in_value: u64,
+        /// Total value of the outputs
+        out_value: u64,
+    },
+    /// The fee of the transaction is higher than allowed
+    AbsurdFee {
+        /// The fee paid
+        fee: u64,
+        /// The highest fee allowed
+        max_fee: u64,
+    },
+    /// Combined psbts have different unsigned transactions
+    UnsignedTxMismatch,
+    /// Combined psbts could not be merged
+    MergeError(dogecoin::util::psbt::Error),
 }
 
 impl fmt::Display for InputError {
@@ -115,6 +160,12 @@ impl fmt::Display for InputError {
                 ref pubkey,
                 ref sig,
             } => write!(f, "PSBT: bad signature {} for key {:?}", pubkey.key, sig),
+            InputError::InvalidPreimageLength { ref hash, len } => write!(
+                f,
+                "PSBT: preimage of hash {} has length {} rather than 32",
+                hash.to_hex(),
+                len
+            ),
             InputError::KeyErr(ref e) => write!(f, "Key Err: {}", e),
             InputError::Interpreter(ref e) => write!(f, "Interpreter: {}", e),
             InputError::SecpErr(ref e) => write!(f, "Secp Err: {}", e),
@@ -159,6 +210,11 @@ impl fmt::Display for InputError {
                  sighashflag {:?} rather than required {:?}",
                 pubkey.key, got, required
             ),
+            InputError::WrongInputCount { in_tx, in_map } => write!(
+                f,
+                "PSBT had {} inputs in transaction but {} inputs in map",
+                in_tx, in_map
+            ),
         }
     }
 }
@@ -184,7 +240,27 @@ impl From<dogecoin::util::key::Error> for InputError {
     }
 }
 
-impl error::Error for Error {}
+impl error::Error for InputError {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            InputError::SecpErr(ref e) => Some(e),
+            InputError::KeyErr(ref e) => Some(e),
+            InputError::Interpreter(ref e) => Some(e),
+            InputError::MiniscriptError(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::InputError(ref e, _) => Some(e),
+            Error::MergeError(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -195,6 +271,26 @@ impl fmt::Display for Error {
                 "PSBT had {} inputs in transaction but {} inputs in map",
                 in_tx, in_map
             ),
+            Error::InputIdxOutofBounds { psbt_inp, index } => write!(
+                f,
+                "PSBT has {} inputs, so no input at index {}",
+                psbt_inp, index
+            ),
+            Error::NegativeFee {
+                in_value,
+                out_value,
+            } => write!(
+                f,
+                "PSBT inputs are worth {} but its outputs are worth {}",
+                in_value, out_value
+            ),
+            Error::AbsurdFee { fee, max_fee } => write!(
+                f,
+                "PSBT pays a fee of {}, more than the maximum of {}",
+                fee, max_fee
+            ),
+            Error::UnsignedTxMismatch => f.write_str("PSBTs have different unsigned transactions"),
+            Error::MergeError(ref e) => write!(f, "PSBTs could not be merged: {}", e),
         }
     }
 }
@@ -209,6 +305,8 @@ pub struct PsbtInputSatisfier<'psbt> {
     pub psbt: &'psbt Psbt,
     /// input index
     pub index: usize,
+    /// If set, only signatures with this sighash type are used
+    sighash_type: Option<dogecoin::SigHashType>,
 }
 
 impl<'psbt> PsbtInputSatisfier<'psbt> {
@@ -218,8 +316,43 @@ impl<'psbt> PsbtInputSatisfier<'psbt> {
         Self {
             psbt: psbt,
             index: index,
+            sighash_type: None,
         }
     }
+
+    /// Only use partial signatures with the sighash type `sighash_type`, e.g.
+    /// the one a hardware wallet was asked to sign with. Signatures with any
+    /// other flag are ignored, so the fragments needing them are treated as
+    /// unsatisfied rather than satisfied with an unexpected sighash type.
+    pub fn with_sighash(mut self, sighash_type: dogecoin::SigHashType) -> Self {
+        self.sighash_type = Some(sighash_type);
+        self
+    }
+
+    // Decode a partial signature, unless it has the wrong sighash type
+    fn decode_sig(&self, rawsig: &[u8]) -> Option<BitcoinSig> {
+        bitcoinsig_from_rawsig(rawsig)
+            .ok()
+            .filter(|&(_, ty)| self.sighash_type.map_or(true, |want| ty == want))
+    }
+
+    /// Create a new PsbtInputSatisfier, first checking that every hash
+    /// preimage in the input is 32 bytes. Preimages of any other length can
+    /// never be used in a satisfaction, and `new` silently ignores them.
+    /// Unlike `new`, this fails with `Error::InputIdxOutofBounds` rather than
+    /// panicking if the psbt has no input at `index`.
+    pub fn new_checked(psbt: &'psbt Psbt, index: usize) -> Result<Self, Error> {
+        let input = psbt.inputs.get(index).ok_or(Error::InputIdxOutofBounds {
+            psbt_inp: psbt.inputs.len(),
+            index: index,
+        })?;
+        check_preimage_lengths(&input.ripemd160_preimages)
+            .and_then(|()| check_preimage_lengths(&input.sha256_preimages))
+            .and_then(|()| check_preimage_lengths(&input.hash160_preimages))
+            .and_then(|()| check_preimage_lengths(&input.hash256_preimages))
+            .map_err(|e| Error::InputError(e, index))?;
+        Ok(Self::new(psbt, index))
+    }
 }
 
 impl<'psbt, Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for PsbtInputSatisfier<'psbt> {
@@ -228,9 +361,9 @@ impl<'psbt, Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for PsbtInputSatisfie
             .partial_sigs
             .get(&pk.to_public_key())
         {
-            // We have already previously checked that all signatures have the
-            // correct sighash flag.
-            bitcoinsig_from_rawsig(rawsig).ok()
+            // The finalizer has already checked that all signatures have the
+            // correct sighash flag; other users may ask us to check it here.
+            self.decode_sig(rawsig)
         } else {
             None
         }
@@ -244,14 +377,30 @@ impl<'psbt, Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for PsbtInputSatisfie
             .next()
         {
             // If the mapping is incorrect, return None
-            bitcoinsig_from_rawsig(sig)
-                .ok()
-                .map(|bitcoinsig| (*pk, bitcoinsig))
+            self.decode_sig(sig).map(|bitcoinsig| (*pk, bitcoinsig))
         } else {
             None
         }
     }
 
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<dogecoin::PublicKey> {
+        // Look for the key among the signing keys, the keys with a bip32
+        // derivation and the keys in the redeem and witness scripts
+        let input = &self.psbt.inputs[self.index];
+        let script_keys = input
+            .redeem_script
+            .iter()
+            .chain(input.witness_script.iter())
+            .flat_map(|script| script_keys(script));
+        input
+            .partial_sigs
+            .keys()
+            .chain(input.bip32_derivation.keys())
+            .cloned()
+            .chain(script_keys)
+            .find(|pk| pk.to_pubkeyhash() == *pkh)
+    }
+
     fn check_after(&self, n: u32) -> bool {
         let locktime = self.psbt.global.unsigned_tx.lock_time;
         let seq = self.psbt.global.unsigned_tx.input[self.index].sequence;
@@ -310,6 +459,17 @@ impl<'psbt, Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for PsbtInputSatisfie
     }
 }
 
+// The public keys pushed by a script
+fn script_keys(script: &Script) -> Vec<dogecoin::PublicKey> {
+    script
+        .instructions()
+        .filter_map(|ins| match ins {
+            Ok(Instruction::PushBytes(bytes)) => dogecoin::PublicKey::from_slice(bytes).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
 fn try_vec_as_preimage32(vec: &Vec<u8>) -> Option<Preimage32> {
     if vec.len() == 32 {
         let mut arr = [0u8; 32];
@@ -320,6 +480,22 @@ fn try_vec_as_preimage32(vec: &Vec<u8>) -> Option<Preimage32> {
     }
 }
 
+fn check_preimage_lengths<'a, H, I>(preimages: I) -> Result<(), InputError>
+where
+    H: Hash + 'a,
+    I: IntoIterator<Item = (&'a H, &'a Vec<u8>)>,
+{
+    for (hash, preimage) in preimages {
+        if preimage.len() != 32 {
+            return Err(InputError::InvalidPreimageLength {
+                hash: hash[..].to_vec(),
+                len: preimage.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
 fn sanity_check(psbt: &Psbt) -> Result<(), Error> {
     if psbt.global.unsigned_tx.input.len() != psbt.inputs.len() {
         return Err(Error::WrongInputCount {
@@ -360,12 +536,89 @@ pub fn extract<C: secp256k1::Verification>(
     Ok(ret)
 }
 
+/// Psbt combiner as defined in BIP174, merging `other`, e.g. a copy of
+/// `base` signed by another participant, into `base` with
+/// `PartiallySignedTransaction::merge`. This adds the key-value entries of
+/// `other`, such as partial signatures, bip32 derivations, hash preimages,
+/// global xpubs and unknown or proprietary entries of the psbt, its inputs
+/// and its outputs, and takes its single-valued fields, such as UTXOs and
+/// scripts, where `base` has none. The input sighash types, which `merge`
+/// leaves alone, are taken in the same way. Where both have a value for
+/// the same key or field, the one in `base` is kept.
+///
+/// Fails with `Error::UnsignedTxMismatch` if the unsigned transactions
+/// differ, or `Error::MergeError` if the psbts otherwise conflict, in which
+/// case `base` is left unchanged. Differing transactions are not reported
+/// as `Error::WrongInputCount`, which is kept for a psbt whose number of
+/// inputs disagrees with its own transaction: the transactions of two
+/// psbts may differ with the same number of inputs, and both psbts have
+/// passed that per-psbt check by the time they are compared.
+pub fn combine(base: &mut Psbt, other: Psbt) -> Result<(), Error> {
+    sanity_check(base)?;
+    sanity_check(&other)?;
+    if base.global.unsigned_tx != other.global.unsigned_tx {
+        return Err(Error::UnsignedTxMismatch);
+    }
+
+    // Only replace `base` once both merges have succeeded
+    let mut combined = base.clone();
+    for (input, other) in combined.inputs.iter_mut().zip(&other.inputs) {
+        input.sighash_type = input.sighash_type.or(other.sighash_type);
+    }
+    // `merge` lets `other` win conflicting entries of the key-value maps,
+    // so merge the original `base` back in to keep its own
+    combined.merge(other).map_err(Error::MergeError)?;
+    combined.merge(base.clone()).map_err(Error::MergeError)?;
+    *base = combined;
+    Ok(())
+}
+
+/// Psbt extractor like `extract`, which also checks that the fee of the
+/// transaction, the value of the inputs according to their UTXOs minus the
+/// value of the outputs, is not negative and not more than `max_fee`.
+pub fn extract_checked<C: secp256k1::Verification>(
+    psbt: &Psbt,
+    secp: &Secp256k1<C>,
+    max_fee: u64,
+) -> Result<dogecoin::Transaction, Error> {
+    sanity_check(psbt)?;
+
+    // Sums past u64::MAX cannot be valid, so saturating is good enough
+    let mut in_value = 0u64;
+    for index in 0..psbt.inputs.len() {
+        let amt = finalizer::get_amt(psbt, index).map_err(|e| Error::InputError(e, index))?;
+        in_value = in_value.saturating_add(amt);
+    }
+    let out_value = psbt
+        .global
+        .unsigned_tx
+        .output
+        .iter()
+        .fold(0u64, |sum, output| sum.saturating_add(output.value));
+    if in_value < out_value {
+        return Err(Error::NegativeFee {
+            in_value: in_value,
+            out_value: out_value,
+        });
+    }
+    let fee = in_value - out_value;
+    if fee > max_fee {
+        return Err(Error::AbsurdFee {
+            fee: fee,
+            max_fee: max_fee,
+        });
+    }
+
+    extract(psbt, secp)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use dogecoin::consensus::encode::deserialize;
     use dogecoin::hashes::hex::FromHex;
+    use test_key;
 
     #[test]
     fn test_extract_bip174() {
@@ -375,4 +628,284 @@ mod tests {
         let expected: dogecoin::Transaction = deserialize(&Vec::<u8>::from_hex("0200000000010258e87a21b56daf0c23be8e7070456c336f7cbaa5c8757924f545887bb2abdd7500000000da00473044022074018ad4180097b873323c0015720b3684cc8123891048e7dbcd9b55ad679c99022073d369b740e3eb53dcefa33823c8070514ca55a7dd9544f157c167913261118c01483045022100f61038b308dc1da865a34852746f015772934208c6d24454393cd99bdf2217770220056e675a675a6d0a02b85b14e5e29074d8a25a9b5760bea2816f661910a006ea01475221029583bf39ae0a609747ad199addd634fa6108559d6c5cd39b4c2183f1ab96e07f2102dab61ff49a14db6a7d02b0cd1fbb78fc4b18312b5b4e54dae4dba2fbfef536d752aeffffffff838d0427d0ec650a68aa46bb0b098aea4422c071b2ca78352a077959d07cea1d01000000232200208c2353173743b595dfb4a07b72ba8e42e3797da74e87fe7d9d7497e3b2028903ffffffff0270aaf00800000000160014d85c2b71d0060b09c9886aeb815e50991dda124d00e1f5050000000016001400aea9a2e5f0f876a588df5546e8742d1d87008f000400473044022062eb7a556107a7c73f45ac4ab5a1dddf6f7075fb1275969a7f383efff784bcb202200c05dbb7470dbf2f08557dd356c7325c1ed30913e996cd3840945db12228da5f01473044022065f45ba5998b59a27ffe1a7bed016af1f1f90d54b3aa8f7450aa5f56a25103bd02207f724703ad1edb96680b284b56d4ffcb88f7fb759eabbe08aa30f29b851383d20147522103089dc10c7ac6db54f91329af617333db388cead0c231f723379d1b99030b02dc21023add904f3d6dcf59ddb906b0dee23529b7ffb9ed50e5e86151926860221f0e7352ae00000000").unwrap()).unwrap();
         assert_eq!(tx, expected);
     }
+
+    #[test]
+    fn extract_checked() {
+        let psbt: dogecoin::util::psbt::PartiallySignedTransaction = deserialize(&Vec::<u8>::from_hex("70736274ff01009a020000000258e87a21b56daf0c23be8e7070456c336f7cbaa5c8757924f545887bb2abdd750000000000ffffffff838d0427d0ec650a68aa46bb0b098aea4422c071b2ca78352a077959d07cea1d0100000000ffffffff0270aaf00800000000160014d85c2b71d0060b09c9886aeb815e50991dda124d00e1f5050000000016001400aea9a2e5f0f876a588df5546e8742d1d87008f00000000000100bb0200000001aad73931018bd25f84ae400b68848be09db706eac2ac18298babee71ab656f8b0000000048473044022058f6fc7c6a33e1b31548d481c826c015bd30135aad42cd67790dab66d2ad243b02204a1ced2604c6735b6393e5b41691dd78b00f0c5942fb9f751856faa938157dba01feffffff0280f0fa020000000017a9140fb9463421696b82c833af241c78c17ddbde493487d0f20a270100000017a91429ca74f8a08f81999428185c97b5d852e4063f6187650000000107da00473044022074018ad4180097b873323c0015720b3684cc8123891048e7dbcd9b55ad679c99022073d369b740e3eb53dcefa33823c8070514ca55a7dd9544f157c167913261118c01483045022100f61038b308dc1da865a34852746f015772934208c6d24454393cd99bdf2217770220056e675a675a6d0a02b85b14e5e29074d8a25a9b5760bea2816f661910a006ea01475221029583bf39ae0a609747ad199addd634fa6108559d6c5cd39b4c2183f1ab96e07f2102dab61ff49a14db6a7d02b0cd1fbb78fc4b18312b5b4e54dae4dba2fbfef536d752ae0001012000c2eb0b0000000017a914b7f5faf40e3d40a5a459b1db3535f2b72fa921e8870107232200208c2353173743b595dfb4a07b72ba8e42e3797da74e87fe7d9d7497e3b20289030108da0400473044022062eb7a556107a7c73f45ac4ab5a1dddf6f7075fb1275969a7f383efff784bcb202200c05dbb7470dbf2f08557dd356c7325c1ed30913e996cd3840945db12228da5f01473044022065f45ba5998b59a27ffe1a7bed016af1f1f90d54b3aa8f7450aa5f56a25103bd02207f724703ad1edb96680b284b56d4ffcb88f7fb759eabbe08aa30f29b851383d20147522103089dc10c7ac6db54f91329af617333db388cead0c231f723379d1b99030b02dc21023add904f3d6dcf59ddb906b0dee23529b7ffb9ed50e5e86151926860221f0e7352ae00220203a9a4c37f5996d3aa25dbac6b570af0650394492942460b354753ed9eeca5877110d90c6a4f000000800000008004000080002202027f6399757d2eff55a136ad02c684b1838b6556e5f1b6b34282a94b6b5005109610d90c6a4f00000080000000800500008000").unwrap()).unwrap();
+        let secp = Secp256k1::verification_only();
+        let expected = extract(&psbt, &secp).unwrap();
+        // 0.5 + 2 coins in, 1.4999 + 1 coins out
+        assert_eq!(
+            super::extract_checked(&psbt, &secp, 10_000).unwrap(),
+            expected
+        );
+        match super::extract_checked(&psbt, &secp, 9_999) {
+            Err(Error::AbsurdFee {
+                fee: 10_000,
+                max_fee: 9_999,
+            }) => {}
+            x => panic!("expected fee error, got {:?}", x),
+        }
+
+        let mut psbt = psbt;
+        psbt.inputs[0].non_witness_utxo.as_mut().unwrap().output[0].value = 0;
+        match super::extract_checked(&psbt, &secp, u64::max_value()) {
+            Err(Error::NegativeFee {
+                in_value: 200_000_000,
+                out_value: 249_990_000,
+            }) => {}
+            x => panic!("expected fee error, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_new_checked_preimage_len() {
+        use dogecoin::hashes::Hash;
+
+        let tx = dogecoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![dogecoin::TxIn::default()],
+            output: vec![],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        assert!(PsbtInputSatisfier::new_checked(&psbt, 0).is_ok());
+
+        let preimage = vec![0xab; 32];
+        psbt.inputs[0]
+            .sha256_preimages
+            .insert(sha256::Hash::hash(&preimage), preimage.clone());
+        assert!(PsbtInputSatisfier::new_checked(&psbt, 0).is_ok());
+
+        let short = vec![0xab; 31];
+        let short_hash = hash160::Hash::hash(&short);
+        psbt.inputs[0].hash160_preimages.insert(short_hash, short);
+        match PsbtInputSatisfier::new_checked(&psbt, 0) {
+            Err(Error::InputError(InputError::InvalidPreimageLength { ref hash, len: 31 }, 0))
+                if *hash == short_hash[..].to_vec() => {}
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("short preimage accepted"),
+        }
+
+        match PsbtInputSatisfier::new_checked(&psbt, 1) {
+            Err(Error::InputIdxOutofBounds {
+                psbt_inp: 1,
+                index: 1,
+            }) => {}
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("missing input accepted"),
+        }
+    }
+
+    #[test]
+    fn with_sighash() {
+        let secp = Secp256k1::signing_only();
+        let (sk, pk) = test_key();
+        let msg = secp256k1::Message::from_slice(&[0xab; 32]).unwrap();
+        let sig = secp.sign(&msg, &sk);
+        let mut rawsig = sig.serialize_der().to_vec();
+        rawsig.push(dogecoin::SigHashType::Single as u8);
+
+        let tx = dogecoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![dogecoin::TxIn::default()],
+            output: vec![],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].partial_sigs.insert(pk, rawsig);
+
+        let expected = Some((sig, dogecoin::SigHashType::Single));
+        let satisfier = PsbtInputSatisfier::new(&psbt, 0);
+        assert_eq!(Satisfier::lookup_sig(&satisfier, &pk), expected);
+        let satisfier = satisfier.with_sighash(dogecoin::SigHashType::Single);
+        assert_eq!(Satisfier::lookup_sig(&satisfier, &pk), expected);
+        assert_eq!(
+            Satisfier::<dogecoin::PublicKey>::lookup_pkh_sig(&satisfier, &pk.to_pubkeyhash()),
+            Some((pk, expected.unwrap()))
+        );
+
+        let satisfier = PsbtInputSatisfier::new(&psbt, 0).with_sighash(dogecoin::SigHashType::All);
+        assert_eq!(Satisfier::lookup_sig(&satisfier, &pk), None);
+        assert_eq!(
+            Satisfier::<dogecoin::PublicKey>::lookup_pkh_sig(&satisfier, &pk.to_pubkeyhash()),
+            None
+        );
+    }
+
+    #[test]
+    fn lookup_raw_pkh_pk() {
+        use dogecoin::util::bip32;
+        use miniscript::context::Segwitv0;
+        use Miniscript;
+
+        let secp = Secp256k1::signing_only();
+        let mut keys = vec![];
+        for i in 1..3 {
+            let sk = secp256k1::SecretKey::from_slice(&[i; 32]).unwrap();
+            keys.push(dogecoin::PublicKey {
+                key: secp256k1::PublicKey::from_secret_key(&secp, &sk),
+                compressed: true,
+            });
+        }
+        let sk = secp256k1::SecretKey::from_slice(&[2; 32]).unwrap();
+        let msg = secp256k1::Message::from_slice(&[0xab; 32]).unwrap();
+        let sig = secp.sign(&msg, &sk);
+        let mut rawsig = sig.serialize_der().to_vec();
+        rawsig.push(dogecoin::SigHashType::All as u8);
+
+        let tx = dogecoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![dogecoin::TxIn::default()],
+            output: vec![],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].partial_sigs.insert(keys[1], rawsig.clone());
+
+        // Spending with the second key needs the first key to dissatisfy
+        // its pkh
+        let keyhash = keys[0].to_pubkeyhash();
+        let ms: Miniscript<dogecoin::PublicKey, Segwitv0> =
+            format!("or_d(c:pk_h({}),pk({}))", keyhash, keys[1])
+                .parse()
+                .unwrap();
+        assert!(ms.satisfy(PsbtInputSatisfier::new(&psbt, 0)).is_err());
+        let satisfier = PsbtInputSatisfier::new(&psbt, 0);
+        let lookup = |satisfier: &PsbtInputSatisfier| {
+            Satisfier::<dogecoin::PublicKey>::lookup_raw_pkh_pk(satisfier, &keyhash)
+        };
+        assert_eq!(lookup(&satisfier), None);
+
+        psbt.inputs[0].bip32_derivation.insert(
+            keys[0],
+            (
+                bip32::Fingerprint::from(&[0; 4][..]),
+                bip32::DerivationPath::from(vec![]),
+            ),
+        );
+        assert_eq!(
+            ms.satisfy(PsbtInputSatisfier::new(&psbt, 0)).unwrap(),
+            vec![rawsig.clone(), vec![], keys[0].to_bytes()]
+        );
+
+        // Keys pushed by the witness script are found too
+        psbt.inputs[0].bip32_derivation.clear();
+        psbt.inputs[0].witness_script = Some(
+            dogecoin::blockdata::script::Builder::new()
+                .push_key(&keys[0])
+                .into_script(),
+        );
+        let satisfier = PsbtInputSatisfier::new(&psbt, 0);
+        assert_eq!(lookup(&satisfier), Some(keys[0]));
+    }
+
+    #[test]
+    fn combine() {
+        use dogecoin::util::bip143;
+
+        let secp = Secp256k1::new();
+        let mut sks = vec![];
+        let mut keys = vec![];
+        for i in 1..3 {
+            let sk = secp256k1::SecretKey::from_slice(&[i; 32]).unwrap();
+            keys.push(dogecoin::PublicKey {
+                key: secp256k1::PublicKey::from_secret_key(&secp, &sk),
+                compressed: true,
+            });
+            sks.push(sk);
+        }
+        let witness_script = dogecoin::blockdata::script::Builder::new()
+            .push_int(2)
+            .push_key(&keys[0])
+            .push_key(&keys[1])
+            .push_int(2)
+            .push_opcode(dogecoin::blockdata::opcodes::all::OP_CHECKMULTISIG)
+            .into_script();
+
+        let tx = dogecoin::Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![dogecoin::TxIn::default()],
+            output: vec![dogecoin::TxOut::default()],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(dogecoin::TxOut {
+            value: 100_000,
+            script_pubkey: witness_script.to_v0_p2wsh(),
+        });
+        let sighash = bip143::SigHashCache::new(&psbt.global.unsigned_tx).signature_hash(
+            0,
+            &witness_script,
+            100_000,
+            dogecoin::SigHashType::All,
+        );
+        let msg = secp256k1::Message::from_slice(&sighash[..]).unwrap();
+
+        // Each signer only returns its own signature, and only the first
+        // fills in the witness script
+        let mut signed = vec![];
+        for (sk, pk) in sks.iter().zip(&keys) {
+            let mut rawsig = secp.sign(&msg, sk).serialize_der().to_vec();
+            rawsig.push(dogecoin::SigHashType::All as u8);
+            let mut psbt = psbt.clone();
+            psbt.inputs[0].partial_sigs.insert(*pk, rawsig);
+            signed.push(psbt);
+        }
+        signed[0].inputs[0].witness_script = Some(witness_script.clone());
+        let mut other = signed.pop().unwrap();
+        let mut base = signed.pop().unwrap();
+        assert!(finalize(&mut base.clone(), &secp).is_err());
+
+        let mut wrong_tx = other.clone();
+        wrong_tx.global.unsigned_tx.lock_time = 1;
+        match super::combine(&mut base.clone(), wrong_tx) {
+            Err(Error::UnsignedTxMismatch) => {}
+            x => panic!("expected mismatch, got {:?}", x),
+        }
+
+        // A failed merge leaves the base untouched, even the fields merged
+        // before the conflict was found
+        use dogecoin::util::bip32;
+        let xpriv = bip32::ExtendedPrivKey::new_master(dogecoin::Network::Bitcoin, &[1; 32]);
+        let xpub = bip32::ExtendedPubKey::from_private(&secp, &xpriv.unwrap());
+        let fp = xpub.fingerprint();
+        let path = |i| bip32::DerivationPath::from(vec![bip32::ChildNumber::from(i)]);
+        let mut conflicting = other.clone();
+        conflicting.inputs[0].sighash_type = Some(dogecoin::SigHashType::All);
+        conflicting.global.xpub.insert(xpub, (fp, path(0)));
+        let mut unchanged = base.clone();
+        unchanged.global.xpub.insert(xpub, (fp, path(1)));
+        let original = unchanged.clone();
+        match super::combine(&mut unchanged, conflicting) {
+            Err(Error::MergeError(..)) => {}
+            x => panic!("expected merge conflict, got {:?}", x),
+        }
+        assert_eq!(unchanged, original);
+
+        // The base's fields and entries win over conflicting ones, and
+        // the other entries of all maps are kept
+        let unknown = dogecoin::util::psbt::raw::Key {
+            type_value: 0xf0,
+            key: vec![1],
+        };
+        other.inputs[0].witness_script = Some(Script::new());
+        other.inputs[0].sighash_type = Some(dogecoin::SigHashType::All);
+        other.inputs[0].unknown.insert(unknown.clone(), vec![2]);
+        other.global.unknown.insert(unknown.clone(), vec![3]);
+        other.outputs[0].witness_script = Some(witness_script.clone());
+        base.inputs[0].unknown.insert(unknown.clone(), vec![4]);
+        super::combine(&mut base, other).unwrap();
+        assert_eq!(base.inputs[0].partial_sigs.len(), 2);
+        assert_eq!(base.inputs[0].witness_script, Some(witness_script.clone()));
+        assert_eq!(
+            base.inputs[0].sighash_type,
+            Some(dogecoin::SigHashType::All)
+        );
+        assert_eq!(base.inputs[0].unknown[&unknown], vec![4]);
+        assert_eq!(base.global.unknown[&unknown], vec![3]);
+        assert_eq!(base.outputs[0].witness_script, Some(witness_script));
+        finalize(&mut base, &secp).unwrap();
+        extract(&base, &secp).unwrap();
+    }
 }